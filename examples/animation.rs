@@ -0,0 +1,55 @@
+use narui::*;
+use std::time::Duration;
+
+#[widget]
+pub fn animation_demo(context: &mut WidgetContext) -> Fragment {
+    let expanded = context.listenable(false);
+    let is_expanded = context.listen(expanded);
+
+    let width = context.tween(
+        if is_expanded { 400.0 } else { 100.0 },
+        Duration::from_millis(500),
+        Curve::EaseInOut,
+    );
+    let color = context.tween(
+        if is_expanded {
+            Color::new(0.67, 0.27, 0.27, 1.0)
+        } else {
+            Color::new(0.27, 0.27, 0.67, 1.0)
+        },
+        Duration::from_millis(500),
+        Curve::Linear,
+    );
+    let spring_width = context.spring(if is_expanded { 400.0 } else { 100.0 }, 120.0, 8.0);
+
+    rsx! {
+        <column cross_axis_alignment=CrossAxisAlignment::Start>
+            <button on_click=move |context: &CallbackContext| context.shout(expanded, !context.spy(expanded))>
+                <text>{if is_expanded { "collapse" } else { "expand" }}</text>
+            </button>
+            <padding>
+                <rect fill=Some(context.listen(color)) constraint=BoxConstraints::tight(context.listen(width), 50.0) />
+            </padding>
+            <padding>
+                <rect fill=Some(Color::new(0.27, 0.67, 0.27, 1.0)) constraint=BoxConstraints::tight(context.listen(spring_width), 50.0) />
+            </padding>
+            <sized constraint=BoxConstraints::tight(400.0, 100.0)>
+                <align alignment=if is_expanded { Alignment::bottom_right() } else { Alignment::top_left() }>
+                    <animated_layout duration=Duration::from_millis(300)>
+                        <rect fill=Some(Color::new(0.67, 0.67, 0.27, 1.0)) constraint=BoxConstraints::tight(50.0, 50.0) />
+                    </animated_layout>
+                </align>
+            </sized>
+        </column>
+    }
+}
+
+fn main() {
+    env_logger::init();
+    app::render(
+        app::WindowBuilder::new().with_title("narui animation demo"),
+        rsx_toplevel! {
+            <animation_demo />
+        },
+    );
+}
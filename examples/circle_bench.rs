@@ -8,18 +8,23 @@ pub fn top(context: &mut WidgetContext) -> Fragment {
     });
 
     rsx! {
-        <row main_axis_alignment=MainAxisAlignment::SpaceEvenly>
-            {(0..300).map(|x| rsx!{
-                <column main_axis_alignment=MainAxisAlignment::SpaceEvenly key=x>
-                    {(0..300).map(|y| rsx! {
-                        <sized constraint=BoxConstraints::tight(10.0, 10.0) key=y><rect_leaf fill=Some({
-                                let val = context.listen(frame_counter);
-                                Color::from_components((x as f32 / 50., y as f32 / 50., ((val as f32 / 10.0).sin() + 1.) / 2., 1.))
-                        }) /></sized>
-                    }).collect()}
-                </column>
-            }).collect()}
-        </row>
+        <stack>
+            <row main_axis_alignment=MainAxisAlignment::SpaceEvenly>
+                {(0..300).map(|x| rsx!{
+                    <column main_axis_alignment=MainAxisAlignment::SpaceEvenly key=x>
+                        {(0..300).map(|y| rsx! {
+                            <sized constraint=BoxConstraints::tight(10.0, 10.0) key=y><rect_leaf fill=Some({
+                                    let val = context.listen(frame_counter);
+                                    Color::from_components((x as f32 / 50., y as f32 / 50., ((val as f32 / 10.0).sin() + 1.) / 2., 1.))
+                            }) /></sized>
+                        }).collect()}
+                    </column>
+                }).collect()}
+            </row>
+            <positioned pos=AbsolutePosition::from_offset(Offset { x: 10.0, y: 10.0 }) z_top=true>
+                <frame_stats_overlay />
+            </positioned>
+        </stack>
     }
 }
 
@@ -0,0 +1,39 @@
+use narui::*;
+
+#[widget]
+pub fn fragile(count: i32, context: &mut WidgetContext) -> Fragment {
+    if count % 3 == 2 {
+        panic!("fragile can not show {}", count);
+    }
+
+    rsx! {
+        <text>{format!("count is {}", count)}</text>
+    }
+}
+
+#[widget]
+pub fn error_boundary_demo(context: &mut WidgetContext) -> Fragment {
+    let count = context.listenable(0);
+
+    rsx! {
+         <column>
+            <error_boundary>
+                <fragile count=context.listen(count) />
+            </error_boundary>
+            <button on_click=move |context: &CallbackContext| context.shout(count, context.spy(count) + 1)>
+                <text>{" + "}</text>
+            </button>
+         </column>
+    }
+}
+
+
+fn main() {
+    env_logger::init();
+    app::render(
+        app::WindowBuilder::new().with_title("narui error boundary demo"),
+        rsx_toplevel! {
+            <error_boundary_demo />
+        },
+    );
+}
@@ -0,0 +1,49 @@
+use narui::*;
+use std::time::Duration;
+
+#[widget]
+pub fn fading_item(number: usize, context: &mut WidgetContext) -> Fragment {
+    // while the item is leaving, it fades out and can not be clicked anymore
+    let opacity = 1.0 - context.exit_transition(Duration::from_millis(500)).unwrap_or(0.0);
+
+    rsx! {
+        <padding padding=EdgeInsets::all(5.0)>
+            <rect fill=Some(Color::new(0.27, 0.27, 0.67, opacity)) constraint=BoxConstraints::tight(200.0, 30.0)>
+                <text color=Color::new(1.0, 1.0, 1.0, opacity)>{format!("item {}", number)}</text>
+            </rect>
+        </padding>
+    }
+}
+
+#[widget]
+pub fn exit_transition_demo(context: &mut WidgetContext) -> Fragment {
+    let count = context.listenable(3usize);
+
+    rsx! {
+        <column>
+            <row>
+                <button on_click=move |context: &CallbackContext| context.shout(count, context.spy(count) + 1)>
+                    <text>{"add"}</text>
+                </button>
+                <button on_click=move |context: &CallbackContext| context.shout(count, context.spy(count).saturating_sub(1))>
+                    <text>{"remove"}</text>
+                </button>
+            </row>
+            <column>
+                {(0..context.listen(count)).map(|i| rsx! {
+                    <fading_item key=i number=i />
+                }).collect()}
+            </column>
+        </column>
+    }
+}
+
+fn main() {
+    env_logger::init();
+    app::render(
+        app::WindowBuilder::new().with_title("narui exit transition demo"),
+        rsx_toplevel! {
+            <exit_transition_demo />
+        },
+    );
+}
@@ -0,0 +1,53 @@
+use narui::*;
+use std::{thread, time::Duration};
+
+#[widget]
+pub fn count_display(count: Listenable<i32>, context: &mut WidgetContext) -> Fragment {
+    rsx! {
+        <padding>
+            <text>{format!("{}", context.listen(count))}</text>
+        </padding>
+    }
+}
+
+#[widget]
+pub fn count_buttons(count: Listenable<i32>, context: &mut WidgetContext) -> Fragment {
+    rsx! {
+         <row>
+            <button on_click=move |context: &CallbackContext| context.shout(count, context.spy(count) - 1)>
+                <text>{" - "}</text>
+            </button>
+            <button on_click=move |context: &CallbackContext| context.shout(count, context.spy(count) + 1)>
+                <text>{" + "}</text>
+            </button>
+         </row>
+    }
+}
+
+
+fn main() {
+    env_logger::init();
+
+    // the store exists before any widget and is shared by all widgets that get
+    // handed the listenable
+    let mut stores = Stores::new();
+    let count = stores.listenable(0);
+
+    // business logic can shout into the store without being part of the tree
+    let thread_context = stores.thread_context();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(5));
+        thread_context.shout(count, 0);
+    });
+
+    app::render_with_stores(
+        app::WindowBuilder::new().with_title("narui stores demo"),
+        stores,
+        rsx_toplevel! {
+            <column>
+                <count_display count=count />
+                <count_buttons count=count />
+            </column>
+        },
+    );
+}
@@ -0,0 +1,33 @@
+use narui::*;
+use std::time::Duration;
+
+#[widget]
+pub fn timer_demo(context: &mut WidgetContext) -> Fragment {
+    let ticks = context.listenable(0);
+    let greeting = context.listenable("waiting...");
+
+    context.interval(Duration::from_secs(1), move |context: &CallbackContext| {
+        context.shout(ticks, context.spy(ticks) + 1)
+    });
+    context.timeout(Duration::from_secs(3), move |context: &CallbackContext| {
+        context.shout(greeting, "hello after three seconds")
+    });
+
+    rsx! {
+         <column>
+            <text>{format!("{} seconds passed", context.listen(ticks))}</text>
+            <text>{context.listen(greeting)}</text>
+         </column>
+    }
+}
+
+
+fn main() {
+    env_logger::init();
+    app::render(
+        app::WindowBuilder::new().with_title("narui timer demo"),
+        rsx_toplevel! {
+            <timer_demo />
+        },
+    );
+}
@@ -0,0 +1,44 @@
+use narui::*;
+
+#[widget]
+pub fn undo_demo(context: &mut WidgetContext) -> Fragment {
+    let value = context.listenable(24.0);
+    context.track_undo(value);
+    let can_undo = context.listen(context.can_undo());
+    let can_redo = context.listen(context.can_redo());
+
+    rsx! {
+        <column>
+            <sized constraint=BoxConstraints::default().with_tight_height(300.0)>
+                <align alignment=Alignment::bottom_center()>
+                    <text size=context.listen(value)>
+                        {format!("{:.1} px", context.listen(value))}
+                    </text>
+                </align>
+            </sized>
+            <slider
+                val={context.listen(value)}
+                on_change={move |context: &CallbackContext, new_val| context.shout(value, new_val)}
+                min=12.0 max=300.0
+            />
+            <row>
+                <button on_click=move |context: &CallbackContext| context.undo()>
+                    <text>{if can_undo { "undo" } else { "-" }}</text>
+                </button>
+                <button on_click=move |context: &CallbackContext| context.redo()>
+                    <text>{if can_redo { "redo" } else { "-" }}</text>
+                </button>
+            </row>
+        </column>
+    }
+}
+
+fn main() {
+    env_logger::init();
+    app::render(
+        app::WindowBuilder::new().with_title("narui undo demo"),
+        rsx_toplevel! {
+            <undo_demo />
+        },
+    );
+}
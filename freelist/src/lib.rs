@@ -1,4 +1,5 @@
 use std::{
+    fmt::Debug,
     mem::{ManuallyDrop, MaybeUninit},
     num::NonZeroUsize,
     ops::{Deref, Index, IndexMut},
@@ -6,24 +7,49 @@ use std::{
 
 pub type Idx = NonZeroUsize;
 
+/// The counter a `FreeList` keeps per slot to detect stale indices. `()`
+/// doesn't count and takes no space, `u32` enables `generation` and the
+/// checked `get`.
+pub trait Generation: Copy + Default + Ord + Debug {
+    fn next(self) -> Self;
+}
+
+impl Generation for () {
+    fn next(self) -> Self {}
+}
+
+impl Generation for u32 {
+    fn next(self) -> Self { self.wrapping_add(1) }
+}
+
+/// a `FreeList` that detects indices of removed values, even after their
+/// slot was reused
+pub type GenerationalFreeList<T> = FreeList<T, u32>;
+
 #[derive(Debug, Clone)]
-struct Slot<T> {
+struct Slot<T, G> {
     data: ManuallyDrop<T>,
     next_free: Option<Idx>,
+    // incremented every time the slot is freed, to detect stale indices
+    generation: G,
 }
 
 #[derive(Debug, Clone)]
-pub struct FreeList<T> {
-    entries: Vec<Slot<T>>,
+pub struct FreeList<T, G: Generation = ()> {
+    entries: Vec<Slot<T, G>>,
     next_free: Option<Idx>,
     used_slot_two: bool,
+    len: usize,
+    // the generation new slots start with, above the generations of the slots
+    // dropped by `shrink`
+    first_generation: G,
 }
 
-impl<T> Default for FreeList<T> {
+impl<T, G: Generation> Default for FreeList<T, G> {
     fn default() -> Self { Self::new() }
 }
 
-impl<T> Drop for FreeList<T> {
+impl<T, G: Generation> Drop for FreeList<T, G> {
     fn drop(&mut self) {
         let raw = self.entries.as_mut_ptr();
         let start = if self.used_slot_two { 1 } else { 2 };
@@ -37,28 +63,47 @@ impl<T> Drop for FreeList<T> {
     }
 }
 
-impl<T> FreeList<T> {
+impl<T, G: Generation> FreeList<T, G> {
     pub fn new() -> Self {
         let mut data = vec![];
 
         // push a dummy thing to idx zero so we can use NonZeroUsize
         #[allow(clippy::uninit_assumed_init)]
-        data.push(Slot { data: unsafe { MaybeUninit::uninit().assume_init() }, next_free: None });
+        data.push(Slot {
+            data: unsafe { MaybeUninit::uninit().assume_init() },
+            next_free: None,
+            generation: G::default(),
+        });
 
         // push a empty slot to use as first entry
         #[allow(clippy::uninit_assumed_init)]
-        data.push(Slot { data: unsafe { MaybeUninit::uninit().assume_init() }, next_free: None });
+        data.push(Slot {
+            data: unsafe { MaybeUninit::uninit().assume_init() },
+            next_free: None,
+            generation: G::default(),
+        });
 
         let next_free = Some(unsafe { Idx::new_unchecked(1) });
 
-        Self { entries: data, next_free, used_slot_two: false }
+        Self {
+            entries: data,
+            next_free,
+            used_slot_two: false,
+            len: 0,
+            first_generation: G::default(),
+        }
     }
 
     pub fn add(&mut self, data: T) -> Idx {
+        self.len += 1;
         match self.next_free {
             None => {
                 let idx = self.entries.len();
-                self.entries.push(Slot { data: ManuallyDrop::new(data), next_free: None });
+                self.entries.push(Slot {
+                    data: ManuallyDrop::new(data),
+                    next_free: None,
+                    generation: self.first_generation,
+                });
                 unsafe { Idx::new_unchecked(idx) }
             }
             Some(idx) => {
@@ -76,12 +121,18 @@ impl<T> FreeList<T> {
     }
 
     pub fn remove(&mut self, idx: Idx) {
-        self.entries[idx.get()].next_free = self.next_free;
+        self.len -= 1;
+        let slot = &mut self.entries[idx.get()];
+        slot.next_free = self.next_free;
+        slot.generation = slot.generation.next();
         self.next_free = Some(idx);
     }
 
     pub fn remove_replace(&mut self, idx: Idx, sentinel: T) {
-        self.entries[idx.get()].next_free = self.next_free;
+        self.len -= 1;
+        let slot = &mut self.entries[idx.get()];
+        slot.next_free = self.next_free;
+        slot.generation = slot.generation.next();
         unsafe {
             ManuallyDrop::drop(&mut std::mem::replace(
                 &mut self.entries[idx.get()].data,
@@ -112,6 +163,45 @@ impl<T> FreeList<T> {
 
     pub fn used_space(&self) -> usize { self.entries.len() }
 
+    /// the number of values that were added and not removed
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// the number of slots there is memory for
+    pub fn capacity(&self) -> usize { self.entries.capacity() }
+
+    /// drops the free slots at the end of the list and gives back the memory
+    /// that is not needed anymore. Slots in the middle stay, as their indices
+    /// are still in use.
+    pub fn shrink(&mut self) {
+        let mut chain = vec![];
+        let mut free = vec![false; self.entries.len()];
+        let mut next = self.next_free;
+        while let Some(idx) = next {
+            chain.push(idx);
+            free[idx.get()] = true;
+            next = self.entries[idx.get()].next_free;
+        }
+
+        let mut len = self.entries.len();
+        while len > 2 && free[len - 1] {
+            len -= 1;
+        }
+        for slot in self.entries.drain(len..) {
+            self.first_generation = self.first_generation.max(slot.generation);
+            drop(ManuallyDrop::into_inner(slot.data));
+        }
+
+        // the dropped slots are taken out of the chain of free slots
+        chain.retain(|idx| idx.get() < len);
+        self.next_free = chain.first().copied();
+        for (i, idx) in chain.iter().enumerate() {
+            self.entries[idx.get()].next_free = chain.get(i + 1).copied();
+        }
+        self.entries.shrink_to_fit();
+    }
+
     pub unsafe fn get_unchecked(&self, idx: Idx) -> &T {
         &self.entries.get_unchecked(idx.get()).data
     }
@@ -121,14 +211,86 @@ impl<T> FreeList<T> {
     }
 }
 
-impl<T> Index<Idx> for FreeList<T> {
+impl<T> FreeList<T, u32> {
+    /// the generation of the slot, which changes every time it is removed.
+    /// Together with the index it identifies one value, even after the slot
+    /// was reused.
+    pub fn generation(&self, idx: Idx) -> u32 { self.entries[idx.get()].generation }
+
+    /// the value at `idx`, if it was not removed since `generation` was read
+    pub fn get(&self, idx: Idx, generation: u32) -> Option<&T> {
+        self.is_current(idx, generation).then(|| &*self.entries[idx.get()].data)
+    }
+
+    pub fn get_mut(&mut self, idx: Idx, generation: u32) -> Option<&mut T> {
+        if self.is_current(idx, generation) {
+            Some(&mut self.entries[idx.get()].data)
+        } else {
+            None
+        }
+    }
+
+    fn is_current(&self, idx: Idx, generation: u32) -> bool {
+        // the first slot is uninitialized until it is used
+        let initialized = (idx.get() != 1) || self.used_slot_two;
+        initialized && self.entries.get(idx.get()).is_some_and(|s| s.generation == generation)
+    }
+}
+
+impl<T, G: Generation> Index<Idx> for FreeList<T, G> {
     type Output = T;
 
     fn index(&self, index: Idx) -> &Self::Output { &*self.entries[index.get()].data }
 }
 
-impl<T> IndexMut<Idx> for FreeList<T> {
+impl<T, G: Generation> IndexMut<Idx> for FreeList<T, G> {
     fn index_mut(&mut self, index: Idx) -> &mut Self::Output {
         &mut *self.entries[index.get()].data
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generations() {
+        let mut list = GenerationalFreeList::new();
+        let a = list.add("a".to_string());
+        let generation = list.generation(a);
+        assert_eq!(list.get(a, generation).map(String::as_str), Some("a"));
+
+        list.remove(a);
+        assert_eq!(list.get(a, generation), None);
+        let b = list.add("b".to_string());
+        assert_eq!(a, b);
+        assert_eq!(list.get(a, generation), None);
+        assert_eq!(list.get_mut(b, list.generation(b)).map(|b| b.as_str()), Some("b"));
+        assert_eq!(list.get(Idx::new(100).unwrap(), 0), None);
+    }
+
+    #[test]
+    fn shrink() {
+        let mut list = GenerationalFreeList::new();
+        let indices: Vec<_> = (0..100).map(|i| list.add(i.to_string())).collect();
+        let kept = indices[10];
+        let generation = list.generation(indices[50]);
+        for idx in &indices {
+            if *idx != kept {
+                list.remove(*idx);
+            }
+        }
+        assert_eq!(list.len(), 1);
+        list.shrink();
+        assert_eq!(list[kept], "10");
+        assert!(list.used_space() <= 12);
+
+        // the free slots before the kept one are still used, and the slots that were
+        // dropped do not come back with an old generation
+        let added: Vec<_> = (0..60).map(|i| list.add(i.to_string())).collect();
+        assert!(added.iter().filter(|idx| idx.get() < kept.get()).count() >= 9);
+        assert!(added.contains(&indices[50]));
+        assert_eq!(list.get(indices[50], generation), None);
+        assert_eq!(list.len(), 61);
+    }
+}
@@ -0,0 +1,101 @@
+// Passes unchanged and changed args to many widgets and reads them back, like
+// when the parent is re-evaluated, and creates many new widgets. Reports the
+// time per widget for both. Run with
+// `cargo bench -p narui_core --features narui_core/bench --bench arg_storage`
+use narui_core::{
+    bench_api::{root_widget, FragmentStore, WidgetLocalContext},
+    _macro_api::{shout_args, smallvec, ArgRef, ArgValues, Fragment, WidgetArgs},
+};
+use std::time::Instant;
+
+// like the context the macros expand in
+struct Context {
+    fragment_store: FragmentStore,
+    widget_local: WidgetLocalContext,
+}
+
+impl Context {
+    fn new() -> Self {
+        let fragment_store = FragmentStore::default();
+        let widget_local = root_widget(&fragment_store);
+        Self { fragment_store, widget_local }
+    }
+}
+
+// what `#[widget]` generates for a widget with these args
+struct Args<A, B, C> {
+    size: A,
+    label: B,
+    on_click: C,
+    offset: (f32, f32),
+    count: usize,
+    enabled: bool,
+    z: i64,
+    mark: char,
+}
+
+impl<A: 'static, B: 'static, C: 'static> WidgetArgs for Args<A, B, C> {
+    fn values(&self) -> ArgValues {
+        smallvec![
+            &self.size as _,
+            &self.label as _,
+            &self.on_click as _,
+            &self.offset as _,
+            &self.count as _,
+            &self.enabled as _,
+            &self.z as _,
+            &self.mark as _,
+        ]
+    }
+}
+
+fn args(size: f32, label: &'static str) -> Args<f32, &'static str, impl Fn()> {
+    Args {
+        size,
+        label,
+        on_click: || {},
+        offset: (1.0, 2.0),
+        count: 4,
+        enabled: true,
+        z: 5,
+        mark: 'c',
+    }
+}
+
+fn shout(context: &mut Context, idx: Fragment, args: Args<f32, &'static str, impl Fn() + 'static>) {
+    shout_args!(
+        context, idx, args, size: 0, label: 1, on_click: 2, offset: 3, count: 4, enabled: 5, z: 6,
+        mark: 7
+    );
+}
+
+fn main() {
+    let mut context = Context::new();
+    let fragments: Vec<_> =
+        (0..1000).map(|_| context.fragment_store.add_empty_fragment()).collect();
+    let rounds = 2000;
+    let mut sum = 0.0;
+    let start = Instant::now();
+    for round in 0..rounds {
+        for idx in fragments.iter().copied() {
+            shout(&mut context, idx, args((round % 10) as f32, "label"));
+            let any = &**context.fragment_store.get_args(idx).as_ref().unwrap();
+            let args = unsafe { ArgRef::for_value(&args(0.0, "")).parse(any) };
+            sum += args.size + args.offset.0 + args.count as f32 + args.z as f32;
+            sum += args.label.len() as f32 + args.enabled as u8 as f32 + args.mark as u8 as f32;
+        }
+        context.fragment_store.dirty_args().for_each(drop);
+    }
+    let elapsed = start.elapsed().as_nanos() as f64;
+    println!("{:.1} ns per widget ({})", elapsed / (rounds * fragments.len()) as f64, sum);
+
+    let start = Instant::now();
+    for _ in 0..200 {
+        let mut context = Context::new();
+        for _ in 0..1000 {
+            let idx = context.fragment_store.add_empty_fragment();
+            shout(&mut context, idx, args(1.0, "label"));
+        }
+    }
+    println!("{:.1} ns per new widget", start.elapsed().as_nanos() as f64 / 200_000.0);
+}
@@ -0,0 +1,119 @@
+// Looks up the keys of the children of a widget in a shuffled order, so most
+// lookups search the packed tails of all children, like when a keyed list is
+// reordered. Reports the time per lookup for integer and string keys, and for
+// the baseline that interned every key and packed the tails into i64 (so AVX2
+// compared 4 instead of 8 tails at once). Run with
+// `cargo bench -p narui_core --features narui_core/bench --bench keyed_children`
+use narui_core::{
+    bench_api::{widget, KeyMap},
+    _macro_api::{arg_formatter, direct_key, KeyPart},
+    Key,
+};
+use std::{hash::Hash, time::Instant};
+
+const CHILDREN: u32 = 1000;
+const ROUNDS: usize = 200;
+
+// the part rsx generates for `key=value`
+fn part<K: Hash + Eq + 'static>(key_map: &mut KeyMap, value: K) -> KeyPart {
+    match direct_key!(&value) {
+        Some(key) => KeyPart::FragmentKey { widget_id: 0, location_id: 3, key },
+        None => {
+            let formatter = arg_formatter!(&value);
+            let key = key_map.intern_key(Key::default(), 3, value, formatter);
+            KeyPart::FragmentInternedKey { widget_id: 0, location_id: 3, key }
+        }
+    }
+}
+
+fn current<K: Hash + Eq + Clone + 'static>(keys: &[K], order: &[usize]) -> f64 {
+    let mut key_map = KeyMap::default();
+    let mut next = 2;
+    let mut new_key = || {
+        next += 1;
+        widget(next).0
+    };
+    for key in keys {
+        let part = part(&mut key_map, key.clone());
+        key_map.key_with(Key::default(), part, &mut new_key);
+    }
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for i in order {
+            let part = part(&mut key_map, keys[*i].clone());
+            key_map.key_with(Key::default(), part, &mut new_key);
+        }
+    }
+    start.elapsed().as_nanos() as f64 / (ROUNDS * order.len()) as f64
+}
+
+// the tails as the baseline packed them
+fn pack_baseline(location_id: u16, key: u32) -> i64 {
+    ((key as i64 + 1) << 16) | location_id as i64
+}
+
+#[cfg(target_arch = "x86_64")]
+fn find_baseline(data: &[i64], needle: i64) -> Option<usize> {
+    use std::arch::x86_64::*;
+    let trunc = (data.len() / 4) * 4;
+
+    unsafe {
+        let needle_wide = _mm256_set1_epi64x(needle);
+        for i in (0..trunc).step_by(4) {
+            let addr = data.get_unchecked(i) as *const i64 as *const __m256i;
+            let d = _mm256_loadu_si256(addr);
+            let cmp = _mm256_cmpeq_epi64(needle_wide, d);
+            let mask = _mm256_movemask_epi8(cmp);
+            if mask != 0 {
+                return Some(i + (mask.trailing_zeros() / 8) as usize);
+            }
+        }
+
+        for i in trunc..data.len() {
+            if *data.get_unchecked(i) == needle {
+                return Some(i);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn find_baseline(data: &[i64], needle: i64) -> Option<usize> {
+    data.iter().position(|v| *v == needle)
+}
+
+fn baseline<K: Hash + Eq + Clone + 'static>(keys: &[K], order: &[usize]) -> f64 {
+    let mut key_map = KeyMap::default();
+    let mut intern = |key: &K| {
+        let formatter = arg_formatter!(key);
+        key_map.intern_key(Key::default(), 3, key.clone(), formatter)
+    };
+    let tails: Vec<_> = keys.iter().map(|key| pack_baseline(3, intern(key))).collect();
+    let mut found = 0;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for i in order {
+            found += find_baseline(&tails, pack_baseline(3, intern(&keys[*i]))).unwrap();
+        }
+    }
+    let elapsed = start.elapsed().as_nanos() as f64 / (ROUNDS * order.len()) as f64;
+    assert_eq!(found, ROUNDS * order.iter().sum::<usize>());
+    elapsed
+}
+
+fn main() {
+    // a fixed permutation, so runs can be compared
+    let order: Vec<_> = (0..CHILDREN as usize).map(|i| i * 7919 % CHILDREN as usize).collect();
+    let integers: Vec<_> = (0..CHILDREN).map(|i| i * 3).collect();
+    let strings: Vec<_> = integers.iter().map(|i| format!("item {}", i)).collect();
+
+    println!("keys     current   baseline");
+    for (name, current, baseline) in [
+        ("integer", current(&integers, &order), baseline(&integers, &order)),
+        ("string", current(&strings, &order), baseline(&strings, &order)),
+    ] {
+        println!("{:7}  {:5.1} ns  {:5.1} ns", name, current, baseline);
+    }
+}
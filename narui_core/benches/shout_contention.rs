@@ -0,0 +1,260 @@
+// Threads shouting at and spying on stores while the UI thread evaluates a
+// tree that listens to the hooks of a few hundred widgets. Reports how long
+// the evaluation and the update of the tree (update_tree and dependents) take
+// per frame for every number of shouting threads, which should stay flat as
+// long as there are cores for the threads. The baseline is the tree before the
+// patches were double buffered, which kept them in a DashMap and took the
+// write lock of the tree for every hook lookup and every listen. Run with
+// `cargo bench -p narui_core --features narui_core/bench --bench shout_contention`
+//
+// On a machine with a single core this printed
+//
+// tree      threads  shouts/s  frames/s  evaluation  update
+// current         0         0      7349    135.8 µs  0.3 µs
+// current         1   4929183      2274    382.2 µs  57.5 µs
+// current         2   6582301      1030    798.3 µs  172.1 µs
+// current         4   7487158       173   2674.7 µs  3100.8 µs
+// current         8   7177615       114   2886.4 µs  5862.5 µs
+// baseline        0         0      9562    104.3 µs  0.2 µs
+// baseline        1  13864706      3627    224.6 µs  50.7 µs
+// baseline        2  18840778      2016    323.8 µs  172.2 µs
+// baseline        4  22489115       872    462.8 µs  684.5 µs
+// baseline        8  24566684       190    635.0 µs  4615.5 µs
+//
+// With one core the threads only take turns and never contend for the locks,
+// so this does not show what the double buffering is for. It only shows the
+// cost of every operation, where the current tree is slower: listening locks
+// the dependents of the hook, and every shout allocates a patch that is kept
+// until the next update_tree. The evaluation times with shouting threads
+// mostly measure how long the threads held the core.
+use narui_core::bench_api::{widget, HookRef, KeyMap, PatchedTree};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+const WIDGETS: u32 = 500;
+const HOOKS: u16 = 4;
+const DURATION: Duration = Duration::from_secs(3);
+
+// what the evaluation and the shouting threads do with the tree
+trait Tree: Default + Send + Sync + 'static {
+    type Hook: Copy + Send + 'static;
+
+    fn hook(&self, widget: u32, index: u16) -> Self::Hook;
+    fn listen(&self, hook: Self::Hook, widget: u32) -> usize;
+    fn shout(&self, hook: Self::Hook, value: usize);
+    fn spy(&self, hook: Self::Hook) -> usize;
+    fn update(&self, key_map: &mut KeyMap);
+}
+
+impl Tree for PatchedTree {
+    type Hook = HookRef;
+
+    fn hook(&self, idx: u32, index: u16) -> Self::Hook {
+        self.initialize_with((widget(idx).0, index), || Box::new(0usize))
+    }
+
+    fn listen(&self, hook: Self::Hook, idx: u32) -> usize {
+        self.set_dependent(hook, widget(idx).1);
+        *self.get_unpatched(hook).unwrap().downcast_ref::<usize>().unwrap()
+    }
+
+    fn shout(&self, hook: Self::Hook, value: usize) { self.set(hook, Box::new(value)) }
+
+    fn spy(&self, hook: Self::Hook) -> usize {
+        *self.get_patched(hook).unwrap().downcast_ref::<usize>().unwrap()
+    }
+
+    fn update(&self, key_map: &mut KeyMap) {
+        for hook in self.update_tree(key_map).collect::<Vec<_>>() {
+            self.dependents(hook).for_each(drop);
+        }
+    }
+}
+
+// the tree as of 60d53f0, without undo and persistence
+mod baseline {
+    use dashmap::DashMap;
+    use freelist::{FreeList, Idx};
+    use hashbrown::HashMap;
+    use parking_lot::RwLock;
+    use std::{
+        any::Any,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    type Dependents = tinyset::Set64<usize>;
+    type TreeItem = Box<dyn Any + Send + Sync>;
+    type HookKey = (u32, u16);
+    pub type HookRef = (HookKey, Idx);
+
+    struct Patch {
+        key: HookKey,
+        value: TreeItem,
+    }
+
+    #[derive(Default)]
+    pub struct PatchedTree {
+        data: RwLock<FreeList<(Dependents, TreeItem)>>,
+        key_to_idx: RwLock<HashMap<u32, HashMap<u16, Idx>>>,
+        patch: DashMap<Idx, Patch, ahash::RandomState>,
+        wake_pending: AtomicBool,
+    }
+
+    impl PatchedTree {
+        pub fn get_patched(&self, idx: HookRef) -> usize {
+            match self.patch.get(&idx.1) {
+                Some(patch) => *patch.value.downcast_ref().unwrap(),
+                None => self.get_unpatched(idx),
+            }
+        }
+
+        pub fn get_unpatched(&self, idx: HookRef) -> usize {
+            *self.data.read()[idx.1].1.downcast_ref().unwrap()
+        }
+
+        pub fn initialize_with(&self, key: HookKey, gen: impl FnOnce() -> TreeItem) -> HookRef {
+            (
+                key,
+                *self
+                    .key_to_idx
+                    .write()
+                    .entry(key.0)
+                    .or_default()
+                    .entry(key.1)
+                    .or_insert_with(|| self.data.write().add((Default::default(), gen()))),
+            )
+        }
+
+        pub fn set(&self, idx: HookRef, value: TreeItem) {
+            self.patch.insert(idx.1, Patch { value, key: idx.0 });
+            self.wake_pending.swap(true, Ordering::AcqRel);
+        }
+
+        fn is_live(&self, hook: HookRef) -> bool {
+            let key_to_idx = self.key_to_idx.read();
+            key_to_idx.get(&hook.0 .0).and_then(|indices| indices.get(&hook.0 .1)) == Some(&hook.1)
+        }
+
+        pub fn update_tree(&self) -> impl Iterator<Item = HookRef> {
+            self.wake_pending.store(false, Ordering::Release);
+            let keys: Vec<_> = self.patch.iter().map(|kv| *kv.key()).collect();
+            let touched: Vec<_> = keys
+                .into_iter()
+                .filter_map(|idx| {
+                    let (idx, Patch { value, key }) = self.patch.remove(&idx).unwrap();
+                    if !self.is_live((key, idx)) {
+                        return None;
+                    }
+                    let old = std::mem::replace(&mut self.data.write()[idx].1, value);
+                    std::mem::drop(old);
+                    Some((key, idx))
+                })
+                .collect();
+            touched.into_iter()
+        }
+
+        pub fn set_dependent(&self, key: HookRef, frag: u32) {
+            self.data.write()[key.1].0.insert(frag as _);
+        }
+
+        pub fn dependents(&'_ self, key: HookRef) -> impl Iterator<Item = usize> + '_ {
+            std::mem::take(&mut self.data.write()[key.1].0).into_iter()
+        }
+    }
+}
+
+impl Tree for baseline::PatchedTree {
+    type Hook = baseline::HookRef;
+
+    fn hook(&self, idx: u32, index: u16) -> Self::Hook {
+        self.initialize_with((idx, index), || Box::new(0usize))
+    }
+
+    fn listen(&self, hook: Self::Hook, idx: u32) -> usize {
+        self.set_dependent(hook, idx);
+        self.get_unpatched(hook)
+    }
+
+    fn shout(&self, hook: Self::Hook, value: usize) { self.set(hook, Box::new(value)) }
+
+    fn spy(&self, hook: Self::Hook) -> usize { self.get_patched(hook) }
+
+    fn update(&self, _key_map: &mut KeyMap) {
+        for hook in self.update_tree().collect::<Vec<_>>() {
+            self.dependents(hook).for_each(drop);
+        }
+    }
+}
+
+fn run<T: Tree>(name: &str) {
+    for threads in [0, 1, 2, 4, 8] {
+        let tree = Arc::new(T::default());
+        // the stores are hooks of a widget that is not evaluated
+        let stores: Vec<_> = (0..threads).map(|i| tree.hook(WIDGETS + 2, i)).collect();
+        let shouts = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+        let workers: Vec<_> = stores
+            .iter()
+            .copied()
+            .map(|store| {
+                let (tree, shouts, done) = (tree.clone(), shouts.clone(), done.clone());
+                std::thread::spawn(move || {
+                    let mut count = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        tree.shout(store, tree.spy(store) + 1);
+                        count += 1;
+                    }
+                    shouts.fetch_add(count, Ordering::Relaxed);
+                })
+            })
+            .collect();
+
+        let mut key_map = KeyMap::default();
+        let (mut frames, mut evaluation, mut update) = (0, Duration::ZERO, Duration::ZERO);
+        let start = Instant::now();
+        while start.elapsed() < DURATION {
+            let evaluation_start = Instant::now();
+            for idx in 2..WIDGETS + 2 {
+                for index in 0..HOOKS {
+                    let hook = tree.hook(idx, index);
+                    tree.listen(hook, idx);
+                }
+                // every widget also listens to one of the stores that are shouted at
+                if let Some(store) = stores.get(idx as usize % threads.max(1) as usize) {
+                    tree.listen(*store, idx);
+                }
+            }
+            let update_start = Instant::now();
+            tree.update(&mut key_map);
+            evaluation += update_start - evaluation_start;
+            update += update_start.elapsed();
+            frames += 1;
+        }
+        done.store(true, Ordering::Relaxed);
+        workers.into_iter().for_each(|worker| worker.join().unwrap());
+
+        let seconds = start.elapsed().as_secs_f64();
+        println!(
+            "{:8}  {:>7}  {:>8.0}  {:>8.0}  {:>7.1} µs  {:>3.1} µs",
+            name,
+            threads,
+            shouts.load(Ordering::Relaxed) as f64 / seconds,
+            frames as f64 / seconds,
+            evaluation.as_secs_f64() * 1e6 / frames as f64,
+            update.as_secs_f64() * 1e6 / frames as f64,
+        );
+    }
+}
+
+fn main() {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    println!("{} cores", cores);
+    println!("tree      threads  shouts/s  frames/s  evaluation  update");
+    run::<PatchedTree>("current");
+    run::<baseline::PatchedTree>("baseline");
+}
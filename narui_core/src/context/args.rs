@@ -18,46 +18,73 @@ impl<T> ArgRef<T> {
     where
         T: 'static,
     {
-        any.downcast_ref().expect("wrong type for argument")
+        any.downcast_ref().expect("wrong type for arguments")
     }
 
     pub fn for_value(_: &T) -> Self { Default::default() }
 }
 
-pub fn listen_args<'a>(
-    context: &'a mut WidgetContext,
-    key: &Fragment,
-) -> &'a SmallVec<[Box<dyn Any>; 8]> {
-    context.fragment_store.get_args(*key).as_ref().unwrap()
+pub type ArgValues<'a> = SmallVec<[&'a dyn Any; 8]>;
+pub type ArgValuesFn = for<'a> fn(&'a dyn Any) -> ArgValues<'a>;
+
+/// Implemented by the struct the `#[widget]` macro generates for the args of
+/// every widget. The args of a widget are stored together in one of these.
+pub trait WidgetArgs: 'static {
+    /// the args in the order of the widget function
+    fn values(&self) -> ArgValues;
+}
+
+fn arg_values<T: WidgetArgs>(any: &dyn Any) -> ArgValues {
+    any.downcast_ref::<T>().expect("wrong type for arguments").values()
+}
+
+/// gets the args out of a stored args struct of the same type as `args`
+pub fn arg_values_fn<T: WidgetArgs>(_args: &T) -> ArgValuesFn { arg_values::<T> }
+
+pub fn listen_args<'a>(context: &'a mut WidgetContext, key: &Fragment) -> &'a dyn Any {
+    &**context.fragment_store.get_args(*key).as_ref().unwrap()
 }
 
 /// shout arg is a macro for not requiring the implementation of PartialEq on
-/// all args.
+/// all args. The args struct is compared field by field, each field is given
+/// with its index.
 #[macro_export]
 macro_rules! shout_args_ {
-    ($context:expr, $idx:ident, $($values:expr,)*) => {
+    ($context:expr, $idx:ident, $args:expr, $($field:ident: $index:literal),* $(,)?) => {{
+        let args = $args;
         match $context.fragment_store.get_args_mut($idx) {
-            None => $context.fragment_store.set_args($idx, $crate::_macro_api::smallvec![$(Box::new($values) as _,)*]),
-            Some(old_values) => {
-                let mut idx = 0;
-                let mut any_changed = false;
+            None => {
+                $context.fragment_store.set_arg_formatters(
+                    $idx,
+                    $crate::_macro_api::arg_values_fn(&args),
+                    $crate::_macro_api::smallvec![$($crate::_macro_api::arg_formatter!(&args.$field),)*],
+                );
+                $context.fragment_store.set_args($idx, Box::new(args))
+            }
+            Some(old_args) => {
                 #[allow(unused)]
                 fn constrain_type<T>(a: &mut T, b: &T) {}
-                $({
-                    let old_value = &mut old_values[idx];
-                    let old = old_value.downcast_mut().expect("wrong type for argument");
-                    constrain_type(old, &$values);
-                    if !$crate::_macro_api::all_eq!(&*old, &$values) {
-                        *old = $values;
-                        any_changed = true;
+                #[allow(unused)]
+                let old = old_args.downcast_mut().expect("wrong type for arguments");
+                constrain_type(old, &args);
+                #[allow(unused_mut)]
+                let mut changed = $crate::_macro_api::SmallVec::<[usize; 8]>::new();
+                $(
+                    if !$crate::_macro_api::all_eq!(&old.$field, &args.$field) {
+                        old.$field = args.$field;
+                        changed.push($index);
+                    }
+                )*
+                if !changed.is_empty() {
+                    let by = $context.widget_local.idx;
+                    for arg in changed {
+                        $context.fragment_store.arg_changed($idx, arg, by);
                     }
-                    idx += 1;
-                })*
-                if any_changed {
                     $context.fragment_store.set_args_dirty($idx);
                 }
             }
         };
-    };
+    }};
 }
 pub use shout_args_ as shout_args;
+
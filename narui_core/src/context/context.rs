@@ -1,14 +1,25 @@
+#[cfg(debug_assertions)]
+use super::hook_sites::{HookSite, HookSites};
+#[cfg(feature = "hot_reload")]
+use super::hot_reload::HookType;
 use super::{
+    args::ArgValuesFn,
     key::{Key, KeyMap},
     patched_tree::{HookKey, PatchedTree},
+    scheduler::Scheduler,
 };
-use crate::eval::{
-    delta_eval::EvaluatedFragment,
-    fragment::{Fragment, UnevaluatedFragment},
-    layout::{Layouter, ScaleFactor},
+use crate::{
+    eval::{
+        delta_eval::EvaluatedFragment,
+        fragment::{Fragment, UnevaluatedFragment},
+        layout::{Layouter, ScaleFactor},
+        render_trace::RenderTrace,
+    },
+    util::{arg_format::ArgFormatter, memory_stats::SlotStats},
+    Listenable,
 };
 use derivative::Derivative;
-use freelist::FreeList;
+use freelist::{GenerationalFreeList, Idx};
 use smallvec::SmallVec;
 use std::{any::Any, fmt::Debug, sync::Arc};
 use vulkano::{
@@ -93,14 +104,25 @@ impl MaybeEvaluatedFragment {
 #[derive(Debug)]
 pub struct FragmentInfo {
     pub fragment: Option<MaybeEvaluatedFragment>,
-    pub args: Option<SmallVec<[Box<dyn Any>; 8]>>,
+    // the args struct of the widget
+    pub args: Option<Box<dyn Any>>,
+    // used by the inspector to show the args
+    pub(crate) arg_values: Option<ArgValuesFn>,
+    pub(crate) arg_formatters: SmallVec<[ArgFormatter; 8]>,
     pub external_hook_count: u16,
+    // set for widgets that catch panics in their subtree
+    pub(crate) error_boundary: Option<Listenable<Option<String>>>,
+    // the nearest error boundary above this fragment in the evaluation tree
+    pub(crate) enclosing_boundary: Option<Fragment>,
+    #[cfg(debug_assertions)]
+    pub(crate) hook_sites: HookSites,
 }
 
 #[derive(Debug, Default)]
 pub struct FragmentStore {
-    pub(crate) data: FreeList<FragmentInfo>,
+    pub(crate) data: GenerationalFreeList<FragmentInfo>,
     dirty_args: Vec<Fragment>,
+    pub(crate) render_trace: Option<RenderTrace>,
 }
 
 impl FragmentStore {
@@ -115,16 +137,40 @@ impl FragmentStore {
     }
 
     pub fn add_empty_fragment(&mut self) -> Fragment {
-        let idx = Fragment(
-            self.data.add(FragmentInfo { fragment: None, args: None, external_hook_count: 0 }).get()
-                as _,
-        );
+        let idx = self.data.add(FragmentInfo {
+            fragment: None,
+            args: None,
+            arg_values: None,
+            arg_formatters: Default::default(),
+            external_hook_count: 0,
+            error_boundary: None,
+            enclosing_boundary: None,
+            #[cfg(debug_assertions)]
+            hook_sites: Default::default(),
+        });
+        let idx = Fragment(idx.get() as _, self.data.generation(idx));
         log::trace!("initialized a new fragment with idx {:?}", idx);
         idx
     }
 
+    /// the handle of the fragment of `key`, with the current generation of its
+    /// slot
+    pub fn fragment(&self, key: Key) -> Fragment {
+        let idx = unsafe { Idx::new_unchecked(key.0 as _) };
+        Fragment(key.0, self.data.generation(idx))
+    }
+
+    /// like get, but `None` if the fragment was removed since the handle was
+    /// created, even if its slot is used by another fragment now
+    pub(crate) fn get_checked(&self, idx: Fragment) -> Option<&MaybeEvaluatedFragment> {
+        self.data.get(idx.into(), idx.1)?.fragment.as_ref()
+    }
+
     pub unsafe fn removed(&mut self, idx: Fragment) -> bool {
-        self.data.removed(idx.into()) || self.data[idx.into()].fragment.is_none()
+        // the slots of removed fragments might be gone after compacting
+        idx.0 as usize >= self.data.used_space()
+            || self.data.removed(idx.into())
+            || self.data[idx.into()].fragment.is_none()
     }
 
     pub fn add_fragment(
@@ -143,6 +189,27 @@ impl FragmentStore {
         self.data[idx.into()].fragment.as_ref().unwrap()
     }
 
+    /// like get, but for fragment ids that come from outside and might not
+    /// exist (anymore)
+    #[cfg(feature = "devtools")]
+    pub(crate) fn try_get(&self, idx: Fragment) -> Option<&MaybeEvaluatedFragment> {
+        if idx.0 == 0 || idx.0 as usize >= self.data.used_space() {
+            return None;
+        }
+        self.data[idx.into()].fragment.as_ref()
+    }
+
+    /// like fragment, but `None` if the key comes from outside and there is no
+    /// fragment for it (anymore)
+    #[cfg(feature = "devtools")]
+    pub(crate) fn try_fragment(&self, key: Key) -> Option<Fragment> {
+        if key.0 == 0 || key.0 as usize >= self.data.used_space() {
+            return None;
+        }
+        let fragment = self.fragment(key);
+        self.get_checked(fragment).map(|_| fragment)
+    }
+
     pub(crate) fn get_mut(&mut self, idx: Fragment) -> &mut MaybeEvaluatedFragment {
         self.data[idx.into()].fragment.as_mut().unwrap()
     }
@@ -152,21 +219,68 @@ impl FragmentStore {
         self.data.remove(idx.into());
     }
 
-    pub fn get_args(&self, idx: Fragment) -> &Option<SmallVec<[Box<dyn Any>; 8]>> {
-        &self.data[idx.into()].args
+    pub(crate) fn set_error_boundary(&mut self, idx: Fragment, error: Listenable<Option<String>>) {
+        self.data[idx.into()].error_boundary = Some(error);
     }
 
-    pub fn get_args_mut(&mut self, idx: Fragment) -> &mut Option<SmallVec<[Box<dyn Any>; 8]>> {
+    pub(crate) fn error_boundary(&self, idx: Fragment) -> Option<Listenable<Option<String>>> {
+        self.data[idx.into()].error_boundary
+    }
+
+    /// records that child is evaluated as a child of parent
+    pub(crate) fn set_enclosing_boundary(&mut self, child: Fragment, parent: Fragment) {
+        let parent_info = &self.data[parent.into()];
+        let boundary = match parent_info.error_boundary {
+            Some(_) => Some(parent),
+            None => parent_info.enclosing_boundary,
+        };
+        self.data[child.into()].enclosing_boundary = boundary;
+    }
+
+    pub(crate) fn enclosing_boundary(&self, idx: Fragment) -> Option<Fragment> {
+        self.data[idx.into()].enclosing_boundary
+    }
+
+    pub fn get_args(&self, idx: Fragment) -> &Option<Box<dyn Any>> { &self.data[idx.into()].args }
+
+    pub fn get_args_mut(&mut self, idx: Fragment) -> &mut Option<Box<dyn Any>> {
         &mut self.data[idx.into()].args
     }
 
+    pub fn set_arg_formatters(
+        &mut self,
+        idx: Fragment,
+        values: ArgValuesFn,
+        formatters: SmallVec<[ArgFormatter; 8]>,
+    ) {
+        self.data[idx.into()].arg_values = Some(values);
+        self.data[idx.into()].arg_formatters = formatters;
+    }
+
     pub fn set_args_dirty(&mut self, idx: Fragment) { self.dirty_args.push(idx); }
 
-    pub fn set_args(&mut self, idx: Fragment, args: SmallVec<[Box<dyn Any>; 8]>) {
+    /// records that the arg with the index `arg` passed to `idx` by the widget
+    /// `by` changed, if rerenders are traced
+    pub fn arg_changed(&mut self, idx: Fragment, arg: usize, by: Fragment) {
+        if let Some(trace) = &mut self.render_trace {
+            trace.arg_changed(idx, arg, by);
+        }
+    }
+
+    pub fn set_args(&mut self, idx: Fragment, args: Box<dyn Any>) {
         self.dirty_args.push(idx);
         self.data[idx.into()].args = Some(args);
     }
 
+    pub(crate) fn memory_stats(&self) -> SlotStats {
+        SlotStats { live: self.data.len(), capacity: self.data.capacity() }
+    }
+
+    pub(crate) fn compact(&mut self) {
+        self.data.shrink();
+        self.dirty_args.shrink_to_fit();
+    }
+
     pub fn dirty_args(&'_ mut self) -> impl Iterator<Item = Fragment> + '_ {
         self.dirty_args.drain(..).rev()
     }
@@ -189,15 +303,44 @@ pub struct WidgetContext<'a> {
     pub fragment_store: &'a mut FragmentStore,
     #[derivative(Debug(format_with = "crate::util::format_helpers::print_vec_len"))]
     pub(crate) after_frame_callbacks: &'a mut Vec<AfterFrameCallback>,
+    #[derivative(Debug = "ignore")]
+    pub(crate) scheduler: &'a mut Scheduler,
     pub key_map: &'a mut KeyMap,
     pub vulkan_context: VulkanContext,
 }
 
 impl<'a> WidgetContext<'a> {
-    pub fn key_for_hook(&mut self) -> HookKey {
+    /// the values of untyped hooks are not carried over by hot reloading
+    #[track_caller]
+    pub fn key_for_hook(&mut self) -> HookKey { self.hook_key::<()>(false) }
+
+    /// like `key_for_hook`, but debug builds also remember the type of the hook
+    /// to detect hooks that are called conditionally
+    #[track_caller]
+    pub fn key_for_typed_hook<T: 'static>(&mut self) -> HookKey { self.hook_key::<T>(true) }
+
+    #[track_caller]
+    #[cfg_attr(not(feature = "hot_reload"), allow(unused_variables))]
+    fn hook_key<T: 'static>(&mut self, typed: bool) -> HookKey {
         if self.local_hook {
             let counter = self.widget_local.hook_counter;
             self.widget_local.hook_counter += 1;
+            #[cfg(debug_assertions)]
+            self.fragment_store.data[self.widget_local.idx.into()].hook_sites.check(
+                self.key_map,
+                self.widget_local.key,
+                counter,
+                HookSite {
+                    type_name: std::any::type_name::<T>(),
+                    location: std::panic::Location::caller(),
+                },
+            );
+            #[cfg(feature = "hot_reload")]
+            if let Some(hook_type) = HookType::of::<T>().filter(|_| typed) {
+                self.tree.restore_hook((self.widget_local.key, counter), hook_type, || {
+                    (format!("{:?}", self.key_map.key_debug(self.widget_local.key)), counter)
+                });
+            }
             log::trace!(
                 "creating local hook: {:?}:{}",
                 self.key_map.key_debug(self.widget_local.key),
@@ -216,6 +359,16 @@ impl<'a> WidgetContext<'a> {
         }
     }
 
+    /// called by the evaluator after the widget was evaluated
+    pub(crate) fn finish_hooks(&mut self) {
+        #[cfg(debug_assertions)]
+        self.fragment_store.data[self.widget_local.idx.into()].hook_sites.finish(
+            self.key_map,
+            self.widget_local.key,
+            self.widget_local.hook_counter,
+        );
+    }
+
     pub fn thread_context(&self) -> ThreadContext { ThreadContext { tree: self.tree.clone() } }
 
     pub fn root(
@@ -224,11 +377,13 @@ impl<'a> WidgetContext<'a> {
         tree: Arc<PatchedTree>,
         fragment_store: &'a mut FragmentStore,
         after_frame_callbacks: &'a mut Vec<AfterFrameCallback>,
+        scheduler: &'a mut Scheduler,
         key_map: &'a mut KeyMap,
     ) -> Self {
         Self {
             tree,
             after_frame_callbacks,
+            scheduler,
             fragment_store,
             widget_local: WidgetLocalContext::for_key(Default::default(), top),
             key_map,
@@ -244,11 +399,16 @@ impl<'a> WidgetContext<'a> {
         key: Key,
         idx: Fragment,
         after_frame_callbacks: &'a mut Vec<AfterFrameCallback>,
+        scheduler: &'a mut Scheduler,
         key_map: &'a mut KeyMap,
     ) -> Self {
+        // the listenables the widget listens to record this handle, so it has to
+        // be the current one of the slot
+        let idx = fragment_store.fragment(idx.into());
         WidgetContext {
             tree,
             after_frame_callbacks,
+            scheduler,
             fragment_store,
             widget_local: WidgetLocalContext::for_key(key, idx),
             key_map,
@@ -258,11 +418,13 @@ impl<'a> WidgetContext<'a> {
     }
 
     pub fn with_key_widget(&mut self, key: Key, idx: Fragment) -> WidgetContext {
+        let idx = self.fragment_store.fragment(idx.into());
         WidgetContext {
             tree: self.tree.clone(),
             local_hook: true,
             fragment_store: self.fragment_store,
             after_frame_callbacks: self.after_frame_callbacks,
+            scheduler: self.scheduler,
             widget_local: WidgetLocalContext::for_key(key, idx),
             key_map: &mut self.key_map,
             vulkan_context: self.vulkan_context.clone(),
@@ -275,6 +437,48 @@ pub struct ThreadContext {
     pub(crate) tree: Arc<PatchedTree>,
 }
 
+/// Stores holds application level state that is not bound to the lifetime of
+/// any widget. It is created before the widget tree exists, handed to
+/// `render_with_stores` and its listenables can then be used from every widget,
+/// callback and thread like any other listenable.
+pub struct Stores {
+    pub(crate) tree: Arc<PatchedTree>,
+    counter: u16,
+}
+
+impl Default for Stores {
+    fn default() -> Self { Self::new() }
+}
+
+// stores used by narui itself count down from the top, so they never collide
+// with the ones handed out by `key_for_store`
+pub(crate) const FRAME_STATS_STORE: u16 = u16::MAX;
+pub(crate) const FRAME_STATS_HISTORY_STORE: u16 = u16::MAX - 1;
+pub(crate) const CAN_UNDO_STORE: u16 = u16::MAX - 2;
+pub(crate) const CAN_REDO_STORE: u16 = u16::MAX - 3;
+
+impl Stores {
+    pub fn new() -> Self { Self { tree: Arc::new(Default::default()), counter: 0 } }
+
+    pub fn key_for_store(&mut self) -> HookKey {
+        let counter = self.counter;
+        assert!(counter < CAN_REDO_STORE, "too many stores were created");
+        self.counter += 1;
+        log::trace!("creating store: {}", counter);
+        (Key::STORE, counter)
+    }
+
+    pub fn thread_context(&self) -> ThreadContext { ThreadContext { tree: self.tree.clone() } }
+
+    /// loads the values of persistent listenables from the JSON file at `path`
+    /// and saves them there. Has to be called before any persistent listenable
+    /// is created.
+    #[cfg(feature = "persistence")]
+    pub fn persist_state(&self, path: impl Into<std::path::PathBuf>) {
+        self.tree.set_state_file(path.into())
+    }
+}
+
 pub struct CallbackContext<'a> {
     pub(crate) tree: Arc<PatchedTree>,
     pub key_map: &'a KeyMap,
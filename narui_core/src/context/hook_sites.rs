@@ -0,0 +1,93 @@
+//! Hooks are identified by the order in which a widget calls them. In debug
+//! builds we remember the type and call site of every hook of a widget, so a
+//! hook that is called conditionally results in a clear error instead of
+//! silently sharing the state of a different hook.
+
+use super::key::{internal::WIDGET_INFO, Key, KeyMap, KeyPart};
+use std::panic::Location;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HookSite {
+    pub(crate) type_name: &'static str,
+    pub(crate) location: &'static Location<'static>,
+}
+
+impl PartialEq for HookSite {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_name == other.type_name
+            && self.location.file() == other.location.file()
+            && self.location.line() == other.location.line()
+            && self.location.column() == other.location.column()
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct HookSites {
+    sites: Vec<HookSite>,
+    // set after the first evaluation of the widget finished
+    complete: bool,
+}
+
+impl HookSites {
+    pub(crate) fn get(&self, index: u16) -> Option<&HookSite> { self.sites.get(index as usize) }
+
+    pub(crate) fn check(&mut self, key_map: &KeyMap, key: Key, index: u16, site: HookSite) {
+        match self.sites.get(index as usize) {
+            Some(old) if *old == site => {}
+            Some(old) => panic!(
+                "hook #{} of {} changed between evaluations: it was `{}` created at {}, but now \
+                 `{}` is created at {}. Hooks must be called in the same order on every \
+                 evaluation, they can not be called conditionally.",
+                index,
+                widget_name(key_map, key),
+                old.type_name,
+                old.location,
+                site.type_name,
+                site.location,
+            ),
+            None if self.complete => panic!(
+                "hook #{} of {} (`{}` created at {}) was not called in the previous evaluations. \
+                 Hooks must be called in the same order on every evaluation, they can not be \
+                 called conditionally.",
+                index,
+                widget_name(key_map, key),
+                site.type_name,
+                site.location,
+            ),
+            None => self.sites.push(site),
+        }
+    }
+
+    /// checks that the widget called all of its hooks, after it was evaluated
+    pub(crate) fn finish(&mut self, key_map: &KeyMap, key: Key, count: u16) {
+        if let Some(missing) = self.sites.get(count as usize) {
+            panic!(
+                "hook #{} of {} (`{}` created at {}) was not called in this evaluation. Hooks must \
+                 be called in the same order on every evaluation, they can not be called \
+                 conditionally.",
+                count,
+                widget_name(key_map, key),
+                missing.type_name,
+                missing.location,
+            );
+        }
+        self.complete = true;
+    }
+}
+
+fn widget_name(key_map: &KeyMap, key: Key) -> String {
+    match key_map.key_tail(key) {
+        KeyPart::Root => "the toplevel widget".to_string(),
+        KeyPart::Fragment { widget_id, .. }
+        | KeyPart::FragmentKey { widget_id, .. }
+        | KeyPart::FragmentInternedKey { widget_id, .. } => {
+            let info = &WIDGET_INFO.read()[widget_id as usize];
+            format!(
+                "widget `{}` (defined at {}, {:?})",
+                info.name,
+                info.loc,
+                key_map.key_debug(key)
+            )
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Carries the hook values of the widget tree over to new widget code, for
+//! the hot reloading enabled with the `hot_reload` feature.
+
+use super::patched_tree::{HookKey, TreeItem};
+use hashbrown::{HashMap, HashSet};
+use lazy_static::lazy_static;
+use std::any::TypeId;
+
+// hooks are identified by the debug path of their widget and their index
+pub(crate) type HookPath = (String, u16);
+
+// the types of the hook values that are carried over, with the layout of all
+// of them defined by the standard library
+macro_rules! carried_types {
+    ($($ty:ty),* $(,)?) => {
+        [$(
+            TypeId::of::<$ty>(),
+            TypeId::of::<Option<$ty>>(),
+            TypeId::of::<Vec<$ty>>(),
+        )*]
+    };
+}
+
+lazy_static! {
+    static ref CARRIED_TYPES: HashSet<TypeId> = carried_types![
+        (), bool, char, String, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize,
+        f32, f64,
+    ]
+    .iter()
+    .copied()
+    .collect();
+}
+
+/// the type of a hook value. The `TypeId` of a type defined by the widget code
+/// stays the same when its fields change, so its old values might not be valid
+/// for the new code. Only values of some types of the standard library, whose
+/// layout can not change, are carried over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct HookType {
+    id: TypeId,
+    name: &'static str,
+}
+
+impl HookType {
+    /// `None` for types whose values are not carried over
+    pub(crate) fn of<T: 'static>() -> Option<Self> {
+        let id = TypeId::of::<T>();
+        CARRIED_TYPES.contains(&id).then(|| Self { id, name: std::any::type_name::<T>() })
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct HotReloadState {
+    // the types given to `key_for_typed_hook` that are carried over, other hooks
+    // start over
+    hook_types: HashMap<HookKey, HookType>,
+    carried: HashMap<HookPath, (HookType, TreeItem)>,
+    dropped: Vec<TreeItem>,
+}
+
+impl HotReloadState {
+    pub(crate) fn record_type(&mut self, key: HookKey, hook_type: HookType) {
+        self.hook_types.insert(key, hook_type);
+    }
+
+    pub(crate) fn type_of(&self, key: HookKey) -> Option<HookType> {
+        self.hook_types.get(&key).copied()
+    }
+
+    pub(crate) fn forget_types(&mut self, keys: impl Iterator<Item = HookKey>) {
+        for key in keys {
+            self.hook_types.remove(&key);
+        }
+    }
+
+    pub(crate) fn carry(&mut self, path: HookPath, hook_type: HookType, value: TreeItem) {
+        self.carried.insert(path, (hook_type, value));
+    }
+
+    pub(crate) fn is_carrying(&self) -> bool { !self.carried.is_empty() }
+
+    /// the value carried over for the hook, if its type still matches
+    pub(crate) fn take(&mut self, path: &HookPath, hook_type: HookType) -> Option<TreeItem> {
+        match self.carried.remove(path) {
+            Some((old_type, value)) if old_type == hook_type => Some(value),
+            Some((old_type, value)) => {
+                log::info!(
+                    "dropping the state of {}:{}, its type changed from {} to {}",
+                    path.0,
+                    path.1,
+                    old_type.name,
+                    hook_type.name
+                );
+                // dropped in `finish`, as dropping it might need the tree
+                self.dropped.push(value);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// the values that were not picked up by the new code
+    pub(crate) fn finish(&mut self) -> Vec<TreeItem> {
+        let mut unused = std::mem::take(&mut self.dropped);
+        unused.extend(self.carried.drain().map(|(_, (_, value))| value));
+        unused
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HookType;
+    use crate::{
+        context::{
+            key::{KeyMap, KeyPart},
+            PatchedTree,
+        },
+        Key,
+    };
+
+    #[test]
+    fn carry_over() {
+        let widget = |key_map: &mut KeyMap, id| {
+            key_map.key_with(Key::ROOT, KeyPart::Fragment { widget_id: 0, location_id: 42 }, || {
+                Key(id)
+            })
+        };
+        let restore = |tree: &PatchedTree, key_map: &KeyMap, key, index, hook_type| {
+            tree.restore_hook((key, index), hook_type, || {
+                (format!("{:?}", key_map.key_debug(key)), index)
+            })
+        };
+        let value = |tree: &PatchedTree, key, index| {
+            let hook = tree.initialize_with((key, index), || Box::new(0u32));
+            *tree.get_unpatched(hook).unwrap().downcast_ref::<u32>().unwrap()
+        };
+
+        let tree = PatchedTree::default();
+        let mut key_map = KeyMap::default();
+        let key = widget(&mut key_map, 2);
+        let u32_type = HookType::of::<u32>().unwrap();
+        for index in 0..5 {
+            restore(&tree, &key_map, key, index, u32_type);
+            tree.initialize((key, index), Box::new(5u32));
+        }
+        // an untyped hook
+        tree.initialize((key, 5), Box::new(5u32));
+        tree.carry_over_widgets(&key_map);
+        assert!(tree.hooks_of(key).is_empty());
+
+        let mut key_map = KeyMap::default();
+        let key = widget(&mut key_map, 7);
+        restore(&tree, &key_map, key, 0, u32_type);
+        restore(&tree, &key_map, key, 1, HookType::of::<f32>().unwrap());
+        assert_eq!(value(&tree, key, 0), 5);
+        assert_eq!(value(&tree, key, 1), 0);
+        assert_eq!(value(&tree, key, 3), 0);
+        assert_eq!(value(&tree, key, 5), 0);
+        tree.finish_carry_over();
+        restore(&tree, &key_map, key, 2, u32_type);
+        assert_eq!(value(&tree, key, 2), 0);
+    }
+
+    #[test]
+    fn carried_types() {
+        // the fields might change with the next build
+        struct Position {
+            _x: u32,
+        }
+        assert!(HookType::of::<Position>().is_none());
+        assert!(HookType::of::<Option<Position>>().is_none());
+        assert!(HookType::of::<(u32, u32)>().is_none());
+        assert!(HookType::of::<Box<dyn Fn() + Send + Sync>>().is_none());
+        assert!(HookType::of::<Option<String>>().is_some());
+        assert!(HookType::of::<Vec<f32>>().is_some());
+        assert_ne!(HookType::of::<u32>(), HookType::of::<i32>());
+    }
+}
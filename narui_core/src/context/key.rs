@@ -1,10 +1,18 @@
+use crate::util::{arg_format::ArgFormatter, memory_stats::SlotStats};
+use derivative::Derivative;
 use freelist::{FreeList, Idx};
+use hashbrown::HashMap;
 use std::{
+    any::{Any, TypeId},
+    convert::TryFrom,
     fmt::{Debug, Formatter},
-    hash::Hash,
+    hash::{Hash, Hasher},
+    rc::Rc,
 };
 
 type KeyInner = u32;
+/// a `KeyPart` packed into a number, to quickly find a child by its tail
+pub(crate) type PackedKeyPart = i32;
 #[derive(Clone, Copy, PartialEq, Hash, Eq, Debug)]
 pub struct Key(pub(crate) KeyInner);
 
@@ -13,7 +21,10 @@ impl Default for Key {
 }
 
 impl Key {
-    const ROOT: Key = Key(1);
+    pub(crate) const ROOT: Key = Key(1);
+    // the FragmentStore never hands out index zero, so it is free to anchor the
+    // hooks of application level stores that do not belong to any widget
+    pub(crate) const STORE: Key = Key(0);
 }
 
 #[derive(Debug, Clone)]
@@ -22,7 +33,7 @@ struct KeyMapEntry {
     tail: KeyPart,
     parent: KeyInner,
     child_idx: Idx,
-    children_tails: Vec<i32>,
+    children_tails: Vec<PackedKeyPart>,
     children_keys: FreeList<KeyInner>,
     // last key that was accessed
     // especially for static widgets, the tails of the children should appear in order
@@ -43,20 +54,58 @@ impl KeyMapEntry {
     }
 }
 
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct KeyMap {
     keys: Vec<KeyMapEntry>,
+    // the values given to `key=` in rsx, interned per parent and call site
+    #[derivative(Debug = "ignore")]
+    key_sites: HashMap<(KeyInner, u16), KeySite>,
+}
+
+/// A value given to `key=` in rsx, with its type erased.
+trait AnyKey {
+    fn as_any(&self) -> &dyn Any;
+    fn eq_key(&self, other: &dyn AnyKey) -> bool;
+    fn hash_key(&self, state: &mut dyn Hasher);
+}
+
+impl<T: Hash + Eq + 'static> AnyKey for T {
+    fn as_any(&self) -> &dyn Any { self }
+    fn eq_key(&self, other: &dyn AnyKey) -> bool {
+        other.as_any().downcast_ref::<T>().map_or(false, |other| self == other)
+    }
+    fn hash_key(&self, mut state: &mut dyn Hasher) {
+        TypeId::of::<T>().hash(&mut state);
+        self.hash(&mut state);
+    }
+}
+
+impl PartialEq for dyn AnyKey {
+    fn eq(&self, other: &Self) -> bool { self.eq_key(other) }
+}
+impl Eq for dyn AnyKey {}
+impl Hash for dyn AnyKey {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.hash_key(state) }
+}
+
+#[derive(Default)]
+struct KeySite {
+    ids: HashMap<Rc<dyn AnyKey>, u32>,
+    // the values by their id, together with a formatter for their `Debug` representation
+    values: Vec<Option<(Rc<dyn AnyKey>, ArgFormatter)>>,
+    free: Vec<u32>,
 }
 
 #[cfg(target_arch = "x86_64")]
-fn find_avx2(data: &[i32], needle: i32) -> Option<usize> {
+fn find_avx2(data: &[PackedKeyPart], needle: PackedKeyPart) -> Option<usize> {
     use std::arch::x86_64::*;
     let trunc = (data.len() / 8) * 8;
 
     unsafe {
         let needle_wide = _mm256_set1_epi32(needle);
         for i in (0..trunc).step_by(8) {
-            let addr = data.get_unchecked(i) as *const i32 as *const __m256i;
+            let addr = data.get_unchecked(i) as *const PackedKeyPart as *const __m256i;
             let d = _mm256_loadu_si256(addr);
             let cmp = _mm256_cmpeq_epi32(needle_wide, d);
             let mask = _mm256_movemask_epi8(cmp);
@@ -98,6 +147,11 @@ impl KeyMap {
                     }
                 }
             };
+            // the packed tails of large keys are not unique
+            let idx = match idx {
+                Some(_) if KeyPart::is_folded(tail_packed) => self.find_folded(parent, tail),
+                idx => idx,
+            };
 
             if let Some(idx) = idx {
                 self.keys.get_unchecked_mut(parent.0 as usize).last = idx;
@@ -136,6 +190,71 @@ impl KeyMap {
             }
         }
     }
+    /// the id of a value given to `key=` in rsx, unique among the keys of the
+    /// children of `parent` created at `location_id`. The formatter is used
+    /// to show the value in the debug representation of the key.
+    pub fn intern_key<K: Hash + Eq + 'static>(
+        &mut self,
+        parent: Key,
+        location_id: u16,
+        key: K,
+        formatter: ArgFormatter,
+    ) -> u32 {
+        let site = self.key_sites.entry((parent.0, location_id)).or_default();
+        if let Some(id) = site.ids.get(&key as &dyn AnyKey) {
+            return *id;
+        }
+        let key: Rc<dyn AnyKey> = Rc::new(key);
+        let id = match site.free.pop() {
+            Some(id) => {
+                site.values[id as usize] = Some((key.clone(), formatter));
+                id
+            }
+            None => {
+                site.values.push(Some((key.clone(), formatter)));
+                u32::try_from(site.values.len() - 1)
+                    .expect("more than u32::MAX different keys were used at one place in rsx")
+            }
+        };
+        site.ids.insert(key, id);
+        id
+    }
+    /// like `key_with` finds a child, but compares the whole tail of every
+    /// child with a matching packed tail
+    fn find_folded(&self, parent: Key, tail: KeyPart) -> Option<usize> {
+        let entry = &self.keys[parent.0 as usize];
+        let packed = tail.pack();
+        (0..entry.children_tails.len()).find(|i| {
+            entry.children_tails[*i] == packed
+                && self.key_tail(Key(unsafe {
+                    *entry.children_keys.get_unchecked(Idx::new_unchecked(i + 1))
+                })) == tail
+        })
+    }
+    fn interned_key(&self, key: Key) -> Option<String> {
+        match self.key_tail(key) {
+            KeyPart::FragmentInternedKey { location_id, key: id, .. } => {
+                let site = self.key_sites.get(&(self.key_parent(key).0, location_id))?;
+                let (value, formatter) = site.values.get(id as usize)?.as_ref()?;
+                formatter((**value).as_any())
+            }
+            _ => None,
+        }
+    }
+    fn release_key(&mut self, key: Key) {
+        if let KeyPart::FragmentInternedKey { location_id, key: id, .. } = self.key_tail(key) {
+            let site_key = (self.key_parent(key).0, location_id);
+            if let Some(site) = self.key_sites.get_mut(&site_key) {
+                if let Some((value, _)) = site.values.get_mut(id as usize).and_then(Option::take) {
+                    site.ids.remove(&value);
+                    site.free.push(id);
+                }
+                if site.ids.is_empty() {
+                    self.key_sites.remove(&site_key);
+                }
+            }
+        }
+    }
     pub fn key_parent(&self, key: Key) -> Key { Key(self.keys[key.0 as usize].parent) }
     pub fn key_tail(&self, key: Key) -> KeyPart { self.keys[key.0 as usize].tail }
     pub fn key_debug(&self, key: Key) -> DebuggableKey { DebuggableKey { key, key_map: self } }
@@ -148,14 +267,40 @@ impl KeyMap {
         }
         parts
     }
+    /// the keys that were created with key as their parent
+    pub(crate) fn children(&self, key: Key) -> Vec<Key> {
+        let entry = &self.keys[key.0 as usize];
+        if entry.deleted {
+            return vec![];
+        }
+        entry
+            .children_tails
+            .iter()
+            .enumerate()
+            .filter(|(_, tail)| **tail != KeyPart::Root.pack())
+            .map(|(i, _)| unsafe {
+                Key(*entry.children_keys.get_unchecked(Idx::new_unchecked(i + 1)))
+            })
+            .collect()
+    }
+    /// the key that was created with the given parent and packed tail, without
+    /// creating it if it does not exist. Of the children whose large keys fold
+    /// to the same packed tail, it is the first one.
+    pub(crate) fn find_child(&self, parent: Key, tail: PackedKeyPart) -> Option<Key> {
+        let entry = self.keys.get(parent.0 as usize).filter(|entry| !entry.deleted)?;
+        let idx = entry.children_tails.iter().position(|v| *v == tail && tail != 0)?;
+        Some(Key(unsafe { *entry.children_keys.get_unchecked(Idx::new_unchecked(idx + 1)) }))
+    }
     pub fn remove(&mut self, key: &Key) {
         // println!("removing {:?}", key);
+        self.release_key(*key);
         self.keys[key.0 as usize].children_keys = Default::default();
         self.keys[key.0 as usize].children_tails = Default::default();
         self.keys[key.0 as usize].deleted = true;
 
         let parent = self.keys[key.0 as usize].parent;
-        if !self.keys[parent as usize].deleted {
+        // the parent might be gone after compacting, if it was removed first
+        if self.keys.get(parent as usize).map_or(false, |parent| !parent.deleted) {
             let child_idx = self.keys[key.0 as usize].child_idx;
             // println!("removing myself from parent = {} as idx = {}", parent, child_idx);
             self.keys[parent as usize].children_keys.remove(child_idx);
@@ -167,6 +312,36 @@ impl KeyMap {
         // v.children.used_space()).sum(); println!("keys.len() = {},
         // sum = {}", self.keys.len(), sum);
     }
+    /// gives back the memory of removed keys. The ids of removed keys are
+    /// reused anyways, as they are the indices of the fragments in the
+    /// `FragmentStore`.
+    pub(crate) fn compact(&mut self) {
+        while self.keys.len() > 2 && self.keys.last().map_or(false, |entry| entry.deleted) {
+            self.keys.pop();
+        }
+        self.keys.shrink_to_fit();
+        for entry in self.keys.iter_mut().filter(|entry| !entry.deleted) {
+            entry.children_keys.shrink();
+            // the tail of the child in the slot i of children_keys is at i - 1
+            entry.children_tails.truncate(entry.children_keys.used_space() - 1);
+            entry.children_tails.shrink_to_fit();
+        }
+        for site in self.key_sites.values_mut() {
+            while let Some(None) = site.values.last() {
+                site.values.pop();
+            }
+            let len = site.values.len();
+            site.free.retain(|id| (*id as usize) < len);
+            site.values.shrink_to_fit();
+            site.free.shrink_to_fit();
+            site.ids.shrink_to_fit();
+        }
+        self.key_sites.shrink_to_fit();
+    }
+    pub(crate) fn memory_stats(&self) -> SlotStats {
+        let live = self.keys.iter().filter(|entry| !entry.deleted).count();
+        SlotStats { live, capacity: self.keys.capacity() }
+    }
 }
 
 impl Default for KeyMap {
@@ -174,7 +349,7 @@ impl Default for KeyMap {
         let mut keys = vec![KeyMapEntry::deleted(), KeyMapEntry::deleted()];
         keys[Key::ROOT.0 as usize].deleted = false;
 
-        Self { keys }
+        Self { keys, key_sites: Default::default() }
     }
 }
 pub struct DebuggableKey<'a> {
@@ -183,11 +358,24 @@ pub struct DebuggableKey<'a> {
 }
 impl<'a> Debug for DebuggableKey<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for (i, part) in self.key_map.get_parts(self.key).iter().rev().enumerate() {
+        let mut path = vec![];
+        let mut current = self.key;
+        while current != Key::ROOT {
+            path.push(current);
+            current = self.key_map.key_parent(current);
+        }
+        for (i, key) in path.into_iter().rev().enumerate() {
             if i != 0 {
                 write!(f, ".")?;
             }
-            write!(f, "{:?}", part)?;
+            // keyed widgets are shown with the value of their key instead of its id
+            match (self.key_map.key_tail(key), self.key_map.interned_key(key)) {
+                (KeyPart::FragmentInternedKey { widget_id, location_id, .. }, Some(value)) => {
+                    let name = internal::name_for_widget(widget_id);
+                    write!(f, "{}<{}>@{}", name, value, format_location_id(location_id))?
+                }
+                (part, _) => write!(f, "{:?}", part)?,
+            }
         }
         Ok(())
     }
@@ -198,9 +386,18 @@ pub enum KeyPart {
     Root,
 
     Fragment { widget_id: u16, location_id: u16 },
-    FragmentKey { widget_id: u16, location_id: u16, key: u16 },
+    // keyed by an integer
+    FragmentKey { widget_id: u16, location_id: u16, key: u32 },
+    // keyed by any other value, with the id `KeyMap::intern_key` gave it
+    FragmentInternedKey { widget_id: u16, location_id: u16, key: u32 },
 }
 
+// the bits of packed keys above the location: keys below EXACT_KEYS are packed
+// as they are, larger keys are folded into that range and marked as FOLDED
+const FOLDED: u32 = 1 << 15;
+const INTERNED: u32 = 1 << 14;
+const EXACT_KEYS: u32 = (1 << 14) - 1;
+
 fn format_location_id(location_id: u16) -> String {
     let column = (location_id & 0b1_1111) * 4;
     let line = location_id >> 5;
@@ -208,21 +405,29 @@ fn format_location_id(location_id: u16) -> String {
 }
 
 impl KeyPart {
-    pub(crate) fn pack(self) -> i32 {
-        unsafe {
-            match self {
-                // location_id can never be zero, as that would mean we are within 4 chars of the
-                // #[widget] thing
-                KeyPart::Root => 0,
-                KeyPart::Fragment { location_id, .. } => {
-                    std::mem::transmute(0b1000_0000_0000_0000u32 | (location_id as u32))
-                }
-                KeyPart::FragmentKey { location_id, key, .. } => std::mem::transmute(
-                    0b0111_1111_1111_1111u32 | ((location_id >> 1) as u32) | ((key as u32) << 15),
-                ),
+    pub(crate) fn pack(self) -> PackedKeyPart {
+        match self {
+            // location_id can never be zero, as that would mean we are within 4 chars of the
+            // #[widget] thing
+            KeyPart::Root => 0,
+            KeyPart::Fragment { location_id, .. } => {
+                (0b1000_0000_0000_0000 | location_id as u32) as PackedKeyPart
+            }
+            KeyPart::FragmentKey { location_id, key, .. } => Self::pack_key(location_id, key, 0),
+            KeyPart::FragmentInternedKey { location_id, key, .. } => {
+                Self::pack_key(location_id, key, INTERNED)
             }
         }
     }
+
+    // the bits above the location are never zero, so these never collide with the
+    // other parts
+    fn pack_key(location_id: u16, key: u32, kind: u32) -> PackedKeyPart {
+        let key = if key < EXACT_KEYS { key + 1 } else { FOLDED | (key % EXACT_KEYS + 1) };
+        ((kind | key) << 16 | location_id as u32) as PackedKeyPart
+    }
+
+    fn is_folded(packed: PackedKeyPart) -> bool { packed as u32 & FOLDED << 16 != 0 }
 }
 
 impl Debug for KeyPart {
@@ -237,6 +442,10 @@ impl Debug for KeyPart {
                 let name = internal::name_for_widget(*widget_id);
                 write!(f, "{}<{}>@{}", name, key, format_location_id(*location_id))
             }
+            KeyPart::FragmentInternedKey { widget_id, location_id, key } => {
+                let name = internal::name_for_widget(*widget_id);
+                write!(f, "{}<#{}>@{}", name, key, format_location_id(*location_id))
+            }
         }
     }
 }
@@ -268,3 +477,94 @@ pub(crate) mod internal {
         ]);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // what rsx generates for `key=value`, at the call site with the location 3
+    macro_rules! part {
+        ($key_map:expr, $value:expr) => {{
+            let value = $value;
+            match crate::_macro_api::direct_key!(&value) {
+                Some(key) => KeyPart::FragmentKey { widget_id: 0, location_id: 3, key },
+                None => {
+                    let formatter = crate::_macro_api::arg_formatter!(&value);
+                    let key = $key_map.intern_key(Key::ROOT, 3, value, formatter);
+                    KeyPart::FragmentInternedKey { widget_id: 0, location_id: 3, key }
+                }
+            }
+        }};
+    }
+
+    fn child(key_map: &mut KeyMap, id: u32, part: &dyn Fn(&mut KeyMap) -> KeyPart) -> Key {
+        let part = part(key_map);
+        key_map.key_with(Key::ROOT, part, || Key(id))
+    }
+
+    #[test]
+    fn interned_keys() {
+        let mut key_map = KeyMap::default();
+        let a = child(&mut key_map, 1, &|m| part!(m, "a".to_string()));
+        let b = child(&mut key_map, 2, &|m| part!(m, (100_000u64, 'b')));
+        let c = child(&mut key_map, 3, &|m| part!(m, (1u8, 'c')));
+        assert_eq!(child(&mut key_map, 4, &|m| part!(m, "a".to_string())), a);
+        assert_ne!(a, b);
+        assert_ne!(b, c);
+        assert_eq!(format!("{:?}", key_map.key_debug(b)), "toplevel<(100000, 'b')>@0:12");
+
+        key_map.remove(&b);
+        let d = child(&mut key_map, 5, &|m| part!(m, (7i64, 'd')));
+        assert_ne!(d, a);
+
+        // keys don't need to implement Debug
+        #[derive(PartialEq, Eq, Hash)]
+        struct Opaque;
+        let e = child(&mut key_map, 6, &|m| part!(m, Opaque));
+        let id = match part!(key_map, Opaque) {
+            KeyPart::FragmentInternedKey { key, .. } => key,
+            _ => unreachable!(),
+        };
+        assert_eq!(format!("{:?}", key_map.key_debug(e)), format!("toplevel<#{}>@0:12", id));
+
+        // more keys at one place than fit into a u16
+        let ids: Vec<_> = (0..70_000u32).map(|i| part!(key_map, i.to_string())).collect();
+        assert_eq!(ids.iter().collect::<hashbrown::HashSet<_>>().len(), ids.len());
+
+        // more keys than are packed without folding
+        let keys: Vec<_> = (0..20_000u32)
+            .map(|i| child(&mut key_map, 10 + i, &|m| part!(m, i.to_string())))
+            .collect();
+        assert_eq!(keys.iter().collect::<hashbrown::HashSet<_>>().len(), keys.len());
+        for i in [0, 16_382, 16_383, 19_999] {
+            assert_eq!(child(&mut key_map, 0, &|m| part!(m, i.to_string())), keys[i as usize]);
+        }
+    }
+
+    #[test]
+    fn integer_keys() {
+        let mut key_map = KeyMap::default();
+        assert!(matches!(part!(key_map, 7u8), KeyPart::FragmentKey { key: 7, .. }));
+        assert!(matches!(part!(key_map, u32::MAX as u64), KeyPart::FragmentKey { .. }));
+        // integers that do not fit are interned
+        assert!(matches!(part!(key_map, -1i64), KeyPart::FragmentInternedKey { .. }));
+        assert!(matches!(part!(key_map, u64::MAX), KeyPart::FragmentInternedKey { .. }));
+
+        let a = child(&mut key_map, 2, &|m| part!(m, 5u64));
+        let b = child(&mut key_map, 3, &|m| part!(m, 1u64 << 40));
+        assert_ne!(a, b);
+        assert_eq!(format!("{:?}", key_map.key_debug(a)), "toplevel<5>@0:12");
+        assert_eq!(format!("{:?}", key_map.key_debug(b)), "toplevel<1099511627776>@0:12");
+
+        // large keys whose packed tails collide
+        let c = child(&mut key_map, 4, &|m| part!(m, 20_000u32));
+        let d = child(&mut key_map, 5, &|m| part!(m, 20_000u32 + EXACT_KEYS));
+        assert_eq!(KeyPart::FragmentKey { widget_id: 0, location_id: 3, key: 20_000 }.pack(), {
+            KeyPart::FragmentKey { widget_id: 0, location_id: 3, key: 20_000 + EXACT_KEYS }.pack()
+        });
+        assert_ne!(c, d);
+        assert_eq!(child(&mut key_map, 0, &|m| part!(m, 20_000u32 + EXACT_KEYS)), d);
+        assert_eq!(child(&mut key_map, 0, &|m| part!(m, 20_000u32)), c);
+        assert_eq!(child(&mut key_map, 0, &|m| part!(m, 5u64)), a);
+    }
+}
@@ -1,8 +1,16 @@
 pub(crate) mod args;
 pub(crate) mod context;
+#[cfg(debug_assertions)]
+pub(crate) mod hook_sites;
+#[cfg(feature = "hot_reload")]
+pub(crate) mod hot_reload;
 pub(crate) mod key;
 pub(crate) mod patched_tree;
+#[cfg(feature = "persistence")]
+pub(crate) mod persistence;
+pub(crate) mod scheduler;
+pub(crate) mod undo;
 
-pub use context::{CallbackContext, ThreadContext, WidgetContext};
+pub use context::{CallbackContext, Stores, ThreadContext, WidgetContext};
 pub use key::Key;
 pub use patched_tree::{HookRef, PatchTreeEntry, PatchedTree};
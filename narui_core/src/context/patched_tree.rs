@@ -1,136 +1,722 @@
-use super::key::KeyMap;
-use crate::{Fragment, Key};
-use dashmap::DashMap;
-use freelist::{FreeList, Idx};
+#[cfg(feature = "hot_reload")]
+use super::hot_reload::{HookPath, HookType, HotReloadState};
+#[cfg(feature = "persistence")]
+use super::persistence::{serialize_item, PersistentState};
+use super::{
+    key::KeyMap,
+    undo::{Cloner, UndoHistory},
+};
+use crate::{
+    hooks::{can_redo_listenable, can_undo_listenable},
+    util::memory_stats::SlotStats,
+    Fragment,
+    Key,
+    ListenableShout,
+};
+use derivative::Derivative;
+use freelist::{GenerationalFreeList, Idx};
 use hashbrown::HashMap;
-use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
-use std::{any::Any, ops::Deref};
+use parking_lot::{MappedRwLockReadGuard, Mutex, RwLock, RwLockReadGuard};
+use std::{
+    any::Any,
+    ops::Deref,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
 
-type Dependents = tinyset::Set64<usize>;
 pub type TreeItem = Box<dyn Any + Send + Sync>;
 
+// a value shouted at a hook, which is applied by the next update_tree
 #[derive(Debug)]
-struct Patch<T> {
+struct Patch {
     key: HookKey,
-    value: T,
+    value: TreeItem,
+    // set by undo and redo, so the change is not recorded again
+    from_history: bool,
 }
 
-// TODO(robin): investigate evmap instead
-type FxDashMap<K, V> = DashMap<K, V, ahash::RandomState>;
+// The patches are double buffered: shouts swap the patch of a hook while
+// holding the read lock of the tree, so they never wait for the evaluation,
+// which only reads too. update_tree takes the write lock once per frame and
+// moves all patches into the values.
+#[derive(Debug)]
+struct Hook {
+    // only touched by the evaluation. Behind their own lock, so listening only
+    // needs the read lock of the tree. The fragments are packed with their
+    // generation in the upper half
+    dependents: Mutex<tinyset::Set64<u64>>,
+    value: TreeItem,
+    // the latest patch since the last update_tree, or null. Replaced patches are
+    // kept until the next update_tree, as readers holding the read lock might
+    // still look at them
+    patch: AtomicPtr<Patch>,
+}
+
+impl Hook {
+    fn new(value: TreeItem) -> Self {
+        Self { dependents: Default::default(), value, patch: AtomicPtr::new(ptr::null_mut()) }
+    }
+
+    fn take_patch(&mut self) -> Option<Box<Patch>> {
+        let patch = std::mem::replace(self.patch.get_mut(), ptr::null_mut());
+        // SAFETY: patches are only created by set_patch, from a box. `&mut self` means
+        // the write lock of the tree is held (or the hook was taken out of it), so no
+        // reader can still look at the patch, and swapping in null hands it out once.
+        (!patch.is_null()).then(|| unsafe { Box::from_raw(patch) })
+    }
+
+    #[cfg(feature = "hot_reload")]
+    fn take_value(mut self) -> TreeItem { std::mem::replace(&mut self.value, Box::new(())) }
+}
+
+impl Drop for Hook {
+    fn drop(&mut self) { self.take_patch(); }
+}
+
+// a patch that was replaced while readers might still look at it
+#[derive(Debug)]
+struct Replaced(*mut Patch);
+// SAFETY: a `Replaced` owns its patch: the pointer was swapped out of its hook,
+// so it is not reachable from the tree anymore and nothing else frees it. It is
+// only dereferenced to drop the patch, by update_tree while it holds the write
+// lock, so no reader of the old patch is left. `Patch` itself is `Send`.
+unsafe impl Send for Replaced {}
 
 // 15 bits of idx + top bit set if external
 pub type HookKey = (Key, u16);
-pub type HookRef = (HookKey, Idx);
+// the slot of the hook and its generation, so a `HookRef` that outlives its
+// hook, for example in a thread of a removed widget, does not reach the hook
+// that reuses the slot
+pub type HookRef = (HookKey, Idx, u32);
 
-#[derive(Debug, Default)]
-pub struct PatchedTree {
-    data: RwLock<FreeList<(Dependents, TreeItem)>>,
-    key_to_idx: RwLock<HashMap<Key, HashMap<u16, Idx>>>,
-    patch: FxDashMap<Idx, Patch<TreeItem>>,
-}
+// the slots of the hooks of a widget by their index, with their generation, so
+// looking up a hook only needs the lock of `key_to_idx`
+type WidgetHooks = HashMap<u16, (Idx, u32)>;
 
-type DataRef<'a> = MappedRwLockReadGuard<'a, (Dependents, TreeItem)>;
-type HashPatchRef<'a> = dashmap::mapref::one::Ref<'a, Idx, Patch<TreeItem>, ahash::RandomState>;
+pub type Waker = Box<dyn Fn() + Send + Sync>;
+// sees every shout from a `ThreadContext` and decides whether it is applied
+pub(crate) type ThreadShoutHook =
+    Box<dyn Fn(HookRef, &(dyn Any + Send + Sync)) -> bool + Send + Sync>;
 
-pub struct PatchTreeEntry<'a> {
-    patched_entry: Option<HashPatchRef<'a>>,
-    unpatched_entry: Option<DataRef<'a>>,
+#[derive(Derivative, Default)]
+#[derivative(Debug)]
+pub struct PatchedTree {
+    data: RwLock<GenerationalFreeList<Hook>>,
+    key_to_idx: RwLock<HashMap<Key, WidgetHooks>>,
+    // the hooks that were patched since the last update_tree
+    patched: Mutex<Vec<Idx>>,
+    // patches that were replaced by newer ones, dropped by the next update_tree
+    replaced: Mutex<Vec<Replaced>>,
+    #[derivative(Debug = "ignore")]
+    waker: RwLock<Option<Waker>>,
+    // set from the first patch after update_tree until the next update_tree, so a
+    // burst of shouts only wakes the event loop once
+    wake_pending: AtomicBool,
+    // used for recording and replaying shouts
+    #[derivative(Debug = "ignore")]
+    thread_shout_hook: RwLock<Option<ThreadShoutHook>>,
+    // lets shouts skip locking `thread_shout_hook` while nothing is recorded or
+    // replayed
+    has_thread_shout_hook: AtomicBool,
+    #[derivative(Debug = "ignore")]
+    undo: Mutex<UndoHistory>,
+    #[cfg(feature = "persistence")]
+    #[derivative(Debug = "ignore")]
+    persistent: Mutex<Option<PersistentState>>,
+    #[cfg(feature = "hot_reload")]
+    #[derivative(Debug = "ignore")]
+    hot_reload: Mutex<HotReloadState>,
 }
 
-impl<'a> PatchTreeEntry<'a> {
-    fn new(patched_entry: Option<HashPatchRef<'a>>, unpatched_entry: Option<DataRef<'a>>) -> Self {
-        Self { patched_entry, unpatched_entry }
-    }
-}
+pub struct PatchTreeEntry<'a>(MappedRwLockReadGuard<'a, TreeItem>);
 
 impl<'a> Deref for PatchTreeEntry<'a> {
     type Target = TreeItem;
 
-    fn deref(&self) -> &Self::Target {
-        match &self.patched_entry {
-            Some(p) => &p.value().value,
-            None => match &self.unpatched_entry {
-                Some(v) => &(*v).1,
-                None => unreachable!(),
-            },
-        }
-    }
+    fn deref(&self) -> &Self::Target { &self.0 }
 }
 
 impl PatchedTree {
-    pub fn get_patched(&self, idx: HookRef) -> PatchTreeEntry {
-        match self.patch.get(&idx.1) {
-            None => self.get_unpatched(idx),
-            Some(patch) => PatchTreeEntry::new(Some(patch), None),
-        }
+    /// `None` if the hook was removed
+    pub fn get_patched(&self, idx: HookRef) -> Option<PatchTreeEntry> {
+        let data = self.data.read();
+        data.get(idx.1, idx.2)?;
+        Some(PatchTreeEntry(RwLockReadGuard::map(data, |data| {
+            let hook = &data[idx.1];
+            let patch = hook.patch.load(Ordering::Acquire);
+            if patch.is_null() {
+                &hook.value
+            } else {
+                // SAFETY: the patch was created from a box by set_patch and is only freed
+                // while the write lock is held: by take_patch, or by update_tree when it
+                // was replaced in the meantime (replacing only moves it to `replaced`).
+                // The entry keeps holding the read lock, so the patch outlives it.
+                unsafe { &(*patch).value }
+            }
+        })))
     }
 
-    pub fn get_unpatched(&self, idx: HookRef) -> PatchTreeEntry {
-        PatchTreeEntry::new(None, Some(RwLockReadGuard::map(self.data.read(), |v| &v[idx.1])))
+    /// `None` if the hook was removed
+    pub fn get_unpatched(&self, idx: HookRef) -> Option<PatchTreeEntry> {
+        let data = self.data.read();
+        data.get(idx.1, idx.2)?;
+        Some(PatchTreeEntry(RwLockReadGuard::map(data, |data| &data[idx.1].value)))
     }
 
-    pub fn remove_patch(&self, idx: HookRef) { self.patch.remove(&idx.1); }
+    pub fn remove_patch(&self, idx: HookRef) {
+        let data = self.data.read();
+        if let Some(hook) = data.get(idx.1, idx.2) {
+            let old = hook.patch.swap(ptr::null_mut(), Ordering::AcqRel);
+            if !old.is_null() {
+                self.replaced.lock().push(Replaced(old));
+            }
+        }
+    }
 
     pub fn initialize(&self, key: HookKey, value: TreeItem) -> HookRef {
-        (
-            key,
-            *self
-                .key_to_idx
-                .write()
-                .entry(key.0)
-                .or_default()
-                .entry(key.1)
-                .or_insert_with(|| self.data.write().add((Default::default(), value))),
-        )
+        self.initialize_with(key, || value)
     }
 
     pub fn initialize_with(&self, key: HookKey, gen: impl FnOnce() -> TreeItem) -> HookRef {
-        (
-            key,
-            *self
-                .key_to_idx
-                .write()
-                .entry(key.0)
-                .or_default()
-                .entry(key.1)
-                .or_insert_with(|| self.data.write().add((Default::default(), gen()))),
-        )
+        // most hooks already exist, which only needs the read lock
+        {
+            let key_to_idx = self.key_to_idx.read();
+            if let Some((idx, generation)) =
+                key_to_idx.get(&key.0).and_then(|indices| indices.get(&key.1))
+            {
+                return (key, *idx, *generation);
+            }
+        }
+        let mut key_to_idx = self.key_to_idx.write();
+        let mut data = self.data.write();
+        let (idx, generation) =
+            *key_to_idx.entry(key.0).or_default().entry(key.1).or_insert_with(|| {
+                let idx = data.add(Hook::new(gen()));
+                (idx, data.generation(idx))
+            });
+        (key, idx, generation)
+    }
+
+    pub fn set(&self, idx: HookRef, value: TreeItem) { self.set_patch(idx, value, false) }
+
+    fn set_patch(&self, idx: HookRef, value: TreeItem, from_history: bool) {
+        {
+            let data = self.data.read();
+            let hook = match data.get(idx.1, idx.2) {
+                Some(hook) => hook,
+                None => {
+                    log::debug!("ignoring a shout at the removed hook {:?}", idx);
+                    return;
+                }
+            };
+            let patch = Box::into_raw(Box::new(Patch { key: idx.0, value, from_history }));
+            let old = hook.patch.swap(patch, Ordering::AcqRel);
+            if old.is_null() {
+                self.patched.lock().push(idx.1);
+            } else {
+                self.replaced.lock().push(Replaced(old));
+            }
+        }
+        // checked before swapping, so shouts from many threads only read the flag
+        if !self.wake_pending.load(Ordering::Acquire)
+            && !self.wake_pending.swap(true, Ordering::AcqRel)
+        {
+            if let Some(waker) = &*self.waker.read() {
+                waker()
+            }
+        }
     }
 
-    pub fn set(&self, idx: HookRef, value: TreeItem) {
-        self.patch.insert(idx.1, Patch { value, key: idx.0 });
+    /// the waker gets called when the tree is patched after the last
+    /// `update_tree`, i.e. when there is new work for the event loop
+    pub fn set_waker(&self, waker: impl Fn() + Send + Sync + 'static) {
+        *self.waker.write() = Some(Box::new(waker));
     }
 
-    pub fn set_unconditional(&self, idx: Idx, value: TreeItem) { self.data.write()[idx].1 = value; }
+    /// calls the waker, regardless of whether the tree was patched
+    #[cfg(feature = "hot_reload")]
+    pub(crate) fn wake(&self) {
+        if let Some(waker) = &*self.waker.read() {
+            waker()
+        }
+    }
+
+    pub(crate) fn set_thread_shout_hook(&self, hook: Option<ThreadShoutHook>) {
+        let mut thread_shout_hook = self.thread_shout_hook.write();
+        self.has_thread_shout_hook.store(hook.is_some(), Ordering::Release);
+        *thread_shout_hook = hook;
+    }
+
+    /// whether a shout from a `ThreadContext` should be applied
+    pub(crate) fn thread_shout(&self, key: HookRef, value: &(dyn Any + Send + Sync)) -> bool {
+        if !self.has_thread_shout_hook.load(Ordering::Acquire) {
+            return true;
+        }
+        match &*self.thread_shout_hook.read() {
+            Some(hook) => hook(key, value),
+            None => true,
+        }
+    }
+
+    pub fn is_patched(&self) -> bool { !self.patched.lock().is_empty() }
+
+    pub fn set_unconditional(&self, idx: HookRef, value: TreeItem) {
+        // the old value is dropped after the lock is released, as its drop might want
+        // to access the tree (for example joining a thread that is shouting)
+        let old = match self.data.write().get_mut(idx.1, idx.2) {
+            Some(hook) => std::mem::replace(&mut hook.value, value),
+            None => {
+                log::debug!("ignoring a shout at the removed hook {:?}", idx);
+                value
+            }
+        };
+        std::mem::drop(old);
+    }
+
+    /// gives back the memory of removed hooks. Their `HookRef`s stay detected as
+    /// removed, as the generations of the dropped slots are not handed out again.
+    pub(crate) fn compact(&self) {
+        self.data.write().shrink();
+        self.key_to_idx.write().shrink_to_fit();
+        self.patched.lock().shrink_to_fit();
+        self.replaced.lock().shrink_to_fit();
+    }
+
+    pub(crate) fn memory_stats(&self) -> SlotStats {
+        let data = self.data.read();
+        SlotStats { live: data.len(), capacity: data.capacity() }
+    }
 
     pub fn remove_widget(&self, key: &Key) {
-        if let Some(indices) = self.key_to_idx.write().remove(key) {
-            for idx in indices.values() {
-                self.data.write().remove(*idx);
+        let indices = self.key_to_idx.write().remove(key);
+        if let Some(indices) = indices {
+            let indices: HashMap<_, _> =
+                indices.into_iter().map(|(index, (idx, _))| (index, idx)).collect();
+            self.undo.lock().forget(*key, indices.values().copied());
+            #[cfg(feature = "persistence")]
+            if let Some(state) = &mut *self.persistent.lock() {
+                state.forget(indices.values().copied());
             }
+            #[cfg(feature = "hot_reload")]
+            self.hot_reload.lock().forget_types(indices.keys().map(|index| (*key, *index)));
+            // drop the hook values right away instead of when their slot gets reused, so
+            // cleanup of effects, threads and futures happens when the widget goes away.
+            self.drop_hooks(indices);
+        }
+    }
+
+    // Takes out and drops the hooks one by one, starting with the last one. A
+    // thread, effect or future that is stopped by the drop might still use the
+    // listenables created before it until it is joined, and those are still
+    // there.
+    fn drop_hooks(&self, hooks: impl IntoIterator<Item = (u16, Idx)>) {
+        let mut hooks: Vec<_> = hooks.into_iter().collect();
+        hooks.sort_unstable_by_key(|(index, _)| std::cmp::Reverse(*index));
+        for (_, idx) in hooks {
+            // dropped after the lock is released
+            std::mem::drop(self.take(idx));
         }
     }
 
-    // apply the patch to the tree starting a new frame
+    // apply the patches to the tree starting a new frame
     pub fn update_tree<'a>(&'a self, _key_map: &mut KeyMap) -> impl Iterator<Item = HookRef> + 'a {
-        let mut keys = vec![];
-        for kv in self.patch.iter() {
-            keys.push(*kv.key());
+        // cleared before taking the patches, so a patch that misses this update
+        // wakes the event loop again
+        self.wake_pending.store(false, Ordering::Release);
+        // the old values are dropped after the locks are released, as their drop might
+        // want to access the tree
+        let mut old_values = vec![];
+        let touched: Vec<_> = {
+            let key_to_idx = self.key_to_idx.read();
+            let mut data = self.data.write();
+            for Replaced(patch) in self.replaced.lock().drain(..) {
+                // SAFETY: see `Replaced`, nobody can look at it while the write lock is held
+                old_values.push(unsafe { Box::from_raw(patch) }.value);
+            }
+            let patched = std::mem::take(&mut *self.patched.lock());
+            patched
+                .into_iter()
+                .filter_map(|idx| {
+                    // a thread might still shout at the hooks of a removed widget, whose slots
+                    // might be reused or gone after compacting
+                    if idx.get() >= data.used_space() {
+                        return None;
+                    }
+                    let generation = data.generation(idx);
+                    let hook = &mut data[idx];
+                    let Patch { key, value, from_history } = *hook.take_patch()?;
+                    let live = key_to_idx.get(&key.0).and_then(|indices| indices.get(&key.1));
+                    if live.map(|(idx, _)| idx) != Some(&idx) {
+                        old_values.push(value);
+                        return None;
+                    }
+                    let cloner = if from_history { None } else { self.undo.lock().cloner(idx) };
+                    if let Some(clone) = cloner {
+                        let old = clone(&*hook.value);
+                        let new = clone(&*value);
+                        self.undo.lock().record((key, idx, generation), old, new, clone);
+                    }
+                    #[cfg(feature = "persistence")]
+                    if let Some(state) = &mut *self.persistent.lock() {
+                        state.changed(idx, &value);
+                    }
+                    old_values.push(std::mem::replace(&mut hook.value, value));
+
+                    Some((key, idx, generation))
+                })
+                .collect()
+        };
+        std::mem::drop(old_values);
+        self.undo.lock().finish_update();
+        self.publish_undo_state();
+        touched.into_iter()
+    }
+
+    /// loads the persisted listenables from `path` and saves them there
+    #[cfg(feature = "persistence")]
+    pub(crate) fn set_state_file(&self, path: std::path::PathBuf) {
+        *self.persistent.lock() = Some(PersistentState::load(path));
+    }
+
+    /// like `initialize_with`, but the value is loaded from the state file and
+    /// saved there whenever it changes. `name` identifies the value in the
+    /// file.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn initialize_persistent<T>(
+        &self,
+        key: HookKey,
+        name: impl Fn() -> String,
+        default: T,
+    ) -> HookRef
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync + 'static,
+    {
+        let hook = self.initialize_with(key, || {
+            let value = match &*self.persistent.lock() {
+                Some(state) => state.get(&name()).unwrap_or(default),
+                None => {
+                    log::warn!("{} is not persisted, as no state file was set", name());
+                    default
+                }
+            };
+            Box::new(value)
+        });
+        // checked on every call, as the value might have been carried over by a hot
+        // reload
+        if let Some(state) = &mut *self.persistent.lock() {
+            if !state.is_persisted(hook.1) {
+                state.persist(hook.1, name(), serialize_item::<T>);
+            }
+        }
+        hook
+    }
+
+    /// remembers the type of a hook and restores its value from before a hot
+    /// reload, if there is one
+    #[cfg(feature = "hot_reload")]
+    pub(crate) fn restore_hook(
+        &self,
+        key: HookKey,
+        hook_type: HookType,
+        path: impl FnOnce() -> HookPath,
+    ) {
+        let value = {
+            let mut state = self.hot_reload.lock();
+            state.record_type(key, hook_type);
+            if !state.is_carrying() {
+                return;
+            }
+            state.take(&path(), hook_type)
+        };
+        if let Some(value) = value {
+            self.initialize(key, value);
         }
+    }
 
-        keys.into_iter().map(move |idx| {
-            let (idx, Patch { value, key }) = self.patch.remove(&idx).unwrap();
-            self.set_unconditional(idx, value);
+    /// removes the hooks of all widgets and keeps the values of typed hooks, so
+    /// the widgets of reloaded code can pick them up again. Stores are kept.
+    #[cfg(feature = "hot_reload")]
+    pub(crate) fn carry_over_widgets(&self, key_map: &KeyMap) {
+        let widgets: Vec<_> = {
+            let mut key_to_idx = self.key_to_idx.write();
+            let keys: Vec<_> =
+                key_to_idx.keys().copied().filter(|key| *key != Key::STORE).collect();
+            keys.into_iter()
+                .map(|key| {
+                    let indices = key_to_idx.remove(&key).unwrap();
+                    (key, indices.into_iter().map(|(index, (idx, _))| (index, idx)).collect())
+                })
+                .collect::<Vec<(_, HashMap<_, _>)>>()
+        };
 
-            (key, idx)
-        })
+        let stores: Vec<_> = match self.key_to_idx.read().get(&Key::STORE) {
+            Some(indices) => indices.values().map(|(idx, _)| *idx).collect(),
+            None => vec![],
+        };
+        // the hooks that are not carried over are dropped first, while the values they
+        // might use are still there
+        let (carried, removed): (Vec<_>, Vec<_>) = {
+            let state = self.hot_reload.lock();
+            widgets
+                .iter()
+                .flat_map(|(key, indices)| {
+                    indices.iter().map(move |(index, idx)| (*key, *index, *idx))
+                })
+                .map(|(key, index, idx)| (key, index, idx, state.type_of((key, index))))
+                .partition(|(.., hook_type)| hook_type.is_some())
+        };
+        self.drop_hooks(removed.into_iter().map(|(_, index, idx, _)| (index, idx)));
+        {
+            let mut state = self.hot_reload.lock();
+            for (key, index, idx, hook_type) in carried {
+                let path = format!("{:?}", key_map.key_debug(key));
+                let value = self.take(idx).take_value();
+                state.carry((path, index), hook_type.unwrap(), value);
+            }
+            // the dependents of the stores are fragments of the old tree
+            let data = self.data.read();
+            for idx in stores {
+                *data[idx].dependents.lock() = Default::default();
+            }
+            state.forget_types(
+                widgets
+                    .iter()
+                    .flat_map(|(key, indices)| indices.keys().map(move |index| (*key, *index))),
+            );
+        }
+        for (key, indices) in &widgets {
+            self.undo.lock().forget(*key, indices.values().copied());
+            #[cfg(feature = "persistence")]
+            if let Some(state) = &mut *self.persistent.lock() {
+                state.forget(indices.values().copied());
+            }
+        }
+    }
+
+    /// drops the carried over values that the reloaded code did not pick up
+    #[cfg(feature = "hot_reload")]
+    pub(crate) fn finish_carry_over(&self) {
+        let unused = self.hot_reload.lock().finish();
+        if !unused.is_empty() {
+            log::info!("dropping the state of {} hooks that no longer exist", unused.len());
+        }
+    }
+
+    pub(crate) fn track_undo(&self, idx: HookRef, clone: Cloner) {
+        self.undo.lock().track(idx.1, clone);
+    }
+
+    pub(crate) fn begin_transaction(&self) { self.undo.lock().begin_transaction(); }
+
+    pub(crate) fn end_transaction(&self) { self.undo.lock().end_transaction(); }
+
+    pub(crate) fn undo(&self) {
+        let values = self.undo.lock().undo();
+        for (hook, value) in values {
+            self.set_patch(hook, value, true);
+        }
+        self.publish_undo_state();
+    }
+
+    pub(crate) fn redo(&self) {
+        let values = self.undo.lock().redo();
+        for (hook, value) in values {
+            self.set_patch(hook, value, true);
+        }
+        self.publish_undo_state();
+    }
+
+    fn publish_undo_state(&self) {
+        let (can_undo, can_redo) = {
+            let undo = self.undo.lock();
+            (undo.can_undo(), undo.can_redo())
+        };
+        self.shout(can_undo_listenable(self), can_undo);
+        self.shout(can_redo_listenable(self), can_redo);
+    }
+
+    /// the hooks of a widget, sorted by their index
+    pub fn hooks_of(&self, key: Key) -> Vec<HookRef> {
+        let mut hooks: Vec<_> = match self.key_to_idx.read().get(&key) {
+            Some(indices) => indices
+                .iter()
+                .map(|(index, (idx, generation))| ((key, *index), *idx, *generation))
+                .collect(),
+            None => vec![],
+        };
+        hooks.sort_by_key(|(hook_key, ..)| hook_key.1);
+        hooks
     }
 
     pub fn set_dependent(&self, key: HookRef, frag: Fragment) {
-        self.data.write()[key.1].0.insert(frag.0 as _);
+        let packed = u64::from(frag.0) | u64::from(frag.1) << 32;
+        // recursive, as `listen_ref` still holds the entry of the hook
+        match self.data.read_recursive().get(key.1, key.2) {
+            Some(hook) => {
+                hook.dependents.lock().insert(packed);
+            }
+            None => log::debug!("ignoring a listen to the removed hook {:?}", key),
+        }
+    }
+
+    /// whether any widget listened to the hook since it was last changed
+    pub fn has_dependents(&self, key: HookRef) -> bool {
+        let data = self.data.read();
+        data.get(key.1, key.2).is_some_and(|hook| !hook.dependents.lock().is_empty())
     }
 
+    /// takes the widgets that listened to the hook, none if it was removed
     pub fn dependents(&'_ self, key: HookRef) -> impl Iterator<Item = Fragment> + '_ {
-        std::mem::take(&mut self.data.write()[key.1].0).into_iter().map(|v| Fragment(v as _))
+        let dependents = match self.data.read().get(key.1, key.2) {
+            Some(hook) => std::mem::take(&mut *hook.dependents.lock()),
+            None => Default::default(),
+        };
+        dependents.into_iter().map(|v| Fragment(v as u32, (v >> 32) as u32))
+    }
+
+    // removes a hook and hands it out, so it is dropped by the caller after the
+    // lock is released
+    fn take(&self, idx: Idx) -> Hook {
+        let mut data = self.data.write();
+        let old = std::mem::replace(&mut data[idx], Hook::new(Box::new(())));
+        data.remove(idx);
+        old
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc,
+    };
+
+    // stands in for a thread handle, whose thread spies on the listenables of
+    // its widget until it is joined
+    struct SpyOnDrop {
+        tree: Arc<PatchedTree>,
+        hook: HookRef,
+        drops: Arc<AtomicUsize>,
+    }
+
+    impl Drop for SpyOnDrop {
+        fn drop(&mut self) {
+            let value = self.tree.get_patched(self.hook).unwrap();
+            assert_eq!(value.downcast_ref::<u32>(), Some(&1));
+            self.drops.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn remove_widget_keeps_earlier_hooks_while_dropping() {
+        let tree = Arc::new(PatchedTree::default());
+        let drops = Arc::new(AtomicUsize::new(0));
+        let key = Key(2);
+        let hook = tree.initialize((key, 0), Box::new(1u32));
+        let spy = SpyOnDrop { tree: tree.clone(), hook, drops: drops.clone() };
+        tree.initialize((key, 1), Box::new(spy));
+        tree.initialize((key, 2), Box::new(2u32));
+
+        tree.remove_widget(&key);
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+        assert!(tree.hooks_of(key).is_empty());
+        assert_eq!(tree.memory_stats().live, 0);
+    }
+
+    #[test]
+    fn removed_hooks_after_compaction() {
+        let tree = PatchedTree::default();
+        let key = Key(2);
+        tree.initialize((key, 0), Box::new(0u32));
+        let hook = tree.initialize((key, 1), Box::new(1u32));
+        tree.remove_widget(&key);
+        tree.compact();
+
+        // a thread of the removed widget might still use its hooks, whose slots are gone
+        assert!(tree.get_unpatched(hook).is_none());
+        tree.set(hook, Box::new(2u32));
+        tree.set_unconditional(hook, Box::new(2u32));
+        tree.remove_patch(hook);
+        tree.set_dependent(hook, Fragment(4, 0));
+        assert!(!tree.has_dependents(hook));
+
+        // or were reused by other hooks
+        let other = Key(3);
+        tree.initialize((other, 0), Box::new(0u32));
+        let reused = tree.initialize((other, 1), Box::new("other"));
+        assert_eq!(reused.1, hook.1);
+        assert!(tree.get_patched(hook).is_none());
+        tree.set(hook, Box::new(3u32));
+        tree.set_dependent(hook, Fragment(4, 0));
+        assert!(!tree.has_dependents(reused));
+        assert_eq!(tree.dependents(hook).count(), 0);
+        assert_eq!(tree.update_tree(&mut KeyMap::default()).count(), 0);
+        assert_eq!(tree.get_patched(reused).unwrap().downcast_ref::<&str>(), Some(&"other"));
+    }
+
+    #[test]
+    fn shouts_from_threads_during_evaluation() {
+        let tree = Arc::new(PatchedTree::default());
+        let stores: Vec<_> =
+            (0..4).map(|i| tree.initialize((Key::STORE, i), Box::new(0usize))).collect();
+        let done = Arc::new(AtomicBool::new(false));
+        let workers: Vec<_> = stores
+            .iter()
+            .copied()
+            .map(|store| {
+                let (tree, done) = (tree.clone(), done.clone());
+                std::thread::spawn(move || {
+                    let mut last = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        let value = *tree.get_patched(store).unwrap().downcast_ref().unwrap();
+                        assert!(value >= last, "{} < {}", value, last);
+                        last = value;
+                        tree.set(store, Box::new(value + 1usize));
+                        let patched = tree.get_patched(store).unwrap();
+                        assert!(*patched.downcast_ref::<usize>().unwrap() > value);
+                    }
+                    last
+                })
+            })
+            .collect();
+
+        // evaluate widgets that come and go, so the slots are reused and compacted
+        let mut key_map = KeyMap::default();
+        for frame in 0..300u32 {
+            for widget in 2..50u32 {
+                let key = Key(widget + (frame % 7) * 100);
+                for index in 0..3 {
+                    let hook = tree.initialize_with((key, index), || Box::new(vec![widget; 3]));
+                    tree.set_dependent(hook, Fragment(widget, 0));
+                    let value = tree.get_unpatched(hook).unwrap();
+                    assert_eq!(value.downcast_ref::<Vec<u32>>().unwrap()[0], widget);
+                    if frame % 3 == 0 {
+                        tree.set(hook, Box::new(vec![widget; 4]));
+                    }
+                }
+            }
+            for hook in tree.update_tree(&mut key_map).collect::<Vec<_>>() {
+                tree.dependents(hook).for_each(drop);
+            }
+            tree.remove_widget(&Key(10 + (frame % 7) * 100));
+            if frame % 100 == 0 {
+                tree.compact();
+            }
+        }
+        done.store(true, Ordering::Relaxed);
+        for worker in workers {
+            assert!(worker.join().unwrap() > 0);
+        }
+
+        // every store ends up with the last value its thread shouted
+        let last: Vec<_> = stores
+            .iter()
+            .map(|store| *tree.get_patched(*store).unwrap().downcast_ref::<usize>().unwrap())
+            .collect();
+        tree.update_tree(&mut key_map).for_each(drop);
+        for (store, last) in stores.iter().zip(last) {
+            assert_eq!(tree.get_unpatched(*store).unwrap().downcast_ref::<usize>(), Some(&last));
+        }
     }
 }
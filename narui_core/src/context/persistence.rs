@@ -0,0 +1,159 @@
+//! Listenables that survive restarts, enabled with the `persistence` feature.
+//! Their values are kept in a JSON file, keyed by the path of the widget they
+//! belong to and a name.
+
+use super::patched_tree::TreeItem;
+use freelist::Idx;
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    any::Any,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+// changes are written once nothing changed for this long
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+pub(crate) type Serializer = fn(&(dyn Any + Send + Sync)) -> serde_json::Result<Value>;
+
+pub(crate) fn serialize_item<T: Serialize + 'static>(
+    item: &(dyn Any + Send + Sync),
+) -> serde_json::Result<Value> {
+    serde_json::to_value(item.downcast_ref::<T>().expect("persistent listenable has wrong type"))
+}
+
+pub(crate) struct PersistentState {
+    path: PathBuf,
+    values: Arc<Mutex<Map<String, Value>>>,
+    persisted: HashMap<Idx, (String, Serializer)>,
+    changed: Sender<()>,
+    dirty: bool,
+}
+
+impl PersistentState {
+    /// loads the state from `path`, if it exists
+    pub(crate) fn load(path: PathBuf) -> Self {
+        let values = match std::fs::read_to_string(&path) {
+            Ok(text) => match serde_json::from_str(&text) {
+                Ok(values) => values,
+                Err(e) => {
+                    log::warn!("ignoring the invalid state file {}: {}", path.display(), e);
+                    Map::new()
+                }
+            },
+            Err(_) => Map::new(),
+        };
+        let values = Arc::new(Mutex::new(values));
+
+        let (changed, rx) = channel();
+        let (written_values, written_path) = (values.clone(), path.clone());
+        thread::spawn(move || {
+            while rx.recv().is_ok() {
+                let closed = loop {
+                    match rx.recv_timeout(DEBOUNCE) {
+                        Ok(()) => continue,
+                        Err(RecvTimeoutError::Timeout) => break false,
+                        Err(RecvTimeoutError::Disconnected) => break true,
+                    }
+                };
+                // the last write after closing happens when the state is dropped
+                if !closed {
+                    write(&written_path, &written_values.lock());
+                }
+            }
+        });
+
+        Self { path, values, persisted: Default::default(), changed, dirty: false }
+    }
+
+    pub(crate) fn get<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let value = self.values.lock().get(name)?.clone();
+        match serde_json::from_value(value) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::warn!("ignoring the persisted value of {}: {}", name, e);
+                None
+            }
+        }
+    }
+
+    pub(crate) fn persist(&mut self, idx: Idx, name: String, serialize: Serializer) {
+        self.persisted.insert(idx, (name, serialize));
+    }
+
+    pub(crate) fn is_persisted(&self, idx: Idx) -> bool { self.persisted.contains_key(&idx) }
+
+    /// records the committed value of a hook, if it is persisted
+    pub(crate) fn changed(&mut self, idx: Idx, value: &TreeItem) {
+        if let Some((name, serialize)) = self.persisted.get(&idx) {
+            match serialize(&**value) {
+                Ok(value) => {
+                    self.values.lock().insert(name.clone(), value);
+                    self.dirty = true;
+                    let _ = self.changed.send(());
+                }
+                Err(e) => log::warn!("could not persist {}: {}", name, e),
+            }
+        }
+    }
+
+    pub(crate) fn forget(&mut self, indices: impl Iterator<Item = Idx>) {
+        for idx in indices {
+            self.persisted.remove(&idx);
+        }
+    }
+}
+
+impl Drop for PersistentState {
+    fn drop(&mut self) {
+        if self.dirty {
+            write(&self.path, &self.values.lock());
+        }
+    }
+}
+
+fn write(path: &Path, values: &Map<String, Value>) {
+    // written to a temporary file first, so a crash while writing does not lose the
+    // old state
+    let tmp = path.with_extension("tmp");
+    let result = serde_json::to_vec_pretty(values)
+        .map_err(std::io::Error::from)
+        .and_then(|json| std::fs::write(&tmp, json))
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if let Err(e) = result {
+        log::error!("could not write the state file {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{context::key::KeyMap, ContextPersistent, ListenableShout, ListenableSpy, Stores};
+
+    #[test]
+    fn survives_restart() {
+        let path = std::env::temp_dir().join(format!("narui_state_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut stores = Stores::new();
+        stores.persist_state(&path);
+        let size = stores.persistent_listenable("size", 1.0f32);
+        assert_eq!(stores.spy(size), 1.0);
+        stores.shout(size, 2.5);
+        stores.tree.update_tree(&mut KeyMap::default()).for_each(|_| {});
+        drop(stores);
+
+        let mut stores = Stores::new();
+        stores.persist_state(&path);
+        let size = stores.persistent_listenable("size", 1.0f32);
+        assert_eq!(stores.spy(size), 2.5);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,434 @@
+use super::{context::CallbackContext, key::Key, patched_tree::HookKey};
+use crate::{eval::fragment::Fragment, hooks::AnimationFrame, Listenable};
+use hashbrown::HashMap;
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+pub type TimerCallback = Rc<dyn for<'a> Fn(&'a CallbackContext<'a>)>;
+pub type FrameCallback = Rc<dyn for<'a> Fn(&'a CallbackContext<'a>, AnimationFrame)>;
+
+// animation frames are not synchronized to the display, they are only paced to
+// not produce more frames than a typical display can show
+pub(crate) const FRAME_INTERVAL: Duration = Duration::from_micros(1_000_000 / 60);
+
+struct Timer {
+    // None once a timeout fired; it keeps its slot so it isn't armed again by
+    // the next evaluation
+    deadline: Option<Instant>,
+    duration: Duration,
+    // false for timeouts, that only fire once
+    repeat: bool,
+    // taken when a timeout fires, so whatever it captured is dropped right away
+    callback: Option<TimerCallback>,
+}
+
+struct FrameListener {
+    last_frame: Option<Instant>,
+    callback: FrameCallback,
+}
+
+struct ExitTransition {
+    duration: Duration,
+    progress: Listenable<Option<f32>>,
+}
+
+/// a fragment that was removed from its parent, but is kept alive until the
+/// exit transitions in its subtree are finished
+pub(crate) struct Leaving {
+    pub(crate) fragment: Fragment,
+    pub(crate) parent: Fragment,
+    // the position in the children of the parent at the time of removal
+    pub(crate) index: usize,
+    start: Instant,
+    duration: Duration,
+    transitions: Vec<Key>,
+}
+
+/// The Scheduler holds work that is driven by the event loop rather than by
+/// changes of listenables. Everything in it is owned by a widget and dropped
+/// together with it.
+#[derive(Default)]
+pub struct Scheduler {
+    timers: HashMap<Key, HashMap<u16, Timer>>,
+    frame_listeners: HashMap<Key, HashMap<u16, FrameListener>>,
+    last_frame: Option<Instant>,
+    exit_transitions: HashMap<Key, ExitTransition>,
+    leaving: Vec<Leaving>,
+    unmount_callbacks: HashMap<Key, HashMap<u16, TimerCallback>>,
+    // callbacks of removed widgets that still have to run
+    unmounted: Vec<TimerCallback>,
+}
+
+impl Scheduler {
+    /// adds a timer for the hook if it doesn't exist yet or its duration
+    /// changed. Otherwise only the callback is updated, but the next deadline
+    /// is kept, so re-evaluating a widget doesn't restart its timers and a
+    /// timeout that fired doesn't fire again.
+    pub(crate) fn set_timer(
+        &mut self,
+        key: HookKey,
+        duration: Duration,
+        repeat: bool,
+        callback: TimerCallback,
+    ) {
+        let timers = self.timers.entry(key.0).or_default();
+        match timers.get_mut(&key.1) {
+            Some(timer) if timer.duration == duration && timer.repeat == repeat => {
+                if timer.deadline.is_some() {
+                    timer.callback = Some(callback);
+                }
+            }
+            _ => {
+                let (deadline, callback) = (Some(Instant::now() + duration), Some(callback));
+                timers.insert(key.1, Timer { deadline, duration, repeat, callback });
+            }
+        }
+    }
+
+    /// returns the callbacks of all timers that are due and reschedules (or
+    /// retires) them
+    pub(crate) fn due_timers(&mut self, now: Instant) -> Vec<TimerCallback> {
+        let mut due = vec![];
+        for timers in self.timers.values_mut() {
+            for timer in timers.values_mut() {
+                let deadline = match timer.deadline {
+                    Some(deadline) if deadline <= now => deadline,
+                    _ => continue,
+                };
+                let callback =
+                    if timer.repeat { timer.callback.clone() } else { timer.callback.take() };
+                due.extend(callback);
+                let interval = timer.duration;
+                timer.deadline = timer.repeat.then(|| {
+                    // skip the ticks we missed instead of firing them all at once
+                    if deadline + interval <= now {
+                        now + interval
+                    } else {
+                        deadline + interval
+                    }
+                });
+            }
+        }
+        due
+    }
+
+    /// registers the callback to receive animation frames, or stops delivering
+    /// them if `None` is passed
+    pub(crate) fn set_frame_listener(&mut self, key: HookKey, callback: Option<FrameCallback>) {
+        match callback {
+            Some(callback) => {
+                let listeners = self.frame_listeners.entry(key.0).or_default();
+                match listeners.get_mut(&key.1) {
+                    Some(listener) => listener.callback = callback,
+                    None => {
+                        listeners.insert(key.1, FrameListener { last_frame: None, callback });
+                    }
+                }
+            }
+            None => {
+                if let Some(listeners) = self.frame_listeners.get_mut(&key.0) {
+                    listeners.remove(&key.1);
+                    if listeners.is_empty() {
+                        self.frame_listeners.remove(&key.0);
+                    }
+                }
+            }
+        }
+    }
+
+    /// returns the frame listeners together with their frame, if it is time for
+    /// a new animation frame
+    pub(crate) fn due_frames(&mut self, now: Instant) -> Vec<(FrameCallback, AnimationFrame)> {
+        if !self.wants_frames() {
+            self.last_frame = None;
+            return vec![];
+        }
+        self.last_frame = match self.last_frame {
+            Some(last_frame) if now < last_frame + FRAME_INTERVAL => return vec![],
+            // pace from the previous frame, so waking up late doesn't lower the frame rate,
+            // but don't catch up on frames we missed entirely
+            Some(last_frame) if now < last_frame + 2 * FRAME_INTERVAL => {
+                Some(last_frame + FRAME_INTERVAL)
+            }
+            _ => Some(now),
+        };
+
+        let mut due = vec![];
+        for listener in
+            self.frame_listeners.values_mut().flat_map(|listeners| listeners.values_mut())
+        {
+            let delta = listener.last_frame.map(|last| now - last).unwrap_or_default();
+            listener.last_frame = Some(now);
+            due.push((listener.callback.clone(), AnimationFrame { time: now, delta }));
+        }
+        due
+    }
+
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        let next_frame = if !self.wants_frames() {
+            None
+        } else {
+            Some(self.last_frame.map(|last| last + FRAME_INTERVAL).unwrap_or_else(Instant::now))
+        };
+        self.timers
+            .values()
+            .flat_map(|timers| timers.values())
+            .filter_map(|timer| timer.deadline)
+            .chain(next_frame)
+            .min()
+    }
+
+    fn wants_frames(&self) -> bool { !self.frame_listeners.is_empty() || !self.leaving.is_empty() }
+
+    pub(crate) fn set_exit_transition(
+        &mut self,
+        key: Key,
+        duration: Duration,
+        progress: Listenable<Option<f32>>,
+    ) {
+        self.exit_transitions.insert(key, ExitTransition { duration, progress });
+    }
+
+    pub(crate) fn has_exit_transition(&self, key: &Key) -> bool {
+        self.exit_transitions.contains_key(key)
+    }
+
+    pub(crate) fn start_leaving(
+        &mut self,
+        fragment: Fragment,
+        parent: Fragment,
+        index: usize,
+        transitions: Vec<Key>,
+    ) {
+        let duration = transitions
+            .iter()
+            .map(|key| self.exit_transitions[key].duration)
+            .max()
+            .unwrap_or_default();
+        self.leaving.push(Leaving {
+            fragment,
+            parent,
+            index,
+            start: Instant::now(),
+            duration,
+            transitions,
+        });
+    }
+
+    /// stops the exit transition of a fragment that was added back to its
+    /// parent. Returns the progress listenables that have to be reset.
+    pub(crate) fn cancel_leaving(&mut self, fragment: Fragment) -> Vec<Listenable<Option<f32>>> {
+        match self.leaving.iter().position(|leaving| leaving.fragment == fragment) {
+            Some(position) => {
+                let leaving = self.leaving.remove(position);
+                leaving
+                    .transitions
+                    .iter()
+                    .filter_map(|key| self.exit_transitions.get(key))
+                    .map(|transition| transition.progress)
+                    .collect()
+            }
+            None => vec![],
+        }
+    }
+
+    /// the leaving children of parent, sorted by their position
+    pub(crate) fn leaving_children(&self, parent: Fragment) -> Vec<(Fragment, usize)> {
+        let mut children: Vec<_> = self
+            .leaving
+            .iter()
+            .filter(|leaving| leaving.parent == parent)
+            .map(|leaving| (leaving.fragment, leaving.index))
+            .collect();
+        children.sort_by_key(|(_, index)| *index);
+        children
+    }
+
+    pub(crate) fn take_leaving_children(&mut self, parent: Fragment) -> Vec<Fragment> {
+        let (taken, kept) = std::mem::take(&mut self.leaving)
+            .into_iter()
+            .partition(|leaving| leaving.parent == parent);
+        self.leaving = kept;
+        taken.into_iter().map(|leaving: Leaving| leaving.fragment).collect()
+    }
+
+    pub(crate) fn leaving_progress(&self, now: Instant) -> Vec<(Listenable<Option<f32>>, f32)> {
+        let mut progress = vec![];
+        for leaving in &self.leaving {
+            let elapsed = now - leaving.start;
+            for transition in
+                leaving.transitions.iter().filter_map(|key| self.exit_transitions.get(key))
+            {
+                let t = if transition.duration.is_zero() {
+                    1.0
+                } else {
+                    (elapsed.as_secs_f32() / transition.duration.as_secs_f32()).min(1.0)
+                };
+                progress.push((transition.progress, t));
+            }
+        }
+        progress
+    }
+
+    pub(crate) fn finished_leaving(&mut self, now: Instant) -> Vec<Leaving> {
+        let (finished, kept) = std::mem::take(&mut self.leaving)
+            .into_iter()
+            .partition(|leaving| now - leaving.start >= leaving.duration);
+        self.leaving = kept;
+        finished
+    }
+
+    pub(crate) fn set_unmount_callback(&mut self, key: HookKey, callback: TimerCallback) {
+        self.unmount_callbacks.entry(key.0).or_default().insert(key.1, callback);
+    }
+
+    pub(crate) fn take_unmounted(&mut self) -> Vec<TimerCallback> {
+        std::mem::take(&mut self.unmounted)
+    }
+
+    pub(crate) fn remove_widget(&mut self, key: &Key) {
+        self.timers.remove(key);
+        self.frame_listeners.remove(key);
+        self.exit_transitions.remove(key);
+        if let Some(callbacks) = self.unmount_callbacks.remove(key) {
+            // in the order of the hooks in the widget
+            let mut callbacks: Vec<_> = callbacks.into_iter().collect();
+            callbacks.sort_unstable_by_key(|(idx, _)| *idx);
+            self.unmounted.extend(callbacks.into_iter().map(|(_, callback)| callback));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use freelist::Idx;
+
+    fn callback() -> TimerCallback { Rc::new(|_: &CallbackContext| {}) }
+
+    fn same(a: &TimerCallback, b: &TimerCallback) -> bool {
+        Rc::as_ptr(a) as *const () == Rc::as_ptr(b) as *const ()
+    }
+
+    #[test]
+    fn timers() {
+        let mut scheduler = Scheduler::default();
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let (timeout, interval) = (callback(), callback());
+        scheduler.set_timer((Key(5), 0), second, false, timeout.clone());
+        scheduler.set_timer((Key(5), 1), 2 * second, true, interval.clone());
+        assert!(scheduler.due_timers(start).is_empty());
+        assert!(scheduler.next_deadline().unwrap() >= start + second);
+
+        let due = scheduler.due_timers(start + second * 3 / 2);
+        assert!(due.len() == 1 && same(&due[0], &timeout));
+        // re-evaluating the widget doesn't restart the interval
+        scheduler.set_timer((Key(5), 1), 2 * second, true, interval.clone());
+        let due = scheduler.due_timers(start + second * 5 / 2);
+        assert!(due.len() == 1 && same(&due[0], &interval));
+        assert!(scheduler.due_timers(start + second * 7 / 2).is_empty());
+        assert_eq!(scheduler.due_timers(start + second * 9 / 2).len(), 1);
+
+        // a timeout that fired only fires again when its duration changes
+        scheduler.set_timer((Key(5), 0), second, false, timeout.clone());
+        assert!(scheduler.due_timers(start + second * 100).iter().all(|due| !same(due, &timeout)));
+        scheduler.set_timer((Key(5), 0), 2 * second, false, timeout.clone());
+        let later = Instant::now() + second * 3;
+        assert!(scheduler.due_timers(later).iter().any(|due| same(due, &timeout)));
+        assert!(scheduler.due_timers(later + second * 100).iter().all(|due| !same(due, &timeout)));
+
+        // unmounting the widget cancels its timers
+        scheduler.remove_widget(&Key(5));
+        assert!(scheduler.due_timers(start + second * 100).is_empty());
+        assert_eq!(scheduler.next_deadline(), None);
+    }
+
+    #[test]
+    fn frame_cadence() {
+        let mut scheduler = Scheduler::default();
+        let start = Instant::now();
+        let frame: FrameCallback = Rc::new(|_: &CallbackContext, _| {});
+        scheduler.set_frame_listener((Key(5), 0), Some(frame.clone()));
+        scheduler.set_frame_listener((Key(6), 0), Some(frame));
+        let first = scheduler.due_frames(start);
+        assert_eq!(first.len(), 2);
+        assert!(first.iter().all(|(_, frame)| frame.delta.is_zero()));
+
+        // polling every millisecond for a second, the event loop wakes up a bit late
+        let mut frames = 0;
+        let mut deltas = vec![];
+        for ms in 1..=1000 {
+            let due = scheduler.due_frames(start + Duration::from_millis(ms));
+            if !due.is_empty() {
+                assert_eq!(due.len(), 2);
+                frames += 1;
+                deltas.push(due[0].1.delta);
+            }
+        }
+        assert_eq!(frames, 60);
+        assert!(deltas.iter().all(|delta| *delta >= Duration::from_millis(16)));
+        assert!(deltas.iter().all(|delta| *delta <= Duration::from_millis(17)));
+
+        scheduler.set_frame_listener((Key(5), 0), None);
+        scheduler.set_frame_listener((Key(6), 0), None);
+        assert!(scheduler.due_frames(start + Duration::from_secs(2)).is_empty());
+        assert_eq!(scheduler.next_deadline(), None);
+    }
+
+    #[test]
+    fn readded_while_leaving() {
+        let mut scheduler = Scheduler::default();
+        let progress = |key| Listenable::new(((Key(key), 0), Idx::new(key as usize).unwrap(), 0));
+        let (parent, child) = (Fragment(5, 0), Fragment(6, 0));
+        scheduler.set_exit_transition(Key(7), Duration::from_secs(1), progress(7));
+        scheduler.set_exit_transition(Key(8), Duration::from_secs(2), progress(8));
+        scheduler.start_leaving(child, parent, 1, vec![Key(7), Key(8)]);
+        assert_eq!(scheduler.leaving_children(parent), vec![(child, 1)]);
+        assert!(scheduler.next_deadline().is_some());
+
+        let half = scheduler.leaving_progress(Instant::now() + Duration::from_millis(500));
+        assert_eq!(half.len(), 2);
+        assert!(half[0].1 > 0.4 && half[0].1 < 0.6);
+        assert!(half[1].1 > 0.2 && half[1].1 < 0.3);
+
+        // added back to the parent halfway through
+        assert_eq!(scheduler.cancel_leaving(child), vec![progress(7), progress(8)]);
+        assert!(scheduler.leaving_children(parent).is_empty());
+        assert!(scheduler.leaving_progress(Instant::now()).is_empty());
+        assert!(scheduler.finished_leaving(Instant::now() + Duration::from_secs(3)).is_empty());
+        assert_eq!(scheduler.next_deadline(), None);
+        assert!(scheduler.cancel_leaving(child).is_empty());
+
+        // removing it again starts the transitions over
+        scheduler.start_leaving(child, parent, 0, vec![Key(7), Key(8)]);
+        let progress = scheduler.leaving_progress(Instant::now());
+        assert!(progress.iter().all(|(_, t)| *t < 0.1));
+        let finished = scheduler.finished_leaving(Instant::now() + Duration::from_secs(3));
+        assert!(finished.len() == 1 && finished[0].fragment == child && finished[0].index == 0);
+    }
+
+    #[test]
+    fn unmount_order() {
+        let mut scheduler = Scheduler::default();
+        let callbacks: Vec<_> = (0..6).map(|_| callback()).collect();
+        // registered out of order, the hook indices decide
+        for (key, hook, callback) in [(6, 9, 2), (6, 3, 1), (5, 0, 3), (6, 1, 0), (5, 7, 4)] {
+            scheduler.set_unmount_callback((Key(key), hook), callbacks[callback].clone());
+        }
+        scheduler.set_unmount_callback((Key(7), 0), callbacks[5].clone());
+        assert!(scheduler.take_unmounted().is_empty());
+
+        // widgets in the order in which they are removed
+        scheduler.remove_widget(&Key(6));
+        scheduler.remove_widget(&Key(5));
+        let unmounted = scheduler.take_unmounted();
+        assert_eq!(unmounted.len(), 5);
+        assert!(unmounted.iter().zip(&callbacks).all(|(a, b)| same(a, b)));
+        assert!(scheduler.take_unmounted().is_empty());
+        scheduler.remove_widget(&Key(6));
+        assert!(scheduler.take_unmounted().is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use super::patched_tree::{HookRef, TreeItem};
+use crate::Key;
+use freelist::Idx;
+use hashbrown::HashMap;
+use std::any::Any;
+
+pub(crate) type Cloner = fn(&(dyn Any + Send + Sync)) -> TreeItem;
+
+pub(crate) fn clone_item<T: Clone + Send + Sync + 'static>(
+    item: &(dyn Any + Send + Sync),
+) -> TreeItem {
+    Box::new(item.downcast_ref::<T>().expect("tracked listenable has wrong type").clone())
+}
+
+struct Change {
+    hook: HookRef,
+    old: TreeItem,
+    new: TreeItem,
+    clone: Cloner,
+}
+
+type Transaction = Vec<Change>;
+
+/// The undo and redo stacks of all listenables that are tracked for undo.
+/// Changes are recorded when they are committed by `PatchedTree::update_tree`
+/// and all changes committed in one update form a transaction, unless an
+/// explicit transaction is open.
+#[derive(Default)]
+pub(crate) struct UndoHistory {
+    tracked: HashMap<Idx, Cloner>,
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    open: Transaction,
+    depth: usize,
+    // transactions that were ended, but whose last changes are not committed yet
+    pending_ends: usize,
+}
+
+impl UndoHistory {
+    // the oldest transactions are dropped once there are more
+    const CAPACITY: usize = 1000;
+
+    pub(crate) fn track(&mut self, idx: Idx, clone: Cloner) { self.tracked.insert(idx, clone); }
+
+    pub(crate) fn cloner(&self, idx: Idx) -> Option<Cloner> { self.tracked.get(&idx).copied() }
+
+    pub(crate) fn record(&mut self, hook: HookRef, old: TreeItem, new: TreeItem, clone: Cloner) {
+        self.redo.clear();
+        // repeated changes in one transaction (for example while dragging) are merged
+        match self.open.iter_mut().find(|change| change.hook == hook) {
+            Some(change) => change.new = new,
+            None => self.open.push(Change { hook, old, new, clone }),
+        }
+    }
+
+    pub(crate) fn begin_transaction(&mut self) { self.depth += 1; }
+
+    pub(crate) fn end_transaction(&mut self) {
+        if self.depth > self.pending_ends {
+            self.pending_ends += 1;
+        }
+    }
+
+    /// closes the transaction of the update, if no explicit transaction is
+    /// open anymore
+    pub(crate) fn finish_update(&mut self) {
+        self.depth -= self.pending_ends;
+        self.pending_ends = 0;
+        if self.depth == 0 {
+            self.close();
+        }
+    }
+
+    fn close(&mut self) {
+        if self.open.is_empty() {
+            return;
+        }
+        if self.undo.len() == Self::CAPACITY {
+            self.undo.remove(0);
+        }
+        self.undo.push(std::mem::take(&mut self.open));
+    }
+
+    /// the values to restore, in the order they have to be set. Ends all open
+    /// transactions.
+    pub(crate) fn undo(&mut self) -> Vec<(HookRef, TreeItem)> {
+        self.depth = 0;
+        self.pending_ends = 0;
+        self.close();
+        match self.undo.pop() {
+            Some(transaction) => {
+                let values = transaction
+                    .iter()
+                    .rev()
+                    .map(|change| (change.hook, (change.clone)(&*change.old)))
+                    .collect();
+                self.redo.push(transaction);
+                values
+            }
+            None => vec![],
+        }
+    }
+
+    pub(crate) fn redo(&mut self) -> Vec<(HookRef, TreeItem)> {
+        match self.redo.pop() {
+            Some(transaction) => {
+                let values = transaction
+                    .iter()
+                    .map(|change| (change.hook, (change.clone)(&*change.new)))
+                    .collect();
+                self.undo.push(transaction);
+                values
+            }
+            None => vec![],
+        }
+    }
+
+    pub(crate) fn can_undo(&self) -> bool { !self.undo.is_empty() || !self.open.is_empty() }
+
+    pub(crate) fn can_redo(&self) -> bool { !self.redo.is_empty() }
+
+    /// drops everything that refers to the hooks of a removed widget
+    pub(crate) fn forget(&mut self, key: Key, indices: impl Iterator<Item = Idx>) {
+        for idx in indices {
+            self.tracked.remove(&idx);
+        }
+        for transaction in
+            self.undo.iter_mut().chain(self.redo.iter_mut()).chain(std::iter::once(&mut self.open))
+        {
+            transaction.retain(|change| change.hook.0 .0 != key);
+        }
+        self.undo.retain(|transaction| !transaction.is_empty());
+        self.redo.retain(|transaction| !transaction.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hook(i: usize) -> HookRef { ((Key::ROOT, i as u16), Idx::new(i).unwrap(), 0) }
+
+    fn change(history: &mut UndoHistory, i: usize, old: u32, new: u32) {
+        history.record(hook(i), Box::new(old), Box::new(new), clone_item::<u32>);
+    }
+
+    fn values(values: Vec<(HookRef, TreeItem)>) -> Vec<(usize, u32)> {
+        values.into_iter().map(|(h, v)| (h.1.get(), *v.downcast_ref::<u32>().unwrap())).collect()
+    }
+
+    #[test]
+    fn transactions() {
+        let mut history = UndoHistory::default();
+        change(&mut history, 1, 0, 1);
+        history.finish_update();
+
+        history.begin_transaction();
+        change(&mut history, 1, 1, 2);
+        history.finish_update();
+        change(&mut history, 2, 0, 5);
+        change(&mut history, 1, 2, 3);
+        history.end_transaction();
+        assert!(history.can_undo());
+        history.finish_update();
+
+        assert_eq!(values(history.undo()), vec![(2, 0), (1, 1)]);
+        assert!(history.can_redo());
+        assert_eq!(values(history.undo()), vec![(1, 0)]);
+        assert!(!history.can_undo());
+        assert_eq!(values(history.redo()), vec![(1, 1)]);
+
+        change(&mut history, 1, 1, 7);
+        history.finish_update();
+        assert!(!history.can_redo());
+        assert_eq!(values(history.undo()), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn tree() {
+        use crate::{
+            context::key::KeyMap,
+            ContextUndo,
+            ContextUndoTrack,
+            ListenableCreate,
+            ListenableShout,
+            ListenableSpy,
+            Stores,
+        };
+
+        let mut stores = Stores::new();
+        let value = stores.listenable(0u32);
+        let untracked = stores.listenable(0u32);
+        stores.track_undo(value);
+        let context = stores.thread_context();
+        let update = || stores.tree.update_tree(&mut KeyMap::default()).for_each(|_| {});
+
+        stores.shout(value, 1);
+        stores.shout(untracked, 1);
+        update();
+        assert!(stores.spy(stores.can_undo()));
+
+        context.undo();
+        update();
+        assert_eq!(stores.spy(value), 0);
+        assert_eq!(stores.spy(untracked), 1);
+        assert!(!stores.spy(stores.can_undo()));
+        assert!(stores.spy(stores.can_redo()));
+
+        context.redo();
+        update();
+        assert_eq!(stores.spy(value), 1);
+    }
+}
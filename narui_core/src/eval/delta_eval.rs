@@ -8,11 +8,15 @@ use crate::{
         },
         key::KeyMap,
         patched_tree::PatchedTree,
+        scheduler::Scheduler,
     },
     eval::{
         fragment::UnevaluatedFragment,
         layout::{LayoutTree, Layouter, ScaleFactor},
+        render_trace::RenderTrace,
     },
+    hooks::ListenableShout,
+    util::span::span,
     CallbackContext,
     Fragment,
     FragmentChildren,
@@ -23,7 +27,12 @@ use crate::{
 use derivative::Derivative;
 use freelist::Idx;
 use hashbrown::HashSet;
-use std::sync::Arc;
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 /// EvaluatedEvalObject is analog to a EvalObject but not lazy and additionally
 /// contains the dependencies of Node for allowing partial rebuild.
@@ -45,6 +54,17 @@ pub struct EvaluatedFragment {
 pub struct EvaluatorInner {
     vulkan_context: VulkanContext,
     pub(crate) tree: Arc<PatchedTree>,
+    // the number of error boundaries we are currently evaluating in
+    catching: usize,
+    // the fragments (and their layout nodes) whose evaluation started inside an
+    // error boundary, so they can be removed if it panics before they are attached
+    // to the tree
+    journal: Vec<(Fragment, Option<Idx>)>,
+    // the number of widget functions that ran since the last frame
+    reevaluated: usize,
+    // the fragments whose widget functions ran since the devtools last asked
+    #[cfg(feature = "devtools")]
+    evaluated: Vec<Fragment>,
 }
 
 impl EvaluatorInner {
@@ -53,40 +73,89 @@ impl EvaluatorInner {
         layout_tree: &mut Layouter,
         fragment_store: &mut FragmentStore,
         after_frame_callbacks: &mut Vec<AfterFrameCallback>,
+        scheduler: &mut Scheduler,
         key_map: &mut KeyMap,
     ) -> bool {
+        let _span = span!("update");
+        let finished_leaving = scheduler.finished_leaving(Instant::now());
+        let any_finished_leaving = !finished_leaving.is_empty();
+        for leaving in finished_leaving {
+            // the parent might have been removed together with another leaving fragment
+            if unsafe { !fragment_store.removed(leaving.parent) } {
+                let parent = fragment_store.get(leaving.parent).assert_evaluated();
+                let parent_layout_idx = parent.layout_idx;
+                let layout_children = Self::layout_children(
+                    fragment_store,
+                    scheduler,
+                    leaving.parent,
+                    &parent.children,
+                );
+                layout_tree.set_children(parent_layout_idx, layout_children.into_iter());
+            }
+            self.remove_tree(key_map, layout_tree, fragment_store, scheduler, leaving.fragment);
+        }
+
         let mut to_update: HashSet<Fragment, ahash::RandomState> = HashSet::default();
 
         let touched_keys = self.tree.update_tree(key_map);
         for key in touched_keys {
             // println!("touched key ({:?}, {})", key_map.key_debug(key.0), key.1);
-            for frag in self.tree.dependents(key) {
-                to_update.insert(frag);
+            // taking the dependents clears them, the re-evaluation registers them again.
+            // Dependents that were removed since they listened are dropped here, so
+            // they do not shadow the fragment that reuses their slot.
+            let dependents: Vec<_> = self
+                .tree
+                .dependents(key)
+                .filter(|frag| fragment_store.get_checked(*frag).is_some())
+                .collect();
+            to_update.extend(dependents.iter().copied());
+            if let Some(trace) = &mut fragment_store.render_trace {
+                trace.hook_patched(key, dependents);
             }
         }
 
         if to_update.is_empty() {
-            return false;
+            if let Some(trace) = &mut fragment_store.render_trace {
+                trace.finish(key_map);
+            }
+            return any_finished_leaving;
         }
 
         for idx in to_update.drain() {
-            self.re_eval_fragment(layout_tree, fragment_store, after_frame_callbacks, key_map, idx)
+            if let Some(trace) = &mut fragment_store.render_trace {
+                trace.rerender_for_hooks(idx);
+            }
+            self.re_eval_fragment_caught(
+                layout_tree,
+                fragment_store,
+                after_frame_callbacks,
+                scheduler,
+                key_map,
+                idx,
+            )
         }
 
         loop {
             let mut len = 0;
             for idx in fragment_store.dirty_args().collect::<Vec<_>>() {
                 len += 1;
-                self.re_eval_fragment(
+                if let Some(trace) = &mut fragment_store.render_trace {
+                    trace.rerender_for_args(idx);
+                }
+                self.re_eval_fragment_caught(
                     layout_tree,
                     fragment_store,
                     after_frame_callbacks,
+                    scheduler,
                     key_map,
                     idx,
                 )
             }
 
             if len == 0 {
+                if let Some(trace) = &mut fragment_store.render_trace {
+                    trace.finish(key_map);
+                }
                 return true;
             }
         }
@@ -102,6 +171,14 @@ impl EvaluatorInner {
             "unconditionally evaluating {:?}",
             context.key_map.key_debug(context.fragment_store.get(fragment_idx).key())
         );
+        let _span = span!(
+            "evaluate",
+            widget = ?context.key_map.key_tail(context.fragment_store.get(fragment_idx).key())
+        );
+        let journal_idx = self.journal.len();
+        if self.catching > 0 {
+            self.journal.push((fragment_idx, None));
+        }
         let (layout_idx, children, gen) = {
             let (key, gen) = {
                 let UnevaluatedFragment { key, gen } =
@@ -110,14 +187,50 @@ impl EvaluatorInner {
             };
             let mut context = context.with_key_widget(key, fragment_idx);
             let evaluated: FragmentInner = gen(&mut context);
+            context.finish_hooks();
+            self.reevaluated += 1;
+            #[cfg(feature = "devtools")]
+            self.evaluated.push(fragment_idx);
 
             let (layout, render_object, children, is_clipper, subpass) = evaluated.unpack();
+            let mut children = children;
             let layout_idx =
                 layout_tree.add_node(layout, render_object, is_clipper, subpass, fragment_idx);
+            if self.catching > 0 {
+                self.journal[journal_idx].1 = Some(layout_idx);
+            }
 
             for child in &children {
-                log::trace!("evaluating (unconditionally) child {:?} of {:?}", child, fragment_idx);
-                self.evaluate_unconditional(*child, layout_tree, &mut context);
+                context.fragment_store.set_enclosing_boundary(*child, fragment_idx);
+            }
+            let is_boundary = context.fragment_store.error_boundary(fragment_idx).is_some();
+            let mut evaluate_children = |this: &mut Self, layout_tree: &mut Layouter| {
+                for child in &children {
+                    log::trace!(
+                        "evaluating (unconditionally) child {:?} of {:?}",
+                        child,
+                        fragment_idx
+                    );
+                    this.evaluate_unconditional(*child, layout_tree, &mut context);
+                }
+            };
+            if is_boundary {
+                if let Err((message, journal)) =
+                    self.catch_unwind(|this| evaluate_children(this, layout_tree))
+                {
+                    self.roll_back(
+                        context.key_map,
+                        layout_tree,
+                        context.fragment_store,
+                        context.scheduler,
+                        fragment_idx,
+                        journal,
+                        message,
+                    );
+                    children = Default::default();
+                }
+            } else {
+                evaluate_children(self, layout_tree);
             }
 
             layout_tree.set_children(
@@ -150,10 +263,11 @@ impl EvaluatorInner {
         layout_tree: &mut Layouter,
         fragment_store: &mut FragmentStore,
         after_frame_callbacks: &mut Vec<AfterFrameCallback>,
+        scheduler: &mut Scheduler,
         key_map: &mut KeyMap,
         frag_idx: Fragment,
     ) {
-        if unsafe { fragment_store.removed(frag_idx) } {
+        if fragment_store.get_checked(frag_idx).is_none() {
             log::trace!("tried to reeval already removed fragment {:?}, skipping it", frag_idx);
             return;
         }
@@ -172,6 +286,7 @@ impl EvaluatorInner {
                         key,
                         frag_idx,
                         after_frame_callbacks,
+                        scheduler,
                         key_map,
                     ),
                 );
@@ -180,6 +295,7 @@ impl EvaluatorInner {
                 log::trace!("reevaluating {:?}", key_map.key_debug(*key));
                 let key = *key;
                 let gen = gen.take().unwrap();
+                let _span = span!("evaluate", widget = ?key_map.key_tail(key));
 
                 let evaluated = {
                     let mut context = WidgetContext::for_fragment(
@@ -189,44 +305,122 @@ impl EvaluatorInner {
                         key,
                         frag_idx,
                         after_frame_callbacks,
+                        scheduler,
                         key_map,
                     );
 
                     let evaluated: FragmentInner = (gen)(&mut context);
+                    context.finish_hooks();
                     evaluated
                 };
+                self.reevaluated += 1;
+                #[cfg(feature = "devtools")]
+                self.evaluated.push(frag_idx);
                 let (layout, render_object, children, is_clipper, subpass) = evaluated.unpack();
 
+                // the old children stay in place until the new ones are evaluated, so an error
+                // boundary can find them if evaluating one of the new children panics
                 let (old_children, layout_idx) = {
-                    let frag = &mut fragment_store.get_mut(frag_idx).assert_evaluated_mut();
-                    (&mut std::mem::take(&mut frag.children), frag.layout_idx)
+                    let frag = fragment_store.get(frag_idx).assert_evaluated();
+                    (frag.children.clone(), frag.layout_idx)
                 };
+                // children that already exist (for example keyed children of a reordered
+                // list) are not evaluated again, only their layout nodes are moved to the
+                // new positions by `set_children` below
+                let mut kept_children: HashSet<Fragment, ahash::RandomState> = HashSet::default();
                 let num_new_children = children.len();
                 let num_old_children = old_children.len();
 
                 for child in &children {
-                    match fragment_store.get(*child) {
-                        Unevaluated(_) => {
-                            self.evaluate_unconditional(
-                                *child,
-                                layout_tree,
-                                &mut WidgetContext::for_fragment(
-                                    self.vulkan_context.clone(),
-                                    self.tree.clone(),
-                                    fragment_store,
-                                    key,
-                                    *child,
-                                    after_frame_callbacks,
-                                    key_map,
-                                ),
-                            );
-                        }
-                        Evaluated(_) => {
-                            old_children
-                                .iter()
-                                .position(|old_idx| old_idx == child)
-                                .map(|idx| old_children.swap_remove(idx));
+                    fragment_store.set_enclosing_boundary(*child, frag_idx);
+                }
+                let is_boundary = fragment_store.error_boundary(frag_idx).is_some();
+                let mut evaluate_children =
+                    |this: &mut Self,
+                     layout_tree: &mut Layouter,
+                     fragment_store: &mut FragmentStore,
+                     scheduler: &mut Scheduler,
+                     key_map: &mut KeyMap| {
+                        for child in &children {
+                            match fragment_store.get(*child) {
+                                Unevaluated(_) => {
+                                    this.evaluate_unconditional(
+                                        *child,
+                                        layout_tree,
+                                        &mut WidgetContext::for_fragment(
+                                            this.vulkan_context.clone(),
+                                            this.tree.clone(),
+                                            fragment_store,
+                                            key,
+                                            *child,
+                                            after_frame_callbacks,
+                                            scheduler,
+                                            key_map,
+                                        ),
+                                    );
+                                }
+                                Evaluated(frag) => {
+                                    kept_children.insert(*child);
+
+                                    // a leaving child was added back before its exit transition
+                                    // finished
+                                    let progress = scheduler.cancel_leaving(*child);
+                                    if !progress.is_empty() {
+                                        layout_tree.set_inert(frag.layout_idx, false);
+                                        for progress in progress {
+                                            this.tree.shout(progress, None);
+                                        }
+                                    }
+                                }
+                            }
                         }
+                    };
+                if is_boundary {
+                    if let Err((message, journal)) = self.catch_unwind(|this| {
+                        evaluate_children(this, layout_tree, fragment_store, scheduler, key_map)
+                    }) {
+                        self.roll_back(
+                            key_map,
+                            layout_tree,
+                            fragment_store,
+                            scheduler,
+                            frag_idx,
+                            journal,
+                            message,
+                        );
+                        let frag = fragment_store.get_mut(frag_idx).assert_evaluated_mut();
+                        frag.gen = Some(gen);
+                        layout_tree.set_node(
+                            frag.layout_idx,
+                            layout,
+                            render_object,
+                            is_clipper,
+                            subpass,
+                            frag_idx,
+                        );
+                        return;
+                    }
+                } else {
+                    evaluate_children(self, layout_tree, fragment_store, scheduler, key_map);
+                }
+
+                // children with exit transitions stay in the layout until the transitions
+                // are finished, the rest is removed right away
+                let mut removed_children = vec![];
+                for (index, child) in old_children.iter().enumerate() {
+                    if kept_children.contains(child) {
+                        continue;
+                    }
+                    let transitions = Self::exit_transitions(fragment_store, scheduler, *child);
+                    if transitions.is_empty() {
+                        removed_children.push(*child);
+                    } else {
+                        log::trace!("{:?} starts leaving", key_map.key_debug((*child).into()));
+                        layout_tree.set_inert(
+                            fragment_store.get(*child).assert_evaluated().layout_idx,
+                            true,
+                        );
+                        scheduler.start_leaving(*child, frag_idx, index, transitions);
                     }
                 }
 
@@ -235,13 +429,12 @@ impl EvaluatorInner {
                 if (num_old_children != 0) || (num_new_children != 0) {
                     layout_tree.set_children(
                         layout_idx,
-                        children
-                            .iter()
-                            .map(|c| fragment_store.get(*c).assert_evaluated().layout_idx),
+                        Self::layout_children(fragment_store, scheduler, frag_idx, &children)
+                            .into_iter(),
                     );
                 }
 
-                let old_children = {
+                {
                     let frag = fragment_store.get_mut(frag_idx).assert_evaluated_mut();
 
                     frag.gen = Some(gen);
@@ -255,22 +448,151 @@ impl EvaluatorInner {
                         subpass,
                         frag_idx,
                     );
+                }
 
-                    old_children
-                };
+                for child in removed_children {
+                    self.remove_tree(key_map, layout_tree, fragment_store, scheduler, child);
+                }
+            }
+        }
+    }
 
-                for child in old_children {
-                    self.remove_tree(key_map, layout_tree, fragment_store, *child);
+    // re-evaluates the fragment and lets the nearest error boundary handle panics
+    fn re_eval_fragment_caught(
+        &mut self,
+        layout_tree: &mut Layouter,
+        fragment_store: &mut FragmentStore,
+        after_frame_callbacks: &mut Vec<AfterFrameCallback>,
+        scheduler: &mut Scheduler,
+        key_map: &mut KeyMap,
+        frag_idx: Fragment,
+    ) {
+        let boundary = match fragment_store.get_checked(frag_idx) {
+            Some(_) => fragment_store.enclosing_boundary(frag_idx),
+            None => None,
+        };
+        match boundary {
+            None => self.re_eval_fragment(
+                layout_tree,
+                fragment_store,
+                after_frame_callbacks,
+                scheduler,
+                key_map,
+                frag_idx,
+            ),
+            Some(boundary) => {
+                if let Err((message, journal)) = self.catch_unwind(|this| {
+                    this.re_eval_fragment(
+                        layout_tree,
+                        fragment_store,
+                        after_frame_callbacks,
+                        scheduler,
+                        key_map,
+                        frag_idx,
+                    )
+                }) {
+                    self.roll_back(
+                        key_map,
+                        layout_tree,
+                        fragment_store,
+                        scheduler,
+                        boundary,
+                        journal,
+                        message,
+                    );
                 }
             }
         }
     }
 
+    fn catch_unwind<R>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> Result<R, (String, Vec<(Fragment, Option<Idx>)>)> {
+        let journal_start = self.journal.len();
+        self.catching += 1;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(self)));
+        self.catching -= 1;
+        match result {
+            Ok(result) => {
+                // an enclosing boundary still needs the journal
+                if self.catching == 0 {
+                    self.journal.clear();
+                }
+                Ok(result)
+            }
+            Err(payload) => Err((panic_message(payload), self.journal.split_off(journal_start))),
+        }
+    }
+
+    // removes everything below the error boundary after a panic, including the
+    // partially evaluated fragments from the journal, and hands the error to the
+    // boundary
+    #[allow(clippy::too_many_arguments)]
+    fn roll_back(
+        &mut self,
+        key_map: &mut KeyMap,
+        layout_tree: &mut Layouter,
+        fragment_store: &mut FragmentStore,
+        scheduler: &mut Scheduler,
+        boundary: Fragment,
+        journal: Vec<(Fragment, Option<Idx>)>,
+        message: String,
+    ) {
+        log::error!("{:?} caught a panic: {}", key_map.key_debug(boundary.into()), message);
+
+        let mut layout_nodes: HashSet<Idx, ahash::RandomState> = HashSet::default();
+        let mut stack = vec![];
+        for (frag, layout_idx) in journal {
+            stack.push(frag);
+            layout_nodes.extend(layout_idx);
+        }
+        if let Evaluated(frag) = fragment_store.get_mut(boundary) {
+            stack.extend(std::mem::take(&mut frag.children));
+            layout_tree.set_children(frag.layout_idx, std::iter::empty());
+        }
+
+        // fragments that were created but never evaluated are only reachable through
+        // the keys of the widget that created them
+        let mut fragments: HashSet<Fragment, ahash::RandomState> = HashSet::default();
+        let mut removed = vec![];
+        while let Some(frag) = stack.pop() {
+            if unsafe { fragment_store.data.removed(frag.into()) } || !fragments.insert(frag) {
+                continue;
+            }
+            removed.push(frag);
+            if let Some(Evaluated(evaluated)) = &fragment_store.data[frag.into()].fragment {
+                stack.extend(evaluated.children.iter().cloned());
+                layout_nodes.insert(evaluated.layout_idx);
+            }
+            stack.extend(scheduler.take_leaving_children(frag));
+            let children = key_map.children(frag.into()).into_iter();
+            stack.extend(children.map(|key| fragment_store.fragment(key)));
+        }
+
+        // children before their parents, like `remove_tree`
+        for frag in removed.into_iter().rev() {
+            let key = frag.into();
+            self.tree.remove_widget(&key);
+            scheduler.remove_widget(&key);
+            key_map.remove(&key);
+            fragment_store.remove(frag);
+        }
+        for layout_idx in layout_nodes {
+            layout_tree.remove_node(layout_idx);
+        }
+
+        if let Some(error) = fragment_store.error_boundary(boundary) {
+            self.tree.shout(error, Some(message));
+        }
+    }
+
     fn remove_tree(
         &mut self,
         key_map: &mut KeyMap,
         layout_tree: &mut Layouter,
         fragment_store: &mut FragmentStore,
+        scheduler: &mut Scheduler,
         frag: Fragment,
     ) {
         let EvaluatedFragment { key, children, layout_idx, store_idx, .. } =
@@ -279,9 +601,13 @@ impl EvaluatorInner {
         let layout_idx = *layout_idx;
         let store_idx = *store_idx;
         for child in children.clone() {
-            self.remove_tree(key_map, layout_tree, fragment_store, child);
+            self.remove_tree(key_map, layout_tree, fragment_store, scheduler, child);
+        }
+        for child in scheduler.take_leaving_children(frag) {
+            self.remove_tree(key_map, layout_tree, fragment_store, scheduler, child);
         }
         self.tree.remove_widget(&key);
+        scheduler.remove_widget(&key);
 
         log::trace!("removing layout_node {:?}", key_map.key_debug(key));
         layout_tree.remove_node(layout_idx);
@@ -289,6 +615,40 @@ impl EvaluatorInner {
 
         fragment_store.remove(store_idx);
     }
+
+    // the keys of all widgets in the subtree that have an exit transition
+    fn exit_transitions(
+        fragment_store: &FragmentStore,
+        scheduler: &Scheduler,
+        frag: Fragment,
+    ) -> Vec<Key> {
+        let mut transitions = vec![];
+        let mut stack = vec![frag];
+        while let Some(frag) = stack.pop() {
+            let frag = fragment_store.get(frag).assert_evaluated();
+            if scheduler.has_exit_transition(&frag.key) {
+                transitions.push(frag.key);
+            }
+            stack.extend(frag.children.iter().cloned());
+        }
+        transitions
+    }
+
+    // the layout children of parent, including the children that are leaving
+    fn layout_children(
+        fragment_store: &FragmentStore,
+        scheduler: &Scheduler,
+        parent: Fragment,
+        children: &FragmentChildren,
+    ) -> Vec<Idx> {
+        let mut layout_children: Vec<_> =
+            children.iter().map(|c| fragment_store.get(*c).assert_evaluated().layout_idx).collect();
+        for (child, index) in scheduler.leaving_children(parent) {
+            let layout_idx = fragment_store.get(child).assert_evaluated().layout_idx;
+            layout_children.insert(index.min(layout_children.len()), layout_idx);
+        }
+        layout_children
+    }
 }
 
 // The evaluator outputs nothing but rather communicates with the layouter with
@@ -296,19 +656,25 @@ impl EvaluatorInner {
 pub struct Evaluator {
     pub(crate) key_map: KeyMap,
     pub(crate) after_frame_callbacks: Vec<AfterFrameCallback>,
+    scheduler: Scheduler,
     fragment_store: FragmentStore,
     inner: EvaluatorInner,
     pub(crate) top_node: Idx,
+    last_compaction: Instant,
 }
 
 impl Evaluator {
+    // how often the memory of removed widgets is given back
+    const COMPACTION_INTERVAL: Duration = Duration::from_secs(10);
+
     pub fn new(
         vulkan_context: VulkanContext,
         top_node_frag: UnevaluatedFragment,
         layout_tree: &mut Layouter,
+        tree: Arc<PatchedTree>,
     ) -> Self {
         let mut evaluator =
-            Evaluator::empty(vulkan_context.clone(), unsafe { Idx::new_unchecked(1) }); // random dummy value
+            Evaluator::empty(vulkan_context.clone(), tree, unsafe { Idx::new_unchecked(1) }); // random dummy value
         let top_node = evaluator.fragment_store.add_empty_fragment();
         evaluator.fragment_store.add_fragment(top_node, || top_node_frag);
         let _root = evaluator.inner.evaluate_unconditional(
@@ -320,34 +686,113 @@ impl Evaluator {
                 evaluator.inner.tree.clone(),
                 &mut evaluator.fragment_store,
                 &mut evaluator.after_frame_callbacks,
+                &mut evaluator.scheduler,
                 &mut evaluator.key_map,
             ),
         );
         evaluator.top_node = evaluator.fragment_store.get(top_node).assert_evaluated().layout_idx;
         std::mem::drop(evaluator.fragment_store.dirty_args());
+        evaluator.fragment_store.render_trace = RenderTrace::from_env();
 
         evaluator
     }
 
-    fn empty(vulkan_context: VulkanContext, top_node: Idx) -> Self {
+    /// an evaluator on a vulkan device without a window, for the tests
+    #[cfg(test)]
+    pub(crate) fn headless(
+        top_node_frag: UnevaluatedFragment,
+        layout_tree: &mut Layouter,
+        tree: Arc<PatchedTree>,
+    ) -> Self {
+        let vulkan_context = crate::vulkano_render::render::headless_vulkan_context()
+            .expect("evaluating widgets needs a vulkan device");
+        Self::new(vulkan_context, top_node_frag, layout_tree, tree)
+    }
+
+    fn empty(vulkan_context: VulkanContext, tree: Arc<PatchedTree>, top_node: Idx) -> Self {
         Self {
             key_map: Default::default(),
             after_frame_callbacks: vec![],
+            scheduler: Default::default(),
             fragment_store: Default::default(),
-            inner: EvaluatorInner { vulkan_context, tree: Arc::new(Default::default()) },
+            inner: EvaluatorInner {
+                vulkan_context,
+                tree,
+                catching: 0,
+                journal: vec![],
+                reevaluated: 0,
+                #[cfg(feature = "devtools")]
+                evaluated: vec![],
+            },
             top_node,
+            last_compaction: Instant::now(),
         }
     }
 
     pub fn update(&mut self, layout_tree: &mut Layouter) -> bool {
-        self.inner.update(
+        let updated = self.inner.update(
             layout_tree,
             &mut self.fragment_store,
             &mut self.after_frame_callbacks,
+            &mut self.scheduler,
             &mut self.key_map,
-        )
+        );
+        // unmount callbacks run after the frame in which the widget disappeared
+        for callback in self.scheduler.take_unmounted() {
+            self.after_frame_callbacks.push(Box::new(move |context| callback(context)));
+        }
+        if self.last_compaction.elapsed() > Self::COMPACTION_INTERVAL {
+            self.compact(layout_tree);
+        }
+        updated
+    }
+
+    /// gives back the memory of the widgets and hooks that were removed
+    pub(crate) fn compact(&mut self, layout_tree: &mut Layouter) {
+        let _span = span!("compact");
+        self.fragment_store.compact();
+        self.key_map.compact();
+        self.inner.tree.compact();
+        layout_tree.compact();
+        self.last_compaction = Instant::now();
+    }
+
+    pub fn is_patched(&self) -> bool { self.inner.tree.is_patched() }
+
+    /// the number of widgets that were (re)evaluated since the last call
+    pub(crate) fn take_reevaluated(&mut self) -> usize {
+        std::mem::take(&mut self.inner.reevaluated)
+    }
+
+    /// the fragments that were (re)evaluated since the last call
+    #[cfg(feature = "devtools")]
+    pub(crate) fn take_evaluated(&mut self) -> Vec<Fragment> {
+        std::mem::take(&mut self.inner.evaluated)
     }
 
+    /// runs the callbacks of all timers that are due and delivers animation
+    /// frames
+    pub fn run_scheduled(&mut self, layout: &Layouter, scale_factor: &ScaleFactor) {
+        let now = Instant::now();
+        let timers = self.scheduler.due_timers(now);
+        let frames = self.scheduler.due_frames(now);
+        let leaving_progress = self.scheduler.leaving_progress(now);
+        let callback_context = self.callback_context(layout, scale_factor);
+        for (progress, t) in leaving_progress {
+            callback_context.shout(progress, Some(t));
+        }
+        for callback in timers {
+            callback(&callback_context);
+        }
+        for (callback, frame) in frames {
+            callback(&callback_context, frame);
+        }
+    }
+
+    /// the point in time the event loop has to wake up at, to run the next
+    /// timer or animation frame
+    pub fn next_deadline(&self) -> Option<Instant> { self.scheduler.next_deadline() }
+
     pub fn callback_context<'a>(
         &'a self,
         layout: &'a Layouter,
@@ -362,3 +807,432 @@ impl Evaluator {
         }
     }
 }
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        context::key::KeyPart,
+        ContextErrorBoundary,
+        ContextMeasure,
+        ContextMemoryStats,
+        Listenable,
+        ListenableCreate,
+        ListenableListen,
+        ListenableShout,
+        ListenableSpy,
+        MeasureError,
+        RenderObject,
+        Stores,
+        Vec2,
+    };
+    use rutter_layout::{layouts::*, Size};
+    use std::{
+        cell::{Cell, RefCell},
+        collections::HashMap,
+        rc::Rc,
+    };
+
+    // the number of evaluations and the hook value of every item, by its key
+    type Items = Rc<RefCell<HashMap<u32, (usize, usize)>>>;
+
+    fn item(
+        key: u32,
+        items: Items,
+        created: Rc<RefCell<usize>>,
+        tick: Listenable<u32>,
+    ) -> UnevaluatedFragment {
+        UnevaluatedFragment {
+            key: Default::default(),
+            gen: Some(Box::new(move |context: &mut WidgetContext| {
+                context.listen(tick);
+                // every new hook gets a different value
+                let state = context.listenable_with(|| {
+                    *created.borrow_mut() += 1;
+                    *created.borrow()
+                });
+                let state = context.tree.spy(state);
+                let mut items = items.borrow_mut();
+                let entry = items.entry(key).or_insert((0, state));
+                entry.0 += 1;
+                entry.1 = state;
+                FragmentInner::Leaf {
+                    render_object: RenderObject::None,
+                    layout: Box::new(SizedBox::new(Size { width: 10.0, height: 10.0 })),
+                }
+            })),
+        }
+    }
+
+    struct List {
+        stores: Stores,
+        order: Listenable<Vec<u32>>,
+        tick: Listenable<u32>,
+        items: Items,
+        // the fragments of the items of the last evaluation of the list
+        fragments: Rc<RefCell<Vec<(u32, Fragment)>>>,
+        list_evaluations: Rc<RefCell<usize>>,
+        layouter: Layouter,
+        evaluator: Evaluator,
+    }
+
+    impl List {
+        fn new(order: Vec<u32>) -> Self {
+            let mut stores = Stores::new();
+            let order = stores.listenable(order);
+            let tick = stores.listenable(0u32);
+            let items: Items = Default::default();
+            let fragments: Rc<RefCell<Vec<_>>> = Default::default();
+            let list_evaluations = Rc::new(RefCell::new(0));
+            let created = Rc::new(RefCell::new(0));
+
+            let (list_items, list_fragments, evaluations) =
+                (items.clone(), fragments.clone(), list_evaluations.clone());
+            let top_node = UnevaluatedFragment {
+                key: Default::default(),
+                gen: Some(Box::new(move |context: &mut WidgetContext| {
+                    *evaluations.borrow_mut() += 1;
+                    let parent = context.widget_local.key;
+                    let mut fragments = list_fragments.borrow_mut();
+                    fragments.clear();
+                    for value in context.listen(order) {
+                        let part = KeyPart::FragmentKey { widget_id: 0, location_id: 1, key: value };
+                        let fragment_store = &mut context.fragment_store;
+                        let key = context
+                            .key_map
+                            .key_with(parent, part, || fragment_store.add_empty_fragment().into());
+                        let fragment = context.fragment_store.fragment(key);
+                        let fragment = context.fragment_store.add_fragment(fragment, || {
+                            let item = item(value, list_items.clone(), created.clone(), tick);
+                            UnevaluatedFragment { key, ..item }
+                        });
+                        fragments.push((value, fragment));
+                    }
+                    FragmentInner::Node {
+                        children: fragments.iter().map(|(_, fragment)| *fragment).collect(),
+                        layout: Box::new(Column {
+                            cross_axis_alignment: CrossAxisAlignment::Start,
+                            main_axis_alignment: MainAxisAlignment::Start,
+                            main_axis_size: MainAxisSize::Max,
+                        }),
+                        is_clipper: false,
+                        subpass: None,
+                    }
+                })),
+            };
+
+            let mut layouter = Layouter::new();
+            let tree = stores.tree.clone();
+            let evaluator = Evaluator::headless(top_node, &mut layouter, tree);
+            let mut list = Self {
+                stores,
+                order,
+                tick,
+                items,
+                fragments,
+                list_evaluations,
+                layouter,
+                evaluator,
+            };
+            list.layout();
+            list
+        }
+
+        fn layout(&mut self) {
+            self.layouter.do_layout(self.evaluator.top_node, Vec2::new(100.0, 100.0));
+        }
+
+        fn set_order(&mut self, order: &[u32]) {
+            self.stores.shout(self.order, order.to_vec());
+            self.evaluator.update(&mut self.layouter);
+            self.layout();
+        }
+
+        fn evaluate_items(&mut self) {
+            let tick = self.stores.spy(self.tick);
+            self.stores.shout(self.tick, tick + 1);
+            self.evaluator.update(&mut self.layouter);
+            self.layout();
+        }
+
+        fn fragment(&self, key: u32) -> Fragment {
+            self.fragments.borrow().iter().find(|(value, _)| *value == key).unwrap().1
+        }
+
+        fn evaluations(&self, key: u32) -> usize { self.items.borrow()[&key].0 }
+
+        fn state(&self, key: u32) -> usize { self.items.borrow()[&key].1 }
+
+        // the keys of the items, in the order in which they are laid out
+        fn laid_out(&self) -> Vec<u32> {
+            let mut items: Vec<_> = self
+                .fragments
+                .borrow()
+                .iter()
+                .map(|(value, fragment)| {
+                    let frag = self.evaluator.fragment_store.get(*fragment).assert_evaluated();
+                    let (rect, _) = self.layouter.get_positioned_logical(frag.layout_idx);
+                    (rect.pos.y as u32, *value)
+                })
+                .collect();
+            items.sort_unstable();
+            assert!(items.iter().enumerate().all(|(i, (y, _))| *y == i as u32 * 10));
+            items.into_iter().map(|(_, value)| value).collect()
+        }
+    }
+
+    #[test]
+    #[ignore = "evaluating widgets needs a vulkan device"]
+    fn reorder_keyed_children() {
+        let mut list = List::new(vec![0, 1, 2, 3]);
+        let states: Vec<_> = (0..4).map(|key| list.state(key)).collect();
+        assert_eq!(list.laid_out(), vec![0, 1, 2, 3]);
+
+        // shuffling only moves the items
+        list.set_order(&[3, 1, 0, 2]);
+        assert_eq!(*list.list_evaluations.borrow(), 2);
+        assert_eq!(list.laid_out(), vec![3, 1, 0, 2]);
+        assert!((0..4).all(|key| list.evaluations(key) == 1));
+
+        // only the inserted item is evaluated
+        list.set_order(&[3, 4, 1, 0, 2]);
+        assert_eq!(list.laid_out(), vec![3, 4, 1, 0, 2]);
+        assert!((0..5).all(|key| list.evaluations(key) == 1));
+
+        list.set_order(&[2, 4, 0]);
+        assert_eq!(list.laid_out(), vec![2, 4, 0]);
+
+        // the remaining items still have their hooks
+        list.evaluate_items();
+        assert!([0, 2, 4].iter().all(|key| list.evaluations(*key) == 2));
+        assert_eq!(list.state(0), states[0]);
+        assert_eq!(list.state(2), states[2]);
+        assert_eq!(list.evaluations(1), 1);
+
+        // removed items start over when they come back
+        list.set_order(&[1, 0, 2, 4]);
+        assert_eq!(list.laid_out(), vec![1, 0, 2, 4]);
+        assert_ne!(list.state(1), states[1]);
+        assert_eq!(list.evaluations(0), 2);
+    }
+
+    #[test]
+    #[ignore = "evaluating widgets needs a vulkan device"]
+    fn stale_fragments() {
+        let mut list = List::new(vec![0, 1]);
+        let removed = list.fragment(1);
+        let scale_factor = ScaleFactor(1.0);
+        let context = list.evaluator.callback_context(&list.layouter, &scale_factor);
+        assert!(context.measure(removed).is_ok());
+
+        list.set_order(&[0]);
+        list.set_order(&[0, 2]);
+        let added = list.fragment(2);
+        // the new item reuses the slot of the removed one
+        assert_eq!(added, removed);
+        let context = list.evaluator.callback_context(&list.layouter, &scale_factor);
+        assert_eq!(context.measure(removed).err(), Some(MeasureError::Stale));
+        assert!(context.measure(added).is_ok());
+    }
+
+    #[test]
+    #[ignore = "evaluating widgets needs a vulkan device"]
+    fn bounded_memory() {
+        let mut list = List::new(vec![]);
+        // many more different keys than are alive at the same time
+        let churn = |list: &mut List, rounds: std::ops::Range<u32>| {
+            for round in rounds {
+                list.set_order(&(round * 10..round * 10 + round % 7 + 3).collect::<Vec<_>>());
+            }
+            list.set_order(&[0, 1, 2, 3]);
+            list.evaluator.compact(&mut list.layouter);
+            let scale_factor = ScaleFactor(1.0);
+            list.evaluator.callback_context(&list.layouter, &scale_factor).memory_stats()
+        };
+
+        let warm = churn(&mut list, 1..100);
+        let soaked = churn(&mut list, 100..10_000);
+        assert_eq!(soaked.fragments.live, warm.fragments.live);
+        assert_eq!(soaked.keys.live, warm.keys.live);
+        assert_eq!(soaked.hooks.live, warm.hooks.live);
+        assert_eq!(soaked.layout_nodes.live, warm.layout_nodes.live);
+        for stats in [soaked.fragments, soaked.keys, soaked.hooks, soaked.layout_nodes] {
+            assert!(stats.capacity <= 64, "{:?}", soaked);
+        }
+
+        // the memory needed for a long list is given back once it is short again
+        list.set_order(&(0..1000).collect::<Vec<_>>());
+        let compacted = churn(&mut list, 0..0);
+        assert_eq!(compacted, soaked);
+    }
+
+    fn node(children: FragmentChildren) -> FragmentInner {
+        FragmentInner::Node {
+            children,
+            layout: Box::new(Transparent),
+            is_clipper: false,
+            subpass: None,
+        }
+    }
+
+    fn child(
+        context: &mut WidgetContext,
+        gen: impl Fn(&mut WidgetContext) -> FragmentInner + 'static,
+    ) -> Fragment {
+        let parent = context.widget_local.key;
+        let part = KeyPart::Fragment { widget_id: 0, location_id: 1 };
+        let fragment_store = &mut context.fragment_store;
+        let key =
+            context.key_map.key_with(parent, part, || fragment_store.add_empty_fragment().into());
+        let fragment = context.fragment_store.fragment(key);
+        context
+            .fragment_store
+            .add_fragment(fragment, || UnevaluatedFragment { key, gen: Some(Box::new(gen)) })
+    }
+
+    // an error boundary around a widget with a leaf that panics on demand
+    struct Boundary {
+        stores: Stores,
+        tick: Listenable<u32>,
+        panicking: Rc<Cell<bool>>,
+        error: Rc<Cell<Option<Listenable<Option<String>>>>>,
+        // the number of hooks the leaf created
+        created: Rc<Cell<usize>>,
+        layouter: Layouter,
+        evaluator: Evaluator,
+    }
+
+    impl Boundary {
+        fn new(panicking: bool) -> Self {
+            let mut stores = Stores::new();
+            let tick = stores.listenable(0u32);
+            let panicking = Rc::new(Cell::new(panicking));
+            let error: Rc<Cell<Option<_>>> = Default::default();
+            let created = Rc::new(Cell::new(0));
+
+            let leaf = {
+                let (panicking, created) = (panicking.clone(), created.clone());
+                move |context: &mut WidgetContext| {
+                    context.listen(tick);
+                    context.listenable_with(|| created.set(created.get() + 1));
+                    if panicking.get() {
+                        panic!("the leaf panicked");
+                    }
+                    FragmentInner::Leaf {
+                        render_object: RenderObject::None,
+                        layout: Box::new(SizedBox::new(Size { width: 10.0, height: 10.0 })),
+                    }
+                }
+            };
+            let leaf = Rc::new(leaf);
+            let boundary = {
+                let error = error.clone();
+                move |context: &mut WidgetContext| {
+                    let caught = context.catch_panics();
+                    error.set(Some(caught));
+                    match context.listen(caught) {
+                        None => {
+                            let leaf = leaf.clone();
+                            let widget = child(context, move |context| {
+                                context.listenable(0u8);
+                                let leaf = leaf.clone();
+                                node(child(context, move |context| leaf(context)).into())
+                            });
+                            node(widget.into())
+                        }
+                        Some(_) => node(Default::default()),
+                    }
+                }
+            };
+            let boundary = Rc::new(boundary);
+            let top_node = UnevaluatedFragment {
+                key: Default::default(),
+                gen: Some(Box::new(move |context: &mut WidgetContext| {
+                    let boundary = boundary.clone();
+                    node(child(context, move |context| boundary(context)).into())
+                })),
+            };
+
+            let mut layouter = Layouter::new();
+            let tree = stores.tree.clone();
+            let evaluator = Evaluator::headless(top_node, &mut layouter, tree);
+            let mut boundary =
+                Self { stores, tick, panicking, error, created, layouter, evaluator };
+            boundary.update();
+            boundary
+        }
+
+        // applies the shouts, including the one of the caught error
+        fn update(&mut self) {
+            self.evaluator.update(&mut self.layouter);
+            self.evaluator.update(&mut self.layouter);
+            self.layouter.do_layout(self.evaluator.top_node, Vec2::new(100.0, 100.0));
+        }
+
+        fn evaluate_leaf(&mut self) {
+            let tick = self.stores.spy(self.tick);
+            self.stores.shout(self.tick, tick + 1);
+            self.update();
+        }
+
+        fn error(&self) -> Option<String> { self.stores.spy(self.error.get().unwrap()) }
+
+        fn retry(&mut self) {
+            self.stores.shout(self.error.get().unwrap(), None);
+            self.update();
+        }
+
+        // the number of live fragments, keys, hooks and layout nodes
+        fn live(&self) -> [usize; 4] {
+            let scale_factor = ScaleFactor(1.0);
+            let context = self.evaluator.callback_context(&self.layouter, &scale_factor);
+            let stats = context.memory_stats();
+            [stats.fragments, stats.keys, stats.hooks, stats.layout_nodes].map(|stats| stats.live)
+        }
+    }
+
+    #[test]
+    #[ignore = "evaluating widgets needs a vulkan device"]
+    fn error_boundary() {
+        let mut boundary = Boundary::new(false);
+        let healthy = boundary.live();
+        assert_eq!(boundary.error(), None);
+        assert_eq!(boundary.created.get(), 1);
+
+        // a panic while re-evaluating removes the subtree below the boundary
+        boundary.panicking.set(true);
+        boundary.evaluate_leaf();
+        assert_eq!(boundary.error().as_deref(), Some("the leaf panicked"));
+        let caught = boundary.live();
+        // the widget and the leaf, with one hook each
+        assert_eq!(caught.map(|live| live + 2), healthy);
+
+        // the same as if it panicked in the first evaluation
+        let first = Boundary::new(true);
+        assert_eq!(first.error().as_deref(), Some("the leaf panicked"));
+        assert_eq!(first.live(), caught);
+
+        // retrying creates the subtree again, with new hooks
+        boundary.retry();
+        assert_eq!(boundary.error().as_deref(), Some("the leaf panicked"));
+        assert_eq!(boundary.live(), caught);
+        boundary.panicking.set(false);
+        boundary.retry();
+        assert_eq!(boundary.error(), None);
+        assert_eq!(boundary.created.get(), 3);
+        assert_eq!(boundary.live(), healthy);
+        boundary.evaluate_leaf();
+        assert_eq!(boundary.created.get(), 3);
+        assert_eq!(boundary.live(), healthy);
+    }
+}
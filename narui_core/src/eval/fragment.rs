@@ -34,12 +34,12 @@ the output of this stage is the visual output :). profit!
 
  */
 
-/// Fragment is merely a reference (for performance reasons)
+/// Fragment is merely a reference (for performance reasons). Besides the
+/// index it carries the generation of its slot in the `FragmentStore`, so a
+/// handle that outlived its widget can be told apart from the widget that
+/// reuses the slot. Use `FragmentStore::fragment` to get the handle of a key.
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
-pub struct Fragment(pub(crate) u32);
-impl From<Key> for Fragment {
-    fn from(key: Key) -> Self { Fragment(key.0) }
-}
+pub struct Fragment(pub(crate) u32, pub(crate) u32);
 impl From<Fragment> for Idx {
     fn from(fragment: Fragment) -> Self { unsafe { Idx::new_unchecked(fragment.0 as _) } }
 }
@@ -49,9 +49,6 @@ impl From<Fragment> for Key {
 impl From<Fragment> for FragmentChildren {
     fn from(fragment: Fragment) -> Self { smallvec![fragment] }
 }
-impl From<Idx> for Fragment {
-    fn from(idx: Idx) -> Self { Fragment(idx.get() as _) }
-}
 pub type FragmentChildren = SmallVec<[Fragment; 8]>;
 
 
@@ -0,0 +1,204 @@
+//! Dumps the live widget tree as JSON for debugging.
+
+use crate::{
+    context::{
+        context::{FragmentStore, MaybeEvaluatedFragment::Evaluated},
+        key::{internal::WIDGET_INFO, KeyMap, KeyPart},
+        patched_tree::PatchedTree,
+    },
+    eval::layout::Layouter,
+    geom::{Rect, Vec2},
+    Color,
+    Fragment,
+    Json,
+    Key,
+};
+use freelist::Idx;
+use hashbrown::HashMap;
+use std::any::Any;
+
+pub(crate) fn inspect(
+    tree: &PatchedTree,
+    key_map: &KeyMap,
+    fragment_store: &FragmentStore,
+    layout: &Layouter,
+) -> Json {
+    let root = fragment_store.fragment(Key::ROOT);
+    let layout_info = match fragment_store.get(root) {
+        Evaluated(frag) => layout.layout_info(frag.layout_idx),
+        _ => return Json::Null,
+    };
+    Inspector { tree, key_map, fragment_store, layout_info }.fragment(root)
+}
+
+/// Dumps the subtrees of the given (re)evaluated fragments, leaving out the
+/// ones that are already part of another dumped subtree and the ones that were
+/// removed since.
+#[cfg(feature = "devtools")]
+pub(crate) fn inspect_changes(
+    tree: &PatchedTree,
+    key_map: &KeyMap,
+    fragment_store: &FragmentStore,
+    layout: &Layouter,
+    evaluated: &[Fragment],
+) -> Vec<Json> {
+    use hashbrown::HashSet;
+
+    fn mark_children(fragment_store: &FragmentStore, frag: Fragment, seen: &mut HashSet<Fragment>) {
+        if let Some(Evaluated(frag)) = fragment_store.try_get(frag) {
+            for child in &frag.children {
+                if seen.insert(*child) {
+                    mark_children(fragment_store, *child, seen);
+                }
+            }
+        }
+    }
+
+    let mut covered = HashSet::new();
+    for frag in evaluated {
+        mark_children(fragment_store, *frag, &mut covered);
+    }
+
+    let root = fragment_store.fragment(Key::ROOT);
+    let layout_info = match fragment_store.get(root) {
+        Evaluated(frag) => layout.layout_info(frag.layout_idx),
+        _ => return vec![],
+    };
+    let inspector = Inspector { tree, key_map, fragment_store, layout_info };
+    let mut dumped = HashSet::new();
+    evaluated
+        .iter()
+        .filter(|frag| !covered.contains(*frag) && dumped.insert(**frag))
+        .filter(|frag| matches!(fragment_store.try_get(**frag), Some(Evaluated(_))))
+        .map(|frag| inspector.fragment(*frag))
+        .collect()
+}
+
+struct Inspector<'a> {
+    tree: &'a PatchedTree,
+    key_map: &'a KeyMap,
+    fragment_store: &'a FragmentStore,
+    layout_info: HashMap<Idx, (Rect, u32)>,
+}
+
+impl<'a> Inspector<'a> {
+    fn fragment(&self, frag: Fragment) -> Json {
+        let info = &self.fragment_store.data[frag.into()];
+        let fragment = info.fragment.as_ref().unwrap();
+        let key = fragment.key();
+
+        let (name, location, arg_names) = match self.key_map.key_tail(key) {
+            KeyPart::Root => ("toplevel".to_string(), String::new(), vec![]),
+            KeyPart::Fragment { widget_id, .. }
+            | KeyPart::FragmentKey { widget_id, .. }
+            | KeyPart::FragmentInternedKey { widget_id, .. } => {
+                let widget_info = &WIDGET_INFO.read()[widget_id as usize];
+                (widget_info.name.clone(), widget_info.loc.clone(), widget_info.arg_names.clone())
+            }
+        };
+
+        let args = match (&info.args, info.arg_values) {
+            (Some(args), Some(values)) => Json::Object(
+                arg_names
+                    .into_iter()
+                    .zip(values(&**args))
+                    .enumerate()
+                    .map(|(i, (name, arg))| {
+                        let value = info.arg_formatters.get(i).and_then(|format| format(arg));
+                        (name, value.into())
+                    })
+                    .collect(),
+            ),
+            _ => Json::Null,
+        };
+
+        let hooks = self
+            .tree
+            .hooks_of(key)
+            .into_iter()
+            .map(|hook| {
+                let value = self.tree.get_patched(hook).and_then(|value| format_value(&**value));
+                let index = hook.0 .1;
+                let external = index & 0b1000_0000_0000_0000 != 0;
+                #[allow(unused_mut)]
+                let mut fields = vec![
+                    ("index", Json::from((index & 0b0111_1111_1111_1111) as u32)),
+                    ("external", external.into()),
+                    ("value", value.into()),
+                ];
+                #[cfg(debug_assertions)]
+                if let Some(site) = info.hook_sites.get(index).filter(|_| !external) {
+                    fields.push(("type", site.type_name.into()));
+                    fields.push(("location", site.location.to_string().into()));
+                }
+                Json::object(fields)
+            })
+            .collect();
+
+        let (layout, children) = match fragment {
+            Evaluated(frag) => (
+                self.layout_info.get(&frag.layout_idx).map(|(rect, z_index)| {
+                    Json::object([
+                        ("x", rect.pos.x.into()),
+                        ("y", rect.pos.y.into()),
+                        ("width", rect.size.x.into()),
+                        ("height", rect.size.y.into()),
+                        ("z_index", (*z_index).into()),
+                    ])
+                }),
+                frag.children.iter().map(|child| self.fragment(*child)).collect(),
+            ),
+            _ => (None, vec![]),
+        };
+
+        Json::object([
+            ("id", frag.0.into()),
+            ("key", format!("{:?}", self.key_map.key_debug(key)).into()),
+            ("widget", name.into()),
+            ("location", location.into()),
+            ("evaluated", matches!(fragment, Evaluated(_)).into()),
+            ("args", args),
+            ("hooks", Json::Array(hooks)),
+            ("layout", layout.into()),
+            ("children", Json::Array(children)),
+        ])
+    }
+}
+
+// hooks are stored type erased, so we can only show the values of some common
+// types
+fn format_value(value: &dyn Any) -> Option<String> {
+    macro_rules! try_format {
+        ($($ty:ty),*) => {
+            $(if let Some(value) = value.downcast_ref::<$ty>() {
+                return Some(format!("{:?}", value));
+            })*
+        };
+    }
+    try_format!(
+        bool,
+        i8,
+        i16,
+        i32,
+        i64,
+        isize,
+        u8,
+        u16,
+        u32,
+        u64,
+        usize,
+        f32,
+        f64,
+        String,
+        &'static str,
+        Vec2,
+        Rect,
+        Color,
+        Option<bool>,
+        Option<i32>,
+        Option<f32>,
+        Option<String>,
+        Option<Vec2>
+    );
+    None
+}
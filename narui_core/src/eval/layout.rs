@@ -1,13 +1,27 @@
 use crate::{
     geom::{Rect, Vec2},
+    hooks::{Animatable, Curve},
+    util::{memory_stats::SlotStats, span::span},
     Fragment,
     RenderObject,
     SubPassSetup,
 };
 use derivative::Derivative;
 use freelist::Idx;
-use rutter_layout::{layouter::LayoutIterDirection, BoxConstraints, Layout, Offset};
-use std::ops::Deref;
+use hashbrown::{HashMap, HashSet};
+use rutter_layout::{
+    layouter::{LayoutIterDirection, LayoutableChildren},
+    layouts::Transparent,
+    BoxConstraints,
+    Layout,
+    Offset,
+    Size,
+};
+use std::{
+    any::Any,
+    ops::Deref,
+    time::{Duration, Instant},
+};
 use winit::dpi::PhysicalPosition;
 
 // type to create a seperation between logical and physical pixels
@@ -66,6 +80,10 @@ impl From<PhysicalPosition<f64>> for Physical<Vec2> {
 #[repr(transparent)]
 pub struct ScaleFactor(pub f32);
 
+/// the z index in front of every widget, for debugging overlays
+#[cfg(any(feature = "debug_bounds", feature = "devtools"))]
+pub(crate) const OVERLAY_Z_INDEX: u32 = u16::MAX as u32;
+
 /// PositionedElement is the main output data structure of the Layouting
 /// pass. It attaches Position & other information to the RenderObject.
 #[derive(Debug)]
@@ -143,17 +161,210 @@ impl Deref for LayoutWithData {
     fn deref(&self) -> &Self::Target { &*self.layout }
 }
 
+/// LayoutAnimation lays out its single child like `Transparent`, but when the
+/// resulting rect changes, the rendered rect of the subtree is moved and
+/// scaled from the old rect to the new one instead of jumping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutAnimation {
+    pub duration: Duration,
+    pub curve: Curve,
+}
+
+impl Layout for LayoutAnimation {
+    fn layout(&self, constraint: BoxConstraints, children: LayoutableChildren) -> (Size, u32) {
+        Transparent.layout(constraint, children)
+    }
+
+    fn query<'a>(
+        &'a self,
+        query: &dyn Any,
+        children: LayoutableChildren<'a>,
+    ) -> Option<&'a dyn Any> {
+        Transparent.query(query, children)
+    }
+}
+
+// the rects are relative to the layout rect of the closest animated node above,
+// as that one already moves the whole subtree when it animates
+#[derive(Debug)]
+struct LayoutAnimationState {
+    animation: LayoutAnimation,
+    from: Rect,
+    // None until the node was laid out for the first time
+    to: Option<Rect>,
+    start: Instant,
+    current: Rect,
+}
+
+// maps the layouted rects of a subtree to the rects they are rendered at
+#[derive(Debug, Clone, Copy)]
+struct RenderTransform {
+    scale: Vec2,
+    translate: Vec2,
+}
+
+impl RenderTransform {
+    const IDENTITY: Self =
+        Self { scale: Vec2 { x: 1.0, y: 1.0 }, translate: Vec2 { x: 0.0, y: 0.0 } };
+
+    fn between(from: Rect, to: Rect) -> Self {
+        let scale_for = |from: f32, to: f32| if from == 0.0 { 1.0 } else { to / from };
+        let scale = Vec2::new(scale_for(from.size.x, to.size.x), scale_for(from.size.y, to.size.y));
+        Self { scale, translate: to.pos - from.pos * scale }
+    }
+
+    // first applies self, then outer
+    fn then(self, outer: Self) -> Self {
+        Self {
+            scale: self.scale * outer.scale,
+            translate: self.translate * outer.scale + outer.translate,
+        }
+    }
+
+    fn apply(&self, rect: Rect) -> Rect {
+        Rect { pos: rect.pos * self.scale + self.translate, size: rect.size * self.scale }
+    }
+}
+
 #[derive(Debug, Derivative)]
 #[derivative(Default(new = "true"))]
 pub struct Layouter {
     layouter: rutter_layout::layouter::Layouter<LayoutWithData>,
+    animations: HashMap<Idx, LayoutAnimationState>,
+    // subtrees that are rendered but don't receive input
+    inert: HashSet<Idx>,
 }
 
 impl Layouter {
+    pub(crate) fn compact(&mut self) { self.layouter.shrink() }
+
+    pub(crate) fn memory_stats(&self) -> SlotStats {
+        SlotStats { live: self.layouter.node_count(), capacity: self.layouter.node_capacity() }
+    }
+
     pub fn do_layout(&mut self, top: Idx, size: Vec2) {
+        let _span = span!("layout");
         self.layouter.do_layout(BoxConstraints::tight_for(size.into()), Offset::zero(), top);
     }
 
+    /// advances the layout animations to `now`, has to be called after
+    /// `do_layout`. Returns whether any of them is still in motion.
+    pub fn animate(&mut self, now: Instant) -> bool {
+        let mut in_motion = false;
+        let targets: Vec<_> =
+            self.animations.keys().map(|idx| (*idx, self.animation_target(*idx))).collect();
+        for (idx, target) in targets {
+            // nodes that were not laid out yet start animating once they are
+            let target = match target {
+                Some(target) => target,
+                None => continue,
+            };
+            let state = self.animations.get_mut(&idx).unwrap();
+            match state.to {
+                None => {
+                    // widgets that appear are not animated
+                    state.from = target;
+                    state.current = target;
+                }
+                Some(to) if to != target => {
+                    state.from = state.current;
+                    state.start = now;
+                }
+                _ => {}
+            }
+            state.to = Some(target);
+
+            let duration = state.animation.duration.as_secs_f32();
+            let t = if duration == 0.0 {
+                1.0
+            } else {
+                ((now - state.start).as_secs_f32() / duration).min(1.0)
+            };
+            if t < 1.0 {
+                state.current = state.from.lerp(&target, state.animation.curve.transform(t));
+                in_motion = true;
+            } else {
+                state.current = target;
+            }
+        }
+        in_motion
+    }
+
+    // the layout rect of an animated node, relative to the one of the closest
+    // animated node above it
+    fn animation_target(&self, idx: Idx) -> Option<Rect> {
+        let (offset, size, _) = self.layouter.try_get_layout(idx)?;
+        let mut rect = Rect { pos: offset.into(), size: size.into() };
+        let mut parent = self.layouter.parent(idx);
+        while let Some(idx) = parent {
+            if self.animations.contains_key(&idx) {
+                let (origin, ..) = self.layouter.try_get_layout(idx)?;
+                rect.pos = rect.pos - Vec2::from(origin);
+                break;
+            }
+            parent = self.layouter.parent(idx);
+        }
+        Some(rect)
+    }
+
+    pub(crate) fn set_inert(&mut self, idx: Idx, inert: bool) {
+        if inert {
+            self.inert.insert(idx);
+        } else {
+            self.inert.remove(&idx);
+        }
+    }
+
+    fn set_animation(&mut self, idx: Idx, animation: Option<LayoutAnimation>) {
+        match animation {
+            Some(animation) => {
+                self.animations
+                    .entry(idx)
+                    .and_modify(|state| state.animation = animation)
+                    .or_insert_with(|| LayoutAnimationState {
+                        animation,
+                        from: Rect::zero(),
+                        to: None,
+                        start: Instant::now(),
+                        current: Rect::zero(),
+                    });
+            }
+            None => {
+                self.animations.remove(&idx);
+            }
+        }
+    }
+
+    /// the logical layout rect and z-index of every node below top, for
+    /// debugging tools
+    pub(crate) fn layout_info(&self, top: Idx) -> HashMap<Idx, (Rect, u32)> {
+        use LayoutIterDirection::*;
+
+        let mut info = HashMap::new();
+        let mut parent_z_index = 0;
+        let mut last_z_index_offset = 0;
+        for (layout_item, direction_that_led_here) in self.layouter.iter(top) {
+            let z_index = match direction_that_led_here {
+                Down => {
+                    parent_z_index += last_z_index_offset;
+                    parent_z_index
+                }
+                Right => parent_z_index + layout_item.z_index_offset,
+                Up => {
+                    let z_index = parent_z_index;
+                    parent_z_index -= layout_item.z_index_offset;
+                    z_index
+                }
+            };
+            last_z_index_offset = layout_item.z_index_offset;
+            if direction_that_led_here != Up {
+                let rect = Rect { pos: layout_item.pos.into(), size: layout_item.size.into() };
+                info.insert(layout_item.idx, (rect, z_index));
+            }
+        }
+        info
+    }
+
     pub fn iter_layouted_physical(
         &self,
         top: Idx,
@@ -186,8 +397,41 @@ impl Layouter {
         let mut clipper_stack = Vec::with_capacity(32);
         let mut last_clipper = None;
 
+        // the transforms of the animated nodes above and their layout positions
+        let mut transform_stack: Vec<(RenderTransform, Vec2)> = Vec::new();
+        let mut inert_depth = 0;
+
         self.layouter.iter(top).filter_map(move |(layout_item, direction_that_led_here)| {
-            let current_rect = Rect { pos: layout_item.pos.into(), size: layout_item.size.into() };
+            let layout_rect = Rect { pos: layout_item.pos.into(), size: layout_item.size.into() };
+            let (parent_transform, origin) = transform_stack
+                .last()
+                .cloned()
+                .unwrap_or((RenderTransform::IDENTITY, Vec2::zero()));
+            let animation =
+                self.animations.get(&layout_item.idx).filter(|animation| animation.to.is_some());
+            let transform = match (direction_that_led_here, animation) {
+                (Down | Right, Some(animation)) => {
+                    let current = Rect { pos: animation.current.pos + origin, ..animation.current };
+                    let transform =
+                        RenderTransform::between(layout_rect, current).then(parent_transform);
+                    if layout_item.has_children {
+                        transform_stack.push((transform, layout_rect.pos));
+                    }
+                    transform
+                }
+                (Up, Some(_)) => transform_stack.pop().unwrap().0,
+                (_, None) => parent_transform,
+            };
+            let current_rect = transform.apply(layout_rect);
+
+            let is_inert_node = self.inert.contains(&layout_item.idx);
+            let inert = inert_depth > 0 || is_inert_node;
+            if is_inert_node && layout_item.has_children {
+                match direction_that_led_here {
+                    Down | Right => inert_depth += 1,
+                    Up => inert_depth -= 1,
+                }
+            }
             let z_index;
             let preliminary_clipper = last_clipper.or_else(|| clipper_stack.last().cloned());
             let clip_fn = |rect: Rect| {
@@ -241,9 +485,16 @@ impl Layouter {
                         }
                     })
                 })
+                .filter(|element| {
+                    !(inert
+                        && matches!(
+                            element,
+                            RenderObjectOrSubPass::RenderObject(RenderObject::Input { .. })
+                        ))
+                })
                 .map(|element| {
                     let positioned_render_object = PositionedElement {
-                        rect: Rect { pos: layout_item.pos.into(), size: layout_item.size.into() },
+                        rect: current_rect,
                         z_index,
                         element,
                         clipping_rect: last_clipper.or_else(|| clipper_stack.last().cloned()),
@@ -260,7 +511,7 @@ impl Layouter {
             if direction != LayoutIterDirection::Up {
                 let positioned_render_object = PositionedElement {
                     rect: Rect { pos: layout_item.pos.into(), size: layout_item.size.into() },
-                    z_index: 0,
+                    z_index: OVERLAY_Z_INDEX,
                     element: RenderObjectOrSubPass::RenderObject(&RenderObject::DebugRect),
                     clipping_rect: None,
                 };
@@ -282,7 +533,16 @@ impl LayoutTree for Layouter {
         subpass: Option<SubPassSetup>,
         key: Fragment,
     ) -> Idx {
-        self.layouter.add_node(LayoutWithData { layout, render_object, is_clipper, subpass, key })
+        let animation = layout.as_any().downcast_ref::<LayoutAnimation>().copied();
+        let idx = self.layouter.add_node(LayoutWithData {
+            layout,
+            render_object,
+            is_clipper,
+            subpass,
+            key,
+        });
+        self.set_animation(idx, animation);
+        idx
     }
 
     fn set_node(
@@ -294,11 +554,17 @@ impl LayoutTree for Layouter {
         subpass: Option<SubPassSetup>,
         key: Fragment,
     ) {
+        let animation = layout.as_any().downcast_ref::<LayoutAnimation>().copied();
         self.layouter
             .set_node(idx, LayoutWithData { layout, render_object, is_clipper, subpass, key });
+        self.set_animation(idx, animation);
     }
 
-    fn remove_node(&mut self, idx: Idx) { self.layouter.remove(idx); }
+    fn remove_node(&mut self, idx: Idx) {
+        self.animations.remove(&idx);
+        self.inert.remove(&idx);
+        self.layouter.remove(idx);
+    }
 
     fn set_children(&mut self, parent: Idx, children: impl Iterator<Item = Idx>) {
         self.layouter.set_children(parent, children)
@@ -318,3 +584,68 @@ impl LayoutTree for Layouter {
         (rect.to_physical(scale_factor), obj)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rutter_layout::layouts::{Align, Alignment, EdgeInsets, Padding, SizedBox};
+
+    // keeps the size the same, so the animations only move their subtrees
+    fn padding(left: f32) -> Box<dyn Layout> {
+        Box::new(Padding::new(EdgeInsets { left, right: 200.0 - left, top: 0.0, bottom: 0.0 }))
+    }
+
+    fn node(layouter: &mut Layouter, layout: Box<dyn Layout>, children: &[Idx]) -> Idx {
+        let idx = layouter.add_node(layout, None, false, None, Fragment(1, 0));
+        layouter.set_children(idx, children.iter().copied());
+        idx
+    }
+
+    #[test]
+    fn nested_animations() {
+        let mut layouter = Layouter::new();
+        let linear = LayoutAnimation { duration: Duration::from_secs(1), curve: Curve::Linear };
+        let leaf = layouter.add_node(
+            Box::new(SizedBox::new(Size { width: 10.0, height: 10.0 })),
+            Some(RenderObject::DebugRect),
+            false,
+            None,
+            Fragment(1, 0),
+        );
+        let inner = node(&mut layouter, Box::new(linear), &[leaf]);
+        let inner_padding = node(&mut layouter, padding(10.0), &[inner]);
+        let outer = node(&mut layouter, Box::new(linear), &[inner_padding]);
+        let outer_padding = node(&mut layouter, padding(0.0), &[outer]);
+        let top = node(&mut layouter, Box::new(Align::new(Alignment::top_left())), &[outer_padding]);
+        // an animated node that is not part of the tree is never laid out
+        node(&mut layouter, Box::new(linear), &[]);
+
+        let start = Instant::now();
+        let at = |layouter: &mut Layouter, millis| {
+            layouter.do_layout(top, Vec2::new(500.0, 500.0));
+            layouter.animate(start + Duration::from_millis(millis));
+            let (_, leaf) = layouter.iter_layouted(top).find(|(idx, _)| *idx == leaf).unwrap();
+            leaf.rect.pos.x
+        };
+        assert_eq!(at(&mut layouter, 0), 10.0);
+
+        // the leaf moves along with the outer animation, the inner one stays put
+        layouter.set_node(outer_padding, padding(100.0), None, false, None, Fragment(1, 0));
+        assert_eq!(at(&mut layouter, 0), 10.0);
+        assert_eq!(at(&mut layouter, 500), 60.0);
+        assert_eq!(at(&mut layouter, 1000), 110.0);
+
+        // the inner animation moves the leaf relative to the outer one
+        layouter.set_node(inner_padding, padding(30.0), None, false, None, Fragment(1, 0));
+        assert_eq!(at(&mut layouter, 1000), 110.0);
+        assert_eq!(at(&mut layouter, 1500), 120.0);
+        assert_eq!(at(&mut layouter, 2000), 130.0);
+
+        // both at once
+        layouter.set_node(outer_padding, padding(0.0), None, false, None, Fragment(1, 0));
+        layouter.set_node(inner_padding, padding(10.0), None, false, None, Fragment(1, 0));
+        assert_eq!(at(&mut layouter, 2000), 130.0);
+        assert_eq!(at(&mut layouter, 2500), 70.0);
+        assert_eq!(at(&mut layouter, 3000), 10.0);
+    }
+}
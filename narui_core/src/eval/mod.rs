@@ -1,3 +1,5 @@
 pub(crate) mod delta_eval;
 pub(crate) mod fragment;
+pub(crate) mod inspector;
 pub(crate) mod layout;
+pub(crate) mod render_trace;
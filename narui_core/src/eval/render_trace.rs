@@ -0,0 +1,203 @@
+//! Records why widgets were re-evaluated. Enabled by setting the
+//! `NARUI_TRACE_RERENDERS` environment variable.
+
+use crate::{
+    context::{
+        key::{internal::WIDGET_INFO, KeyMap, KeyPart},
+        patched_tree::HookRef,
+    },
+    Fragment,
+    Json,
+    Key,
+};
+use hashbrown::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RerenderCause {
+    /// hooks the widget listens to were changed
+    Hooks(Vec<HookRef>),
+    /// the widget `by` re-evaluated and passed args (given by their index) that
+    /// compared unequal to the previous ones
+    Args { by: Fragment, changed: Vec<usize> },
+    /// the widget was just created and got its args for the first time
+    FirstArgs,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rerender {
+    pub fragment: Fragment,
+    pub cause: RerenderCause,
+}
+
+/// The trace of a single update of the widget tree.
+#[derive(Debug, Clone, Default)]
+pub struct FrameTrace {
+    /// the hooks that were changed and the widgets that depend on them
+    pub patched: Vec<(HookRef, Vec<Fragment>)>,
+    /// the re-evaluations in the order they happened. Widgets re-evaluated
+    /// because of changed args always come after the widget that passed them.
+    pub rerenders: Vec<Rerender>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RenderTrace {
+    current: FrameTrace,
+    last: Option<FrameTrace>,
+    hook_causes: HashMap<Fragment, Vec<HookRef>>,
+    changed_args: HashMap<Fragment, (Fragment, Vec<usize>)>,
+}
+
+impl RenderTrace {
+    pub(crate) fn from_env() -> Option<Self> {
+        std::env::var("NARUI_TRACE_RERENDERS").ok().map(|_| Self::default())
+    }
+
+    pub(crate) fn hook_patched(&mut self, hook: HookRef, dependents: Vec<Fragment>) {
+        for dependent in &dependents {
+            self.hook_causes.entry(*dependent).or_default().push(hook);
+        }
+        self.current.patched.push((hook, dependents));
+    }
+
+    pub(crate) fn arg_changed(&mut self, fragment: Fragment, arg: usize, by: Fragment) {
+        let (_, changed) = self.changed_args.entry(fragment).or_insert_with(|| (by, vec![]));
+        if !changed.contains(&arg) {
+            changed.push(arg);
+        }
+    }
+
+    pub(crate) fn rerender_for_hooks(&mut self, fragment: Fragment) {
+        let hooks = self.hook_causes.remove(&fragment).unwrap_or_default();
+        self.current.rerenders.push(Rerender { fragment, cause: RerenderCause::Hooks(hooks) });
+    }
+
+    pub(crate) fn rerender_for_args(&mut self, fragment: Fragment) {
+        let cause = match self.changed_args.remove(&fragment) {
+            Some((by, changed)) => RerenderCause::Args { by, changed },
+            None => RerenderCause::FirstArgs,
+        };
+        self.current.rerenders.push(Rerender { fragment, cause });
+    }
+
+    /// ends the current update and logs its trace
+    pub(crate) fn finish(&mut self, key_map: &KeyMap) {
+        self.hook_causes.clear();
+        self.changed_args.clear();
+        let trace = std::mem::take(&mut self.current);
+        if trace.rerenders.is_empty() {
+            return;
+        }
+        for line in trace.describe(key_map) {
+            log::info!("{}", line);
+        }
+        self.last = Some(trace);
+    }
+
+    pub(crate) fn last(&self) -> Option<&FrameTrace> { self.last.as_ref() }
+}
+
+impl FrameTrace {
+    fn describe(&self, key_map: &KeyMap) -> Vec<String> {
+        let mut lines = vec![];
+        for (hook, dependents) in &self.patched {
+            let dependents: Vec<_> =
+                dependents.iter().map(|frag| format_key(key_map, (*frag).into())).collect();
+            lines.push(format!(
+                "hook {} changed, invalidating [{}]",
+                format_hook(key_map, *hook),
+                dependents.join(", ")
+            ));
+        }
+        for Rerender { fragment, cause } in &self.rerenders {
+            let key = format_key(key_map, (*fragment).into());
+            lines.push(match cause {
+                RerenderCause::Hooks(hooks) => {
+                    let hooks: Vec<_> = hooks.iter().map(|h| format_hook(key_map, *h)).collect();
+                    format!("re-evaluated {} because of hooks [{}]", key, hooks.join(", "))
+                }
+                RerenderCause::Args { by, changed } => format!(
+                    "re-evaluated {} because {} passed changed args [{}]",
+                    key,
+                    format_key(key_map, (*by).into()),
+                    arg_names(key_map, *fragment, changed).join(", ")
+                ),
+                RerenderCause::FirstArgs => format!("evaluated {} with its first args", key),
+            });
+        }
+        lines
+    }
+
+    pub(crate) fn to_json(&self, key_map: &KeyMap) -> Json {
+        let patched = self
+            .patched
+            .iter()
+            .map(|(hook, dependents)| {
+                Json::object([
+                    ("hook", format_hook(key_map, *hook).into()),
+                    ("invalidated", Json::Array(dependents.iter().map(|f| f.0.into()).collect())),
+                ])
+            })
+            .collect();
+        let rerenders = self
+            .rerenders
+            .iter()
+            .map(|Rerender { fragment, cause }| {
+                let mut fields = vec![
+                    ("id", fragment.0.into()),
+                    ("key", format_key(key_map, (*fragment).into()).into()),
+                ];
+                match cause {
+                    RerenderCause::Hooks(hooks) => {
+                        fields.push(("cause", "hooks".into()));
+                        fields.push((
+                            "hooks",
+                            Json::Array(
+                                hooks.iter().map(|h| format_hook(key_map, *h).into()).collect(),
+                            ),
+                        ));
+                    }
+                    RerenderCause::Args { by, changed } => {
+                        fields.push(("cause", "args".into()));
+                        fields.push(("by", by.0.into()));
+                        fields.push((
+                            "args",
+                            Json::Array(
+                                arg_names(key_map, *fragment, changed)
+                                    .into_iter()
+                                    .map(Into::into)
+                                    .collect(),
+                            ),
+                        ));
+                    }
+                    RerenderCause::FirstArgs => fields.push(("cause", "first_args".into())),
+                }
+                Json::object(fields)
+            })
+            .collect();
+        Json::object([("patched", Json::Array(patched)), ("rerenders", Json::Array(rerenders))])
+    }
+}
+
+fn format_key(key_map: &KeyMap, key: Key) -> String {
+    if key == Key::ROOT {
+        "toplevel".to_string()
+    } else {
+        format!("{:?}", key_map.key_debug(key))
+    }
+}
+
+fn format_hook(key_map: &KeyMap, ((key, index), ..): HookRef) -> String {
+    format!("{}:{}", format_key(key_map, key), index & 0b0111_1111_1111_1111)
+}
+
+fn arg_names(key_map: &KeyMap, fragment: Fragment, args: &[usize]) -> Vec<String> {
+    let names = match key_map.key_tail(fragment.into()) {
+        KeyPart::Fragment { widget_id, .. }
+        | KeyPart::FragmentKey { widget_id, .. }
+        | KeyPart::FragmentInternedKey { widget_id, .. } => {
+            WIDGET_INFO.read()[widget_id as usize].arg_names.clone()
+        }
+        KeyPart::Root => vec![],
+    };
+    args.iter().map(|i| names.get(*i).cloned().unwrap_or_else(|| i.to_string())).collect()
+}
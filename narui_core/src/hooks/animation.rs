@@ -0,0 +1,240 @@
+use super::{ListenableCreate, ListenableListen, ListenableShout, ListenableSpy};
+use crate::{
+    context::scheduler::FrameCallback,
+    geom::{Rect, Vec2},
+    CallbackContext,
+    Color,
+    Listenable,
+    WidgetContext,
+};
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct AnimationFrame {
+    pub time: Instant,
+    /// time since the previous frame that was delivered to the same hook, zero
+    /// for the first frame
+    pub delta: Duration,
+}
+
+pub trait ContextAnimationFrame {
+    fn animation_frame(
+        &mut self,
+        active: bool,
+        callback: impl for<'a> Fn(&'a CallbackContext, AnimationFrame) + 'static,
+    );
+}
+
+impl<'b> ContextAnimationFrame for WidgetContext<'b> {
+    /// Runs the callback once per frame as long as the last evaluation of the
+    /// widget passed `active = true`. The event loop keeps producing frames
+    /// only while there is an active callback.
+    #[track_caller]
+    fn animation_frame(
+        &mut self,
+        active: bool,
+        callback: impl for<'a> Fn(&'a CallbackContext, AnimationFrame) + 'static,
+    ) {
+        let key = self.key_for_typed_hook::<FrameCallback>();
+        self.scheduler.set_frame_listener(key, if active { Some(Rc::new(callback)) } else { None });
+    }
+}
+
+/// Values that can be animated by `tween` and `spring`. The arithmetic is
+/// component wise.
+pub trait Animatable: Clone + PartialEq + Send + Sync + 'static {
+    fn zero() -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn sub(&self, other: &Self) -> Self;
+    fn scale(&self, factor: f32) -> Self;
+    /// the largest absolute component, used to decide when a spring is at rest
+    fn magnitude(&self) -> f32;
+
+    fn lerp(&self, to: &Self, t: f32) -> Self { self.add(&to.sub(self).scale(t)) }
+}
+
+impl Animatable for f32 {
+    fn zero() -> Self { 0.0 }
+    fn add(&self, other: &Self) -> Self { self + other }
+    fn sub(&self, other: &Self) -> Self { self - other }
+    fn scale(&self, factor: f32) -> Self { self * factor }
+    fn magnitude(&self) -> f32 { self.abs() }
+}
+
+impl Animatable for Vec2 {
+    fn zero() -> Self { Vec2::zero() }
+    fn add(&self, other: &Self) -> Self { *self + *other }
+    fn sub(&self, other: &Self) -> Self { *self - *other }
+    fn scale(&self, factor: f32) -> Self { *self * factor }
+    fn magnitude(&self) -> f32 { self.x.abs().max(self.y.abs()) }
+}
+
+impl Animatable for Rect {
+    fn zero() -> Self { Rect::zero() }
+    fn add(&self, other: &Self) -> Self {
+        Rect { pos: self.pos.add(&other.pos), size: self.size.add(&other.size) }
+    }
+    fn sub(&self, other: &Self) -> Self {
+        Rect { pos: self.pos.sub(&other.pos), size: self.size.sub(&other.size) }
+    }
+    fn scale(&self, factor: f32) -> Self {
+        Rect { pos: self.pos.scale(factor), size: self.size.scale(factor) }
+    }
+    fn magnitude(&self) -> f32 { self.pos.magnitude().max(self.size.magnitude()) }
+}
+
+impl Animatable for Color {
+    fn zero() -> Self { Color::new(0.0, 0.0, 0.0, 0.0) }
+    fn add(&self, other: &Self) -> Self {
+        Color::new(
+            self.red + other.red,
+            self.green + other.green,
+            self.blue + other.blue,
+            self.alpha + other.alpha,
+        )
+    }
+    fn sub(&self, other: &Self) -> Self {
+        Color::new(
+            self.red - other.red,
+            self.green - other.green,
+            self.blue - other.blue,
+            self.alpha - other.alpha,
+        )
+    }
+    fn scale(&self, factor: f32) -> Self {
+        Color::new(self.red * factor, self.green * factor, self.blue * factor, self.alpha * factor)
+    }
+    fn magnitude(&self) -> f32 {
+        self.red.abs().max(self.green.abs()).max(self.blue.abs()).max(self.alpha.abs())
+    }
+}
+
+/// Easing curves for `tween`, see https://easings.net/ for how they look
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Curve {
+    Linear,
+    EaseIn,
+    EaseOut,
+    #[default]
+    EaseInOut,
+}
+
+impl Curve {
+    pub fn transform(&self, t: f32) -> f32 {
+        match self {
+            Curve::Linear => t,
+            Curve::EaseIn => t * t * t,
+            Curve::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Curve::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct TweenState<T> {
+    from: T,
+    to: T,
+    elapsed: Duration,
+}
+
+// below this the spring snaps to its target
+const SPRING_REST_THRESHOLD: f32 = 1e-3;
+// bigger frame deltas are simulated in multiple steps to keep the spring stable
+const SPRING_MAX_STEP: f32 = 1.0 / 120.0;
+
+pub trait ContextAnimation {
+    fn tween<T: Animatable>(
+        &mut self,
+        target: T,
+        duration: Duration,
+        curve: Curve,
+    ) -> Listenable<T>;
+    fn spring<T: Animatable>(&mut self, target: T, stiffness: f32, damping: f32) -> Listenable<T>;
+}
+
+impl<'b> ContextAnimation for WidgetContext<'b> {
+    /// Animates from the current value to `target` in `duration` whenever
+    /// `target` changes. The widget is re-evaluated on every animation frame
+    /// while the value is in motion.
+    #[track_caller]
+    fn tween<T: Animatable>(
+        &mut self,
+        target: T,
+        duration: Duration,
+        curve: Curve,
+    ) -> Listenable<T> {
+        let value = self.listenable(target.clone());
+        let state = self.listenable(TweenState {
+            from: target.clone(),
+            to: target.clone(),
+            elapsed: duration,
+        });
+        let current = self.listen(value);
+
+        if self.tree.spy_ref(state).to != target {
+            self.tree.shout_non_signalling(
+                state,
+                TweenState { from: current.clone(), to: target.clone(), elapsed: Duration::ZERO },
+            );
+        }
+
+        self.animation_frame(current != target, move |context, frame| {
+            let TweenState { from, to, elapsed } = context.spy(state);
+            let elapsed = elapsed + frame.delta;
+            let new_value = if elapsed >= duration {
+                to.clone()
+            } else {
+                from.lerp(&to, curve.transform(elapsed.as_secs_f32() / duration.as_secs_f32()))
+            };
+            context.shout_non_signalling(state, TweenState { from, to, elapsed });
+            context.shout(value, new_value);
+        });
+
+        value
+    }
+
+    /// Animates toward `target` with a damped spring of unit mass. Changing the
+    /// target while the spring is moving keeps its velocity.
+    #[track_caller]
+    fn spring<T: Animatable>(&mut self, target: T, stiffness: f32, damping: f32) -> Listenable<T> {
+        let value = self.listenable(target.clone());
+        let velocity = self.listenable(T::zero());
+        let current = self.listen(value);
+        let in_motion =
+            current != target || self.tree.spy_ref(velocity).magnitude() > SPRING_REST_THRESHOLD;
+
+        self.animation_frame(in_motion, move |context, frame| {
+            let mut position = context.spy(value);
+            let mut v = context.spy(velocity);
+
+            let dt = frame.delta.as_secs_f32();
+            let steps = (dt / SPRING_MAX_STEP).ceil().max(1.0);
+            let step = dt / steps;
+            for _ in 0..steps as usize {
+                let force = target.sub(&position).scale(stiffness).sub(&v.scale(damping));
+                v = v.add(&force.scale(step));
+                position = position.add(&v.scale(step));
+            }
+
+            if target.sub(&position).magnitude() < SPRING_REST_THRESHOLD
+                && v.magnitude() < SPRING_REST_THRESHOLD
+            {
+                position = target.clone();
+                v = T::zero();
+            }
+            context.shout_non_signalling(velocity, v);
+            context.shout(value, position);
+        });
+
+        value
+    }
+}
@@ -18,7 +18,10 @@ impl<T> EffectHandle<T> {
         &self,
     ) -> MappedListenableGuard<Option<T>, T, impl for<'a> Fn(&'a Option<T>) -> &'a T> {
         MappedListenableGuard {
-            entry: self.tree.get_unpatched(self.key),
+            entry: self
+                .tree
+                .get_unpatched(self.key)
+                .expect("the effect belongs to a widget that was removed"),
             mapping_function: |elem: &Option<T>| elem.as_ref().unwrap(),
             phantom: Default::default(),
             phantom2: Default::default(),
@@ -35,6 +38,7 @@ pub trait ContextEffect {
 }
 
 impl<'a> ContextEffect for WidgetContext<'a> {
+    #[track_caller]
     fn effect<T: Send + Sync + 'static>(
         &mut self,
         callback: impl FnOnce(&mut WidgetContext) -> T,
@@ -46,12 +50,12 @@ impl<'a> ContextEffect for WidgetContext<'a> {
 
         if self.listen_ref(handle_listenable).is_none() {
             let handle = callback(self);
-            self.tree.set_unconditional(handle_listenable.key.1, Box::new(Some(handle)));
-            self.tree.set_unconditional(deps_listenable.key.1, Box::new(deps));
+            self.tree.set_unconditional(handle_listenable.key, Box::new(Some(handle)));
+            self.tree.set_unconditional(deps_listenable.key, Box::new(deps));
         } else if *self.listen_ref(deps_listenable) != deps {
-            self.tree.set_unconditional(deps_listenable.key.1, Box::new(deps));
+            self.tree.set_unconditional(deps_listenable.key, Box::new(deps));
             let handle = callback(self);
-            self.tree.set_unconditional(handle_listenable.key.1, Box::new(Some(handle)))
+            self.tree.set_unconditional(handle_listenable.key, Box::new(Some(handle)))
         }
 
         EffectHandle {
@@ -0,0 +1,24 @@
+use super::ListenableCreate;
+use crate::{Listenable, WidgetContext};
+
+pub trait ContextErrorBoundary {
+    fn catch_panics(&mut self) -> Listenable<Option<String>>;
+}
+
+impl<'a> ContextErrorBoundary for WidgetContext<'a> {
+    /// Makes the widget an error boundary: a panic while evaluating one of its
+    /// descendants removes everything that was created below the widget and
+    /// sets the returned listenable to the panic message. Shouting `None` into
+    /// it retries rendering the children.
+    ///
+    /// The children of the widget are created by the widget that uses it, so
+    /// that one is re-evaluated too, whenever the error changes.
+    #[track_caller]
+    fn catch_panics(&mut self) -> Listenable<Option<String>> {
+        let error = self.listenable(None);
+        self.fragment_store.set_error_boundary(self.widget_local.idx, error);
+        let creator = self.key_map.key_parent(self.widget_local.key);
+        self.tree.set_dependent(error.key, self.fragment_store.fragment(creator));
+        error
+    }
+}
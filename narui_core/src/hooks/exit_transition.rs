@@ -0,0 +1,21 @@
+use super::{ListenableCreate, ListenableListen};
+use crate::WidgetContext;
+use std::time::Duration;
+
+pub trait ContextExitTransition {
+    fn exit_transition(&mut self, duration: Duration) -> Option<f32>;
+}
+
+impl<'a> ContextExitTransition for WidgetContext<'a> {
+    /// Keeps the widget alive for `duration` after it (or one of its ancestors)
+    /// was removed from its parent. While leaving, the widget is still rendered
+    /// but does not receive input and this hook returns the progress of the
+    /// transition from 0 to 1, so it can for example fade out. Only one exit
+    /// transition per widget is supported.
+    #[track_caller]
+    fn exit_transition(&mut self, duration: Duration) -> Option<f32> {
+        let progress = self.listenable(None);
+        self.scheduler.set_exit_transition(self.widget_local.key, duration, progress);
+        self.listen(progress)
+    }
+}
@@ -0,0 +1,59 @@
+use crate::{
+    context::{
+        context::{FRAME_STATS_HISTORY_STORE, FRAME_STATS_STORE},
+        PatchedTree,
+    },
+    util::frame_stats::{FrameStats, FrameStatsHistory},
+    CallbackContext,
+    Key,
+    Listenable,
+    ListenableSpy,
+    ThreadContext,
+    WidgetContext,
+};
+
+pub trait ContextFrameStats {
+    /// The statistics of the last rendered frame. Widgets listening to it are
+    /// re-evaluated after every frame, so the app renders continuously while
+    /// they are shown.
+    fn frame_stats(&self) -> Listenable<FrameStats>;
+    /// The statistics of the last `FrameStatsHistory::CAPACITY` frames, for
+    /// exporting them.
+    fn frame_stats_history(&self) -> FrameStatsHistory;
+}
+
+pub(crate) fn frame_stats_listenable(tree: &PatchedTree) -> Listenable<FrameStats> {
+    Listenable::new(
+        tree.initialize_with((Key::STORE, FRAME_STATS_STORE), || Box::new(FrameStats::default())),
+    )
+}
+
+pub(crate) fn frame_stats_history_listenable(tree: &PatchedTree) -> Listenable<FrameStatsHistory> {
+    Listenable::new(tree.initialize_with((Key::STORE, FRAME_STATS_HISTORY_STORE), || {
+        Box::new(FrameStatsHistory::default())
+    }))
+}
+
+impl<'a> ContextFrameStats for WidgetContext<'a> {
+    fn frame_stats(&self) -> Listenable<FrameStats> { frame_stats_listenable(&self.tree) }
+
+    fn frame_stats_history(&self) -> FrameStatsHistory {
+        self.tree.spy(frame_stats_history_listenable(&self.tree))
+    }
+}
+
+impl<'a> ContextFrameStats for CallbackContext<'a> {
+    fn frame_stats(&self) -> Listenable<FrameStats> { frame_stats_listenable(&self.tree) }
+
+    fn frame_stats_history(&self) -> FrameStatsHistory {
+        self.tree.spy(frame_stats_history_listenable(&self.tree))
+    }
+}
+
+impl ContextFrameStats for ThreadContext {
+    fn frame_stats(&self) -> Listenable<FrameStats> { frame_stats_listenable(&self.tree) }
+
+    fn frame_stats_history(&self) -> FrameStatsHistory {
+        self.tree.spy(frame_stats_history_listenable(&self.tree))
+    }
+}
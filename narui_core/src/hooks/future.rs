@@ -0,0 +1,417 @@
+use super::{ListenableCreate, ListenableListen};
+use crate::{Listenable, ThreadContext, WidgetContext};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    task::{self, Poll, Wake, Waker},
+    thread,
+};
+
+pub type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// An Executor drives the futures spawned by the `future` hook. narui brings a
+/// small thread pool based one, but applications that already run an async
+/// runtime can plug it in with `set_executor`.
+pub trait Executor: Send + Sync {
+    fn spawn(&self, future: BoxFuture);
+}
+
+lazy_static::lazy_static! {
+    static ref EXECUTOR: RwLock<Arc<dyn Executor>> = RwLock::new(Arc::new(ThreadPoolExecutor::new(
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(4)
+    )));
+}
+
+/// replaces the executor that is used for all futures spawned after this call
+pub fn set_executor(executor: impl Executor + 'static) { *EXECUTOR.write() = Arc::new(executor); }
+
+struct Job {
+    future: Mutex<Option<BoxFuture>>,
+    queue: Mutex<Sender<Arc<Job>>>,
+}
+
+impl Wake for Job {
+    fn wake(self: Arc<Self>) {
+        let queue = self.queue.lock().clone();
+        // the executor only goes away with the whole process
+        let _ = queue.send(self);
+    }
+}
+
+pub struct ThreadPoolExecutor {
+    queue: Mutex<Sender<Arc<Job>>>,
+}
+
+impl ThreadPoolExecutor {
+    pub fn new(num_threads: usize) -> Self {
+        let (sender, receiver) = channel::<Arc<Job>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..num_threads.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("narui-executor-{}", i))
+                .spawn(move || Self::work(receiver))
+                .expect("could not spawn executor thread");
+        }
+
+        Self { queue: Mutex::new(sender) }
+    }
+
+    fn work(receiver: Arc<Mutex<Receiver<Arc<Job>>>>) {
+        loop {
+            let job = match receiver.lock().recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            };
+            let waker = Waker::from(job.clone());
+            let mut cx = task::Context::from_waker(&waker);
+
+            // the lock is held while polling, so a wake that arrives during the poll
+            // is not lost but handled by the next worker that gets the job
+            let mut future = job.future.lock();
+            if let Some(inner) = future.as_mut() {
+                if inner.as_mut().poll(&mut cx).is_ready() {
+                    *future = None;
+                }
+            }
+        }
+    }
+}
+
+impl Executor for ThreadPoolExecutor {
+    fn spawn(&self, future: BoxFuture) {
+        let queue = self.queue.lock().clone();
+        let job = Arc::new(Job { future: Mutex::new(Some(future)), queue: Mutex::new(queue) });
+        job.wake();
+    }
+}
+
+#[derive(Default)]
+struct TaskState {
+    future: Option<BoxFuture>,
+    waker: Option<Waker>,
+}
+
+// wraps the user future, so it can be dropped by the FutureHandle independent
+// of the executor it runs on
+struct CancellableTask {
+    state: Arc<Mutex<TaskState>>,
+}
+
+impl Future for CancellableTask {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock();
+        let poll = match state.future.as_mut() {
+            None => Poll::Ready(()),
+            Some(future) => future.as_mut().poll(cx),
+        };
+        if poll.is_ready() {
+            state.future = None;
+        } else {
+            state.waker = Some(cx.waker().clone());
+        }
+        poll
+    }
+}
+
+/// FutureHandle cancels the future it belongs to when dropped
+pub struct FutureHandle {
+    state: Arc<Mutex<TaskState>>,
+}
+
+impl FutureHandle {
+    fn spawn<T: Send + Sync + 'static>(
+        executor: &dyn Executor,
+        context: ThreadContext,
+        result: Listenable<Poll<T>>,
+        future: impl Future<Output = T> + Send + 'static,
+    ) -> Self {
+        let future = Box::pin(async move {
+            let value = future.await;
+            // Poll<T> is not PartialEq for arbitrary T, so we skip the comparison of shout
+            context.tree.set(result.key, Box::new(Poll::Ready(value)));
+        });
+        let state = Arc::new(Mutex::new(TaskState { future: Some(future), waker: None }));
+        executor.spawn(Box::pin(CancellableTask { state: state.clone() }));
+
+        Self { state }
+    }
+}
+
+impl Drop for FutureHandle {
+    fn drop(&mut self) {
+        let (future, waker) = {
+            let mut state = self.state.lock();
+            (state.future.take(), state.waker.take())
+        };
+        std::mem::drop(future);
+        // give the executor a chance to forget about the task
+        if let Some(waker) = waker {
+            waker.wake()
+        }
+    }
+}
+
+// cancels the future whose handle is in `handle` and spawns `future` in its place
+fn restart<T: Send + Sync + 'static>(
+    executor: &dyn Executor,
+    context: ThreadContext,
+    result: Listenable<Poll<T>>,
+    handle: Listenable<Option<FutureHandle>>,
+    future: impl Future<Output = T> + Send + 'static,
+) {
+    let tree = context.tree.clone();
+    // cancel the old future first, so it can not deliver a stale result after we
+    // reset the listenable
+    tree.set_unconditional(handle.key, Box::new(None::<FutureHandle>));
+    tree.remove_patch(result.key);
+    tree.set_unconditional(result.key, Box::new(Poll::<T>::Pending));
+
+    let new_handle = FutureHandle::spawn(executor, context, result, future);
+    tree.set_unconditional(handle.key, Box::new(Some(new_handle)));
+}
+
+pub trait ContextFuture {
+    fn future<T: Send + Sync + 'static>(
+        &mut self,
+        future: impl Future<Output = T> + Send + 'static,
+        deps: impl PartialEq + Send + Sync + 'static,
+    ) -> Listenable<Poll<T>>;
+}
+
+impl<'a> ContextFuture for WidgetContext<'a> {
+    /// Runs `future` on the executor and resolves it into a listenable. The
+    /// future is only spawned on the first evaluation and when `deps` changed,
+    /// in which case the still running old future is cancelled. Futures are
+    /// also cancelled when the widget is removed.
+    #[track_caller]
+    fn future<T: Send + Sync + 'static>(
+        &mut self,
+        future: impl Future<Output = T> + Send + 'static,
+        deps: impl PartialEq + Send + Sync + 'static,
+    ) -> Listenable<Poll<T>> {
+        let result: Listenable<Poll<T>> = self.listenable_with(|| Poll::Pending);
+        let deps_listenable = self.listenable(None);
+        let handle_listenable: Listenable<Option<FutureHandle>> = self.listenable(None);
+        let deps = Some(deps);
+
+        if *self.listen_ref(deps_listenable) != deps {
+            self.tree.set_unconditional(deps_listenable.key, Box::new(deps));
+            let executor = EXECUTOR.read().clone();
+            restart(&*executor, self.thread_context(), result, handle_listenable, future);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{context::key::KeyMap, Key, ListenableSpy, Stores};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // runs the spawned futures only when asked to
+    #[derive(Default)]
+    struct ManualExecutor {
+        tasks: Mutex<Vec<Arc<ManualTask>>>,
+    }
+
+    struct ManualTask {
+        future: Mutex<Option<BoxFuture>>,
+        woken: AtomicBool,
+    }
+
+    impl Wake for ManualTask {
+        fn wake(self: Arc<Self>) { self.woken.store(true, Ordering::SeqCst); }
+    }
+
+    impl Executor for ManualExecutor {
+        fn spawn(&self, future: BoxFuture) {
+            let future = Mutex::new(Some(future));
+            self.tasks.lock().push(Arc::new(ManualTask { future, woken: AtomicBool::new(true) }));
+        }
+    }
+
+    impl ManualExecutor {
+        // polls the woken futures until none is woken anymore
+        fn run(&self) {
+            loop {
+                let woken: Vec<_> = self
+                    .tasks
+                    .lock()
+                    .iter()
+                    .filter(|task| task.woken.swap(false, Ordering::SeqCst))
+                    .cloned()
+                    .collect();
+                if woken.is_empty() {
+                    break;
+                }
+                for task in woken {
+                    let waker = Waker::from(task.clone());
+                    let mut cx = task::Context::from_waker(&waker);
+                    let mut future = task.future.lock();
+                    if let Some(ready) = future.as_mut().map(|f| f.as_mut().poll(&mut cx)) {
+                        if ready.is_ready() {
+                            *future = None;
+                        }
+                    }
+                }
+            }
+            self.tasks.lock().retain(|task| task.future.lock().is_some());
+        }
+
+        fn running(&self) -> usize { self.tasks.lock().len() }
+    }
+
+    // a future that resolves once the test sends its value, and tells whether it
+    // was dropped
+    #[derive(Clone, Default)]
+    struct Gate {
+        state: Arc<Mutex<(Option<u32>, Option<Waker>)>>,
+        dropped: Arc<AtomicBool>,
+    }
+
+    struct GateFuture(Gate);
+
+    impl Future for GateFuture {
+        type Output = u32;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<u32> {
+            let mut state = self.0.state.lock();
+            match state.0.take() {
+                Some(value) => Poll::Ready(value),
+                None => {
+                    state.1 = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    impl Drop for GateFuture {
+        fn drop(&mut self) { self.0.dropped.store(true, Ordering::SeqCst); }
+    }
+
+    impl Gate {
+        fn future(&self) -> GateFuture { GateFuture(self.clone()) }
+
+        fn send(&self, value: u32) {
+            let waker = {
+                let mut state = self.state.lock();
+                state.0 = Some(value);
+                state.1.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+
+        fn dropped(&self) -> bool { self.dropped.load(Ordering::SeqCst) }
+    }
+
+    struct Hooks {
+        stores: Stores,
+        executor: ManualExecutor,
+        result: Listenable<Poll<u32>>,
+        handle: Listenable<Option<FutureHandle>>,
+    }
+
+    impl Hooks {
+        fn new() -> Self {
+            let mut stores = Stores::new();
+            let result = stores.listenable(Poll::Pending);
+            let handle = stores.listenable(None);
+            Self { stores, executor: ManualExecutor::default(), result, handle }
+        }
+
+        // what the hook does when it is evaluated with changed deps
+        fn restart(&self, gate: &Gate) {
+            let context = self.stores.thread_context();
+            restart(&self.executor, context, self.result, self.handle, gate.future());
+        }
+
+        fn result(&self) -> Poll<u32> {
+            self.stores.tree.update_tree(&mut KeyMap::default()).for_each(drop);
+            self.stores.spy(self.result)
+        }
+    }
+
+    #[test]
+    fn resolves_into_the_listenable() {
+        let hooks = Hooks::new();
+        let gate = Gate::default();
+        hooks.restart(&gate);
+        hooks.executor.run();
+        assert_eq!(hooks.result(), Poll::Pending);
+
+        gate.send(3);
+        hooks.executor.run();
+        assert_eq!(hooks.result(), Poll::Ready(3));
+        assert_eq!(hooks.executor.running(), 0);
+    }
+
+    #[test]
+    fn cancelled_when_deps_change() {
+        let hooks = Hooks::new();
+        let (old, new) = (Gate::default(), Gate::default());
+        hooks.restart(&old);
+        hooks.executor.run();
+
+        hooks.restart(&new);
+        assert!(old.dropped());
+        assert!(!new.dropped());
+        hooks.executor.run();
+        assert_eq!(hooks.executor.running(), 1);
+
+        new.send(2);
+        hooks.executor.run();
+        assert_eq!(hooks.result(), Poll::Ready(2));
+    }
+
+    #[test]
+    fn cancelled_on_unmount() {
+        let hooks = Hooks::new();
+        let gate = Gate::default();
+        hooks.restart(&gate);
+        hooks.executor.run();
+
+        hooks.stores.tree.remove_widget(&Key::STORE);
+        assert!(gate.dropped());
+        hooks.executor.run();
+        assert_eq!(hooks.executor.running(), 0);
+    }
+
+    #[test]
+    fn results_after_cancellation_are_dropped() {
+        let hooks = Hooks::new();
+        let (old, new) = (Gate::default(), Gate::default());
+        hooks.restart(&old);
+        hooks.executor.run();
+
+        // the old future is ready, but is cancelled before the executor polls it again
+        old.send(1);
+        hooks.restart(&new);
+        hooks.executor.run();
+        assert_eq!(hooks.result(), Poll::Pending);
+
+        // and a result sent after it was cancelled goes nowhere
+        let stale = Gate::default();
+        hooks.restart(&stale);
+        hooks.restart(&new);
+        stale.send(1);
+        hooks.executor.run();
+        assert_eq!(hooks.result(), Poll::Pending);
+
+        new.send(2);
+        hooks.executor.run();
+        assert_eq!(hooks.result(), Poll::Ready(2));
+    }
+}
@@ -0,0 +1,13 @@
+use crate::{eval::inspector, CallbackContext, Json};
+
+pub trait ContextInspect {
+    fn inspect_tree(&self) -> Json;
+}
+
+impl<'a> ContextInspect for CallbackContext<'a> {
+    /// Dumps the current widget tree with the keys, args, hooks and layout of
+    /// every widget. Args are only shown if their type implements `Debug`.
+    fn inspect_tree(&self) -> Json {
+        inspector::inspect(&self.tree, self.key_map, self.fragment_store, self.layout)
+    }
+}
@@ -0,0 +1,48 @@
+use super::{ContextAfterFrame, ListenableCreate, ListenableShout, ListenableSpy};
+use crate::{context::scheduler::TimerCallback, CallbackContext, WidgetContext};
+use std::rc::Rc;
+
+pub trait ContextLifecycle {
+    fn on_mount(&mut self, callback: impl for<'a> Fn(&'a CallbackContext) + 'static);
+    fn on_update(&mut self, callback: impl for<'a> Fn(&'a CallbackContext) + 'static);
+    fn on_unmount(&mut self, callback: impl for<'a> Fn(&'a CallbackContext) + 'static);
+}
+
+impl<'b> ContextLifecycle for WidgetContext<'b> {
+    /// runs the callback once, after the first frame in which the widget was
+    /// laid out
+    #[track_caller]
+    fn on_mount(&mut self, callback: impl for<'a> Fn(&'a CallbackContext) + 'static) {
+        let mounted = self.listenable(false);
+        if !self.tree.spy(mounted) {
+            self.after_frame(move |context| {
+                // the widget might have been evaluated multiple times before the first frame
+                if !context.spy(mounted) {
+                    context.shout_non_signalling(mounted, true);
+                    callback(context);
+                }
+            });
+        }
+    }
+
+    /// runs the callback after the frame following every re-evaluation of the
+    /// widget, but not after the first evaluation
+    #[track_caller]
+    fn on_update(&mut self, callback: impl for<'a> Fn(&'a CallbackContext) + 'static) {
+        let evaluated = self.listenable(false);
+        if self.tree.spy(evaluated) {
+            self.after_frame(callback);
+        } else {
+            self.tree.shout_non_signalling(evaluated, true);
+        }
+    }
+
+    /// runs the callback after the frame in which the widget was removed. The
+    /// callbacks of a widget run in the order of the hooks, the ones of its
+    /// children before it
+    #[track_caller]
+    fn on_unmount(&mut self, callback: impl for<'a> Fn(&'a CallbackContext) + 'static) {
+        let key = self.key_for_typed_hook::<TimerCallback>();
+        self.scheduler.set_unmount_callback(key, Rc::new(callback));
+    }
+}
@@ -1,11 +1,14 @@
 use crate::{
     context::{HookRef, PatchTreeEntry, PatchedTree},
     CallbackContext,
+    Stores,
     ThreadContext,
     WidgetContext,
 };
 use std::{marker::PhantomData, ops::Deref};
 
+const REMOVED: &str = "the listenable belongs to a widget that was removed";
+
 pub trait ListenableCreate {
     fn listenable_with<T: Send + Sync + 'static>(
         &mut self,
@@ -35,30 +38,56 @@ pub trait ListenableSpy {
 }
 
 impl<'a> ListenableCreate for WidgetContext<'a> {
+    #[track_caller]
     fn listenable_with<T: Send + Sync + 'static>(
         &mut self,
         gen: impl FnOnce() -> T,
     ) -> Listenable<T> {
-        let key = self.key_for_hook();
+        let key = self.key_for_typed_hook::<T>();
         let key = self.tree.initialize_with(key, || Box::new(gen()));
         Listenable { key, phantom_data: Default::default() }
     }
 
+    #[track_caller]
     fn listenable<T: Send + Sync + 'static>(&mut self, initial: T) -> Listenable<T> {
-        let key = self.key_for_hook();
+        let key = self.key_for_typed_hook::<T>();
         let key = self.tree.initialize_with(key, || Box::new(initial));
         Listenable { key, phantom_data: Default::default() }
     }
 }
 
+impl ListenableCreate for Stores {
+    fn listenable_with<T: Send + Sync + 'static>(
+        &mut self,
+        gen: impl FnOnce() -> T,
+    ) -> Listenable<T> {
+        let key = self.key_for_store();
+        let key = self.tree.initialize_with(key, || Box::new(gen()));
+        Listenable { key, phantom_data: Default::default() }
+    }
+
+    fn listenable<T: Send + Sync + 'static>(&mut self, initial: T) -> Listenable<T> {
+        self.listenable_with(|| initial)
+    }
+}
+
 impl ListenableShout for PatchedTree {
     fn shout<T: Send + Sync + 'static + PartialEq>(&self, listenable: Listenable<T>, new_value: T) {
-        let old_entry = self.get_unpatched(listenable.key);
+        // a thread might still shout at the listenables of a removed widget
+        let old_entry = match self.get_unpatched(listenable.key) {
+            Some(entry) => entry,
+            None => {
+                log::debug!("ignoring a shout at {:?} of a removed widget", listenable);
+                return;
+            }
+        };
         let old = (&**old_entry)
             .downcast_ref::<T>()
             .expect("old value of arg has wrong type; this is likely an internal narui bug :(");
-        if old != &new_value {
-            std::mem::drop(old_entry);
+        let changed = old != &new_value;
+        // released first, as taking the read lock again could wait for update_tree
+        std::mem::drop(old_entry);
+        if changed {
             self.set(listenable.key, Box::new(new_value));
         } else {
             // it is possible that there was a previous patch, but now we set this
@@ -73,13 +102,15 @@ impl ListenableShout for PatchedTree {
         listenable: Listenable<T>,
         new_value: T,
     ) {
-        self.set_unconditional(listenable.key.1, Box::new(new_value))
+        self.set_unconditional(listenable.key, Box::new(new_value))
     }
 }
 
 impl ListenableShout for ThreadContext {
     fn shout<T: Send + Sync + 'static + PartialEq>(&self, listenable: Listenable<T>, new_value: T) {
-        self.tree.shout(listenable, new_value);
+        if self.tree.thread_shout(listenable.key, &new_value) {
+            self.tree.shout(listenable, new_value);
+        }
     }
 
     fn shout_non_signalling<T: Send + Sync + 'static>(
@@ -105,19 +136,34 @@ impl ListenableShout for CallbackContext<'_> {
     }
 }
 
+impl ListenableShout for Stores {
+    fn shout<T: Send + Sync + 'static + PartialEq>(&self, listenable: Listenable<T>, new_value: T) {
+        self.tree.shout(listenable, new_value);
+    }
+
+    fn shout_non_signalling<T: Send + Sync + 'static>(
+        &self,
+        listenable: Listenable<T>,
+        new_value: T,
+    ) {
+        self.tree.shout_non_signalling(listenable, new_value)
+    }
+}
+
 impl ListenableSpy for PatchedTree {
     fn spy<T: Send + Sync + 'static>(&self, listenable: Listenable<T>) -> T
     where
         T: Clone,
     {
         self.get_patched(listenable.key)
+            .expect(REMOVED)
             .downcast_ref::<T>()
             .expect("Listenable has wrong type")
             .clone()
     }
 
     fn spy_ref<T: Send + Sync>(&self, listenable: Listenable<T>) -> ListenableGuard<T> {
-        ListenableGuard::new(self.get_patched(listenable.key))
+        ListenableGuard::new(self.get_patched(listenable.key).expect(REMOVED))
     }
 }
 
@@ -147,24 +193,41 @@ impl ListenableSpy for ThreadContext {
     }
 }
 
+impl ListenableSpy for Stores {
+    fn spy<T: Send + Sync + 'static>(&self, listenable: Listenable<T>) -> T
+    where
+        T: Clone,
+    {
+        self.tree.spy(listenable)
+    }
+
+    fn spy_ref<T: Send + Sync>(&self, listenable: Listenable<T>) -> ListenableGuard<T> {
+        self.tree.spy_ref(listenable)
+    }
+}
+
 impl<'a> ListenableListen for WidgetContext<'a> {
     fn listen<T: Send + Sync + 'static>(&mut self, listenable: Listenable<T>) -> T
     where
         T: Clone,
     {
-        self.tree.set_dependent(listenable.key, self.widget_local.idx);
-        self.tree
+        let value = self
+            .tree
             .get_unpatched(listenable.key)
+            .expect(REMOVED)
             .downcast_ref::<T>()
             .expect("Listenable has wrong type")
-            .clone()
+            .clone();
+        self.tree.set_dependent(listenable.key, self.widget_local.idx);
+        value
     }
 
     fn listen_ref<T: Send + Sync>(&mut self, listenable: Listenable<T>) -> ListenableGuard<T> {
+        let value = self.tree.get_unpatched(listenable.key).expect(REMOVED);
         // TODO(robin): why was this previously not marked as used?
         self.tree.set_dependent(listenable.key, self.widget_local.idx);
 
-        ListenableGuard::new(self.tree.get_unpatched(listenable.key))
+        ListenableGuard::new(value)
     }
 }
 
@@ -173,10 +236,21 @@ pub struct Listenable<T> {
     pub key: HookRef,
     phantom_data: PhantomData<T>,
 }
+impl<T> Listenable<T> {
+    pub(crate) fn new(key: HookRef) -> Self { Self { key, phantom_data: Default::default() } }
+}
 impl<T> Clone for Listenable<T> {
     fn clone(&self) -> Self { Self { key: self.key, phantom_data: Default::default() } }
 }
 impl<T> Copy for Listenable<T> {}
+impl<T> std::fmt::Debug for Listenable<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Listenable").field("key", &self.key).finish()
+    }
+}
+impl<T> PartialEq for Listenable<T> {
+    fn eq(&self, other: &Self) -> bool { self.key == other.key }
+}
 
 pub struct ListenableGuard<'a, T> {
     pub(crate) entry: PatchTreeEntry<'a>,
@@ -26,6 +26,8 @@ impl Measurement<Vec2> {
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum MeasureError {
     NoPreviousLayout,
+    /// the widget was removed, the fragment might refer to another widget now
+    Stale,
 }
 
 pub trait ContextMeasure {
@@ -60,7 +62,7 @@ impl ContextMeasure for CallbackContext<'_> {
 
 fn get_layout(context: &CallbackContext, idx: Fragment) -> Result<Rect, MeasureError> {
     let layout = context.layout;
-    let frag = context.fragment_store.get(idx);
+    let frag = context.fragment_store.get_checked(idx).ok_or(MeasureError::Stale)?;
     match frag {
         Evaluated(frag) => {
             let rect = layout.get_positioned_logical(frag.layout_idx).0;
@@ -0,0 +1,18 @@
+use crate::{util::memory_stats::MemoryStats, CallbackContext};
+
+pub trait ContextMemoryStats {
+    /// The size of the tables that grow with the widget tree, for keeping an
+    /// eye on apps that run for a long time.
+    fn memory_stats(&self) -> MemoryStats;
+}
+
+impl ContextMemoryStats for CallbackContext<'_> {
+    fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            fragments: self.fragment_store.memory_stats(),
+            keys: self.key_map.memory_stats(),
+            hooks: self.tree.memory_stats(),
+            layout_nodes: self.layout.memory_stats(),
+        }
+    }
+}
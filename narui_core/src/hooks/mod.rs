@@ -12,3 +12,41 @@ pub use measure::*;
 
 pub(crate) mod thread;
 pub use thread::*;
+
+pub(crate) mod future;
+pub use future::*;
+
+pub(crate) mod timer;
+pub use timer::*;
+
+pub(crate) mod animation;
+pub use animation::*;
+
+pub(crate) mod exit_transition;
+pub use exit_transition::*;
+
+pub(crate) mod lifecycle;
+pub use lifecycle::*;
+
+pub(crate) mod error_boundary;
+pub use error_boundary::*;
+
+pub(crate) mod inspect;
+pub use inspect::*;
+
+pub(crate) mod render_trace;
+pub use render_trace::*;
+
+pub(crate) mod frame_stats;
+pub use frame_stats::*;
+
+pub(crate) mod undo;
+pub use undo::*;
+
+pub(crate) mod memory_stats;
+pub use memory_stats::*;
+
+#[cfg(feature = "persistence")]
+pub(crate) mod persistent;
+#[cfg(feature = "persistence")]
+pub use persistent::*;
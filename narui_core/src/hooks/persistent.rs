@@ -0,0 +1,37 @@
+use crate::{Listenable, Stores, WidgetContext};
+use serde::{de::DeserializeOwned, Serialize};
+
+pub trait ContextPersistent {
+    /// Like `listenable`, but the value survives restarts. It is loaded from
+    /// the state file set with `Stores::persist_state` and written back
+    /// shortly after every change. The value is identified by the path of the
+    /// widget and `name`, so `name` has to be unique within the widget.
+    fn persistent_listenable<T>(&mut self, name: &str, default: T) -> Listenable<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static;
+}
+
+impl<'a> ContextPersistent for WidgetContext<'a> {
+    #[track_caller]
+    fn persistent_listenable<T>(&mut self, name: &str, default: T) -> Listenable<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let key = self.key_for_typed_hook::<T>();
+        let widget = self.widget_local.key;
+        let key_map = &*self.key_map;
+        let name = || format!("{:?}/{}", key_map.key_debug(widget), name);
+        Listenable::new(self.tree.initialize_persistent(key, name, default))
+    }
+}
+
+impl ContextPersistent for Stores {
+    fn persistent_listenable<T>(&mut self, name: &str, default: T) -> Listenable<T>
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let key = self.key_for_store();
+        let name = || format!("store/{}", name);
+        Listenable::new(self.tree.initialize_persistent(key, name, default))
+    }
+}
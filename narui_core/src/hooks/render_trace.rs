@@ -0,0 +1,14 @@
+use crate::{eval::render_trace::FrameTrace, CallbackContext};
+
+pub trait ContextRenderTrace {
+    fn render_trace(&self) -> Option<&FrameTrace>;
+}
+
+impl<'a> ContextRenderTrace for CallbackContext<'a> {
+    /// Why widgets were re-evaluated in the last update that re-evaluated
+    /// anything. Only available if the `NARUI_TRACE_RERENDERS` environment
+    /// variable is set, which also logs the trace of every update.
+    fn render_trace(&self) -> Option<&FrameTrace> {
+        self.fragment_store.render_trace.as_ref().and_then(|trace| trace.last())
+    }
+}
@@ -41,6 +41,7 @@ pub trait ContextThread {
 }
 
 impl<'a> ContextThread for WidgetContext<'a> {
+    #[track_caller]
     fn thread<T: Send + Sync + Clone + 'static>(
         &mut self,
         callback: impl Fn(ThreadContext, Receiver<T>) + Sync + Send + 'static,
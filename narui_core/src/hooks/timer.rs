@@ -0,0 +1,41 @@
+use crate::{context::scheduler::TimerCallback, CallbackContext, WidgetContext};
+use std::{rc::Rc, time::Duration};
+
+pub trait ContextTimer {
+    fn interval(
+        &mut self,
+        duration: Duration,
+        callback: impl for<'a> Fn(&'a CallbackContext) + 'static,
+    );
+    fn timeout(
+        &mut self,
+        duration: Duration,
+        callback: impl for<'a> Fn(&'a CallbackContext) + 'static,
+    );
+}
+
+/// Timers are run by the event loop and stopped when the widget is removed.
+/// Re-evaluating the widget only replaces the callback, the timer keeps running
+/// from where it was. Passing a different duration starts the timer over, which
+/// is also the only way to make a timeout that fired fire again.
+impl<'b> ContextTimer for WidgetContext<'b> {
+    #[track_caller]
+    fn interval(
+        &mut self,
+        duration: Duration,
+        callback: impl for<'a> Fn(&'a CallbackContext) + 'static,
+    ) {
+        let key = self.key_for_typed_hook::<TimerCallback>();
+        self.scheduler.set_timer(key, duration, true, Rc::new(callback))
+    }
+
+    #[track_caller]
+    fn timeout(
+        &mut self,
+        duration: Duration,
+        callback: impl for<'a> Fn(&'a CallbackContext) + 'static,
+    ) {
+        let key = self.key_for_typed_hook::<TimerCallback>();
+        self.scheduler.set_timer(key, duration, false, Rc::new(callback))
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{
+    context::{
+        context::{CAN_REDO_STORE, CAN_UNDO_STORE},
+        undo::clone_item,
+        PatchedTree,
+    },
+    CallbackContext,
+    Key,
+    Listenable,
+    Stores,
+    ThreadContext,
+    WidgetContext,
+};
+
+pub trait ContextUndoTrack {
+    /// Records the changes of the listenable, so they can be undone. Changes
+    /// are recorded when they are committed at the start of the next update.
+    fn track_undo<T: Clone + Send + Sync + 'static>(&self, listenable: Listenable<T>);
+    /// Whether there is a change that can be undone.
+    fn can_undo(&self) -> Listenable<bool>;
+    /// Whether there is an undone change that can be redone.
+    fn can_redo(&self) -> Listenable<bool>;
+}
+
+pub trait ContextUndo {
+    /// Reverts the last transaction. The values are restored in the next
+    /// update.
+    fn undo(&self);
+    fn redo(&self);
+    /// All changes committed until the matching `end_transaction` are undone
+    /// together, for example the changes of a whole drag gesture. Without a
+    /// transaction, every update is undone on its own. Transactions can be
+    /// nested.
+    fn begin_transaction(&self);
+    fn end_transaction(&self);
+}
+
+pub(crate) fn can_undo_listenable(tree: &PatchedTree) -> Listenable<bool> {
+    Listenable::new(tree.initialize_with((Key::STORE, CAN_UNDO_STORE), || Box::new(false)))
+}
+
+pub(crate) fn can_redo_listenable(tree: &PatchedTree) -> Listenable<bool> {
+    Listenable::new(tree.initialize_with((Key::STORE, CAN_REDO_STORE), || Box::new(false)))
+}
+
+impl<'a> ContextUndoTrack for WidgetContext<'a> {
+    fn track_undo<T: Clone + Send + Sync + 'static>(&self, listenable: Listenable<T>) {
+        self.tree.track_undo(listenable.key, clone_item::<T>)
+    }
+
+    fn can_undo(&self) -> Listenable<bool> { can_undo_listenable(&self.tree) }
+
+    fn can_redo(&self) -> Listenable<bool> { can_redo_listenable(&self.tree) }
+}
+
+impl<'a> ContextUndoTrack for CallbackContext<'a> {
+    fn track_undo<T: Clone + Send + Sync + 'static>(&self, listenable: Listenable<T>) {
+        self.tree.track_undo(listenable.key, clone_item::<T>)
+    }
+
+    fn can_undo(&self) -> Listenable<bool> { can_undo_listenable(&self.tree) }
+
+    fn can_redo(&self) -> Listenable<bool> { can_redo_listenable(&self.tree) }
+}
+
+impl ContextUndoTrack for Stores {
+    fn track_undo<T: Clone + Send + Sync + 'static>(&self, listenable: Listenable<T>) {
+        self.tree.track_undo(listenable.key, clone_item::<T>)
+    }
+
+    fn can_undo(&self) -> Listenable<bool> { can_undo_listenable(&self.tree) }
+
+    fn can_redo(&self) -> Listenable<bool> { can_redo_listenable(&self.tree) }
+}
+
+impl<'a> ContextUndo for CallbackContext<'a> {
+    fn undo(&self) { self.tree.undo() }
+
+    fn redo(&self) { self.tree.redo() }
+
+    fn begin_transaction(&self) { self.tree.begin_transaction() }
+
+    fn end_transaction(&self) { self.tree.end_transaction() }
+}
+
+impl ContextUndo for ThreadContext {
+    fn undo(&self) { self.tree.undo() }
+
+    fn redo(&self) { self.tree.redo() }
+
+    fn begin_transaction(&self) { self.tree.begin_transaction() }
+
+    fn end_transaction(&self) { self.tree.end_transaction() }
+}
@@ -10,7 +10,7 @@ mod vulkano_render;
 pub mod _macro_api {
     pub use super::{
         context::{
-            args::{listen_args, shout_args, ArgRef},
+            args::{arg_values_fn, listen_args, shout_args, ArgRef, ArgValues, WidgetArgs},
             context::WidgetContext,
             key::{
                 internal::{WidgetDebugInfo, WIDGET_INFO},
@@ -19,15 +19,54 @@ pub mod _macro_api {
         },
         eval::fragment::{Fragment, FragmentInner, UnevaluatedFragment},
         layout::Transparent,
-        re_export::{ctor::ctor, smallvec::smallvec},
-        util::{all_eq as all_eq_mod, all_eq::all_eq},
+        re_export::{
+            ctor::ctor,
+            smallvec::{smallvec, SmallVec},
+        },
+        util::{
+            all_eq as all_eq_mod,
+            all_eq::all_eq,
+            arg_format,
+            arg_format::arg_formatter,
+            key_dispatch,
+            key_dispatch::direct_key,
+        },
+    };
+}
+/// items only for consumption by the benchmarks, enabled with the `bench`
+/// feature. no user code should depend directly on this
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench_api {
+    pub use super::context::{
+        context::{FragmentStore, WidgetLocalContext},
+        key::KeyMap,
+        HookRef,
+        PatchedTree,
     };
+    use super::{Fragment, Key};
+
+    /// the key and the fragment of the widget in the slot `idx`
+    pub fn widget(idx: u32) -> (Key, Fragment) { (Key(idx), Fragment(idx, 0)) }
+
+    /// the context of the root widget
+    pub fn root_widget(fragment_store: &FragmentStore) -> WidgetLocalContext {
+        WidgetLocalContext::for_key(Key::ROOT, fragment_store.fragment(Key::ROOT))
+    }
 }
 pub mod renderer {
     pub use super::vulkano_render::{glyph_brush::FONT, lyon::ColoredBuffersBuilder};
 }
 pub mod app {
-    pub use super::{re_export::winit::window::WindowBuilder, vulkano_render::render::render};
+    #[cfg(feature = "hot_reload")]
+    pub use super::vulkano_render::render::render_hot_reload;
+    pub use super::{
+        re_export::winit::window::WindowBuilder,
+        vulkano_render::{
+            recording::{Batch, RecordedEvent, RecordedShout, Recording},
+            render::{render, render_with_stores, replay_headless},
+        },
+    };
 }
 pub mod layout {
     pub use rutter_layout::{layouts::*, *};
@@ -54,7 +93,16 @@ pub use rutter_layout::{
 };
 
 
-pub use context::{CallbackContext, Key, ThreadContext, WidgetContext};
-pub use eval::{fragment::*, layout::Physical};
+pub use context::{CallbackContext, Key, Stores, ThreadContext, WidgetContext};
+pub use eval::{
+    fragment::*,
+    layout::{LayoutAnimation, Physical},
+    render_trace::{FrameTrace, Rerender, RerenderCause},
+};
 pub use re_export::Color;
-pub use util::geom;
+pub use util::{
+    frame_stats::{FrameStats, FrameStatsHistory},
+    geom,
+    json::Json,
+    memory_stats::{MemoryStats, SlotStats},
+};
@@ -0,0 +1,41 @@
+//! Autoref based dispatch (like `all_eq`) to get a `Debug` representation of
+//! widget arguments, for the ones whose type implements `Debug`.
+
+use std::{any::Any, fmt::Debug, marker::PhantomData};
+
+pub type ArgFormatter = fn(&dyn Any) -> Option<String>;
+
+pub struct ArgFormatDispatcher<T>(pub PhantomData<T>);
+
+impl<T> ArgFormatDispatcher<T> {
+    pub fn for_value(_: &T) -> Self { Self(PhantomData) }
+}
+
+fn format_debug<T: Debug + 'static>(any: &dyn Any) -> Option<String> {
+    any.downcast_ref::<T>().map(|v| format!("{:?}", v))
+}
+
+fn format_none(_: &dyn Any) -> Option<String> { None }
+
+pub trait ArgFormatViaDebug {
+    fn formatter(&self) -> ArgFormatter;
+}
+impl<T: Debug + 'static> ArgFormatViaDebug for &ArgFormatDispatcher<T> {
+    fn formatter(&self) -> ArgFormatter { format_debug::<T> }
+}
+
+pub trait ArgFormatViaNone {
+    fn formatter(&self) -> ArgFormatter;
+}
+impl<T> ArgFormatViaNone for ArgFormatDispatcher<T> {
+    fn formatter(&self) -> ArgFormatter { format_none }
+}
+
+#[macro_export]
+macro_rules! arg_formatter_ {
+    ($value:expr) => {{
+        use $crate::_macro_api::arg_format::*;
+        (&&ArgFormatDispatcher::for_value($value)).formatter()
+    }};
+}
+pub use arg_formatter_ as arg_formatter;
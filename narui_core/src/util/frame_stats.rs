@@ -0,0 +1,126 @@
+use crate::{
+    context::PatchedTree,
+    hooks::{
+        frame_stats_history_listenable,
+        frame_stats_listenable,
+        ListenableShout,
+        ListenableSpy,
+    },
+    Json,
+    Listenable,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// What the renderer spent its time on in a single frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    /// the time since the previous frame
+    pub frame_time: Duration,
+    /// handling input and timers and re-evaluating widgets since the previous
+    /// frame
+    pub evaluation: Duration,
+    pub layout: Duration,
+    pub text_prerender: Duration,
+    /// building the vertex data of all render objects
+    pub tessellation: Duration,
+    /// uploading the vertex data and recording the command buffers
+    pub recording: Duration,
+    /// submitting the command buffers and presenting the image
+    pub submission: Duration,
+    /// the number of widgets that were re-evaluated since the previous frame
+    pub reevaluated: usize,
+    pub vertices: usize,
+    pub indices: usize,
+}
+
+impl FrameStats {
+    pub fn fps(&self) -> f32 {
+        if self.frame_time.is_zero() {
+            0.0
+        } else {
+            1.0 / self.frame_time.as_secs_f32()
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        let ms = |duration: Duration| Json::from(duration.as_secs_f64() * 1000.0);
+        Json::object([
+            ("frame_time_ms", ms(self.frame_time)),
+            ("evaluation_ms", ms(self.evaluation)),
+            ("layout_ms", ms(self.layout)),
+            ("text_prerender_ms", ms(self.text_prerender)),
+            ("tessellation_ms", ms(self.tessellation)),
+            ("recording_ms", ms(self.recording)),
+            ("submission_ms", ms(self.submission)),
+            ("reevaluated", self.reevaluated.into()),
+            ("vertices", self.vertices.into()),
+            ("indices", self.indices.into()),
+        ])
+    }
+}
+
+/// A ring buffer with the statistics of the last `CAPACITY` frames, oldest
+/// first. Clones share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct FrameStatsHistory {
+    frames: Arc<Mutex<VecDeque<FrameStats>>>,
+}
+
+impl FrameStatsHistory {
+    pub const CAPACITY: usize = 600;
+
+    fn push(&self, stats: FrameStats) {
+        let mut frames = self.frames.lock();
+        if frames.len() == Self::CAPACITY {
+            frames.pop_front();
+        }
+        frames.push_back(stats);
+    }
+
+    pub fn to_vec(&self) -> Vec<FrameStats> { self.frames.lock().iter().copied().collect() }
+
+    pub fn to_json(&self) -> Json {
+        Json::Array(self.frames.lock().iter().map(FrameStats::to_json).collect())
+    }
+}
+
+/// Collects the statistics of the current frame in the render loop and
+/// publishes them once it is finished.
+pub(crate) struct FrameStatsRecorder {
+    pub(crate) current: FrameStats,
+    tree: Arc<PatchedTree>,
+    listenable: Listenable<FrameStats>,
+    history: FrameStatsHistory,
+    last_frame: Option<Instant>,
+}
+
+impl FrameStatsRecorder {
+    pub(crate) fn new(tree: Arc<PatchedTree>) -> Self {
+        let listenable = frame_stats_listenable(&tree);
+        let history = tree.spy(frame_stats_history_listenable(&tree));
+        Self { current: Default::default(), tree, listenable, history, last_frame: None }
+    }
+
+    /// publishes the statistics of the current frame and starts the next one
+    pub(crate) fn finish_frame(&mut self) -> FrameStats {
+        let now = Instant::now();
+        let mut stats = std::mem::take(&mut self.current);
+        stats.frame_time = self.last_frame.map(|last| now - last).unwrap_or_default();
+        self.last_frame = Some(now);
+
+        self.history.push(stats);
+        // only wake the event loop if somebody shows the stats, otherwise an idle app
+        // would keep rendering
+        if self.tree.has_dependents(self.listenable.key) {
+            self.tree.shout(self.listenable, stats);
+        } else {
+            self.tree.shout_non_signalling(self.listenable, stats);
+        }
+        stats
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt::{Display, Formatter, Result};
+
+/// A minimal JSON value for the debugging tools, that only needs to be written,
+/// never parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // keeps the insertion order, which makes dumps easier to read
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: impl IntoIterator<Item = (&'static str, Json)>) -> Self {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+}
+
+impl From<&str> for Json {
+    fn from(v: &str) -> Self { Json::String(v.to_string()) }
+}
+impl From<String> for Json {
+    fn from(v: String) -> Self { Json::String(v) }
+}
+impl From<bool> for Json {
+    fn from(v: bool) -> Self { Json::Bool(v) }
+}
+impl From<f32> for Json {
+    fn from(v: f32) -> Self { Json::Number(v as f64) }
+}
+impl From<f64> for Json {
+    fn from(v: f64) -> Self { Json::Number(v) }
+}
+impl From<u32> for Json {
+    fn from(v: u32) -> Self { Json::Number(v as f64) }
+}
+impl From<usize> for Json {
+    fn from(v: usize) -> Self { Json::Number(v as f64) }
+}
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(v: Option<T>) -> Self { v.map(Into::into).unwrap_or(Json::Null) }
+}
+
+fn write_string(f: &mut Formatter, s: &str) -> Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(v) => write!(f, "{}", v),
+            // JSON has no representation for these
+            Json::Number(v) if !v.is_finite() => write!(f, "null"),
+            Json::Number(v) => write!(f, "{}", v),
+            Json::String(v) => write_string(f, v),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Autoref based dispatch (like `arg_format`) to use integer keys given to
+//! `key=` in rsx as they are, so only the keys of other types are interned.
+
+use std::{convert::TryFrom, marker::PhantomData};
+
+pub struct KeyDispatcher<T>(pub PhantomData<T>);
+
+impl<T> KeyDispatcher<T> {
+    pub fn for_value(_: &T) -> Self { Self(PhantomData) }
+}
+
+pub trait IntegerKey: Copy {
+    fn to_u32(self) -> Option<u32>;
+}
+
+macro_rules! integer_key {
+    ($($ty:ty),*) => {
+        $(impl IntegerKey for $ty {
+            fn to_u32(self) -> Option<u32> { u32::try_from(self).ok() }
+        })*
+    };
+}
+integer_key!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+pub trait KeyViaInteger<T> {
+    fn direct_key(&self, value: &T) -> Option<u32>;
+}
+impl<T: IntegerKey> KeyViaInteger<T> for &KeyDispatcher<T> {
+    fn direct_key(&self, value: &T) -> Option<u32> { value.to_u32() }
+}
+
+pub trait KeyViaIntern<T> {
+    fn direct_key(&self, value: &T) -> Option<u32>;
+}
+impl<T> KeyViaIntern<T> for KeyDispatcher<T> {
+    fn direct_key(&self, _: &T) -> Option<u32> { None }
+}
+
+/// the value of an integer key if it fits into a `u32`, `None` if the key has
+/// to be interned
+#[macro_export]
+macro_rules! direct_key_ {
+    ($value:expr) => {{
+        use $crate::_macro_api::key_dispatch::*;
+        let value = $value;
+        (&&KeyDispatcher::for_value(value)).direct_key(value)
+    }};
+}
+pub use direct_key_ as direct_key;
@@ -0,0 +1,22 @@
+/// The number of entries of one of the tables that grow with the widget tree,
+/// and how many of them there is memory for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SlotStats {
+    pub live: usize,
+    pub capacity: usize,
+}
+
+/// The size of the tables that grow with the widget tree. The values stored in
+/// them (the hook values, args and layouts) are not included, as their size is
+/// not known.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// the widgets in the `FragmentStore`
+    pub fragments: SlotStats,
+    /// the keys of the widgets in the `KeyMap`
+    pub keys: SlotStats,
+    /// the hooks in the `PatchedTree`
+    pub hooks: SlotStats,
+    /// the nodes of the `Layouter`
+    pub layout_nodes: SlotStats,
+}
@@ -1,4 +1,9 @@
 pub mod all_eq;
+pub mod arg_format;
 pub(crate) mod format_helpers;
-pub(crate) mod fps_report;
+pub mod frame_stats;
 pub mod geom;
+pub mod json;
+pub mod key_dispatch;
+pub mod memory_stats;
+pub(crate) mod span;
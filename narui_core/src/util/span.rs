@@ -0,0 +1,12 @@
+/// Enters a `tracing` span on the debug level if the `tracing` feature is
+/// enabled. The span is exited when the returned guard is dropped.
+macro_rules! span {
+    ($($args:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        let guard = Some(tracing::debug_span!($($args)*).entered());
+        #[cfg(not(feature = "tracing"))]
+        let guard: Option<()> = None;
+        guard
+    }};
+}
+pub(crate) use span;
@@ -0,0 +1,295 @@
+//! A debugging server for external tools, enabled with the `devtools` feature.
+//!
+//! Clients connect over tcp to `NARUI_DEVTOOLS_ADDR` (`127.0.0.1:9230` by
+//! default) and receive newline delimited JSON messages:
+//! - `{"type": "snapshot", "tree": ..}` the whole widget tree in the format of
+//!   the inspector, sent when a client connects or asks for it
+//! - `{"type": "update", "evaluated": [..], "subtrees": [..]}` the ids of the
+//!   widgets that were (re)evaluated and their new subtrees, which replace the
+//!   old ones with the same id. If rerenders are traced, `"trace"` contains the
+//!   trace of the last update.
+//! - `{"type": "frame", "stats": ..}` the `FrameStats` of every frame
+//! - `{"type": "error", "message": ..}` if a command could not be executed
+//!
+//! Commands are sent as lines of text:
+//! - `snapshot`
+//! - `highlight <id>` draws the bounds of the widget, `highlight none` stops
+//! - `set <id> <hook index> <value>` sets a listenable of the widget. Only
+//!   listenables of simple types (numbers, bools and strings) can be set.
+
+use crate::{
+    context::context::MaybeEvaluatedFragment::Evaluated,
+    eval::{inspector, layout::Physical},
+    geom::Rect,
+    hooks::ContextMeasure,
+    CallbackContext,
+    ContextInspect,
+    Fragment,
+    FrameStats,
+    Json,
+    Key,
+};
+use parking_lot::Mutex;
+use std::{
+    any::Any,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:9230";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Snapshot,
+    // widgets are given by the ids of their keys, as in the snapshots
+    Highlight(Option<u32>),
+    Set { widget: u32, hook: u16, value: String },
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let widget = |id: &str| id.parse().map_err(|_| format!("invalid widget id {:?}", id));
+        match command {
+            "snapshot" => Ok(Command::Snapshot),
+            "highlight" if rest.trim() == "none" => Ok(Command::Highlight(None)),
+            "highlight" => Ok(Command::Highlight(Some(widget(rest.trim())?))),
+            "set" => {
+                let mut parts = rest.splitn(3, ' ');
+                let widget = widget(parts.next().unwrap_or(""))?;
+                let hook = parts.next().unwrap_or("");
+                let hook = hook.parse().map_err(|_| format!("invalid hook index {:?}", hook))?;
+                let value = parts.next().ok_or("missing value")?.to_string();
+                Ok(Command::Set { widget, hook, value })
+            }
+            _ => Err(format!("unknown command {:?}", line)),
+        }
+    }
+}
+
+pub(crate) struct Devtools {
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    commands: Receiver<Command>,
+    highlighted: Option<Fragment>,
+}
+
+impl Devtools {
+    /// starts listening for clients. `wake` is called from other threads when
+    /// commands arrive.
+    pub(crate) fn start(wake: impl Fn() + Send + Sync + 'static) -> Option<Self> {
+        let addr = std::env::var("NARUI_DEVTOOLS_ADDR").unwrap_or_else(|_| DEFAULT_ADDR.into());
+        let listener = match TcpListener::bind(&addr) {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::error!("could not start the devtools server on {}: {}", addr, e);
+                return None;
+            }
+        };
+        log::info!("devtools listening on {}", addr);
+
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        let (tx, rx) = channel();
+        let wake = Arc::new(wake);
+        let accepted_clients = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let reader = match stream.try_clone() {
+                    Ok(reader) => reader,
+                    Err(_) => continue,
+                };
+                // a client that stops reading should not stall the ui for long
+                let _ = stream.set_write_timeout(Some(Duration::from_millis(100)));
+                accepted_clients.lock().push(stream);
+
+                let tx = tx.clone();
+                let wake = wake.clone();
+                thread::spawn(move || {
+                    if tx.send(Command::Snapshot).is_err() {
+                        return;
+                    }
+                    wake();
+                    for line in BufReader::new(reader).lines() {
+                        let line = match line {
+                            Ok(line) => line,
+                            Err(_) => break,
+                        };
+                        match Command::parse(&line) {
+                            Ok(command) => {
+                                if tx.send(command).is_err() {
+                                    break;
+                                }
+                                wake();
+                            }
+                            Err(e) => log::warn!("devtools: {}", e),
+                        }
+                    }
+                });
+            }
+        });
+
+        Some(Self { clients, commands: rx, highlighted: None })
+    }
+
+    fn is_connected(&self) -> bool { !self.clients.lock().is_empty() }
+
+    fn send(&self, message: Json) {
+        let line = format!("{}\n", message);
+        self.clients.lock().retain(|mut client| client.write_all(line.as_bytes()).is_ok());
+    }
+
+    fn send_error(&self, message: String) {
+        log::warn!("devtools: {}", message);
+        self.send(Json::object([("type", "error".into()), ("message", message.into())]));
+    }
+
+    /// executes the commands that arrived since the last call. Returns true if
+    /// the window has to be redrawn.
+    pub(crate) fn handle_commands(&mut self, context: &CallbackContext) -> bool {
+        let mut redraw = false;
+        for command in self.commands.try_iter().collect::<Vec<_>>() {
+            match command {
+                Command::Snapshot => self.send(Json::object([
+                    ("type", "snapshot".into()),
+                    ("tree", context.inspect_tree()),
+                ])),
+                Command::Highlight(widget) => {
+                    match widget.map(|widget| live_fragment(context, widget)).transpose() {
+                        Ok(fragment) => self.highlighted = fragment,
+                        Err(e) => {
+                            self.send_error(e);
+                            continue;
+                        }
+                    }
+                    redraw = true;
+                }
+                Command::Set { widget, hook, value } => {
+                    if let Err(e) = live_fragment(context, widget)
+                        .and_then(|f| set_hook(context, f, hook, &value))
+                    {
+                        self.send_error(e);
+                    }
+                }
+            }
+        }
+        redraw
+    }
+
+    /// sends the subtrees of the fragments that were (re)evaluated. Should be
+    /// called after layouting, so the subtrees contain the new layout.
+    pub(crate) fn send_update(&self, context: &CallbackContext, evaluated: &[Fragment]) {
+        if evaluated.is_empty() || !self.is_connected() {
+            return;
+        }
+        let subtrees = inspector::inspect_changes(
+            &context.tree,
+            context.key_map,
+            context.fragment_store,
+            context.layout,
+            evaluated,
+        );
+        let trace = context.fragment_store.render_trace.as_ref().and_then(|trace| trace.last());
+        self.send(Json::object([
+            ("type", "update".into()),
+            ("evaluated", Json::Array(evaluated.iter().map(|frag| frag.0.into()).collect())),
+            ("subtrees", Json::Array(subtrees)),
+            ("trace", trace.map(|trace| trace.to_json(context.key_map)).into()),
+        ]));
+    }
+
+    pub(crate) fn send_frame(&self, stats: &FrameStats) {
+        if !self.is_connected() {
+            return;
+        }
+        self.send(Json::object([("type", "frame".into()), ("stats", stats.to_json())]));
+    }
+
+    /// the bounds of the highlighted widget, if it still exists
+    pub(crate) fn highlighted_rect(&self, context: &CallbackContext) -> Option<Physical<Rect>> {
+        let fragment = self.highlighted?;
+        match context.fragment_store.get_checked(fragment) {
+            Some(Evaluated(_)) => context.measure(fragment).ok().map(|m| m.physical),
+            _ => None,
+        }
+    }
+}
+
+// the handle of the widget with the id, with the current generation of its slot
+fn live_fragment(context: &CallbackContext, widget: u32) -> Result<Fragment, String> {
+    context
+        .fragment_store
+        .try_fragment(Key(widget))
+        .ok_or_else(|| format!("there is no widget with id {}", widget))
+}
+
+fn set_hook(
+    context: &CallbackContext,
+    fragment: Fragment,
+    index: u16,
+    value: &str,
+) -> Result<(), String> {
+    let hook = context
+        .tree
+        .hooks_of(Key::from(fragment))
+        .into_iter()
+        .find(|hook| hook.0 .1 == index)
+        .ok_or_else(|| format!("widget {} has no hook with index {}", fragment.0, index))?;
+    let current = context.tree.get_patched(hook).ok_or("the hook was removed")?;
+    let parsed = parse_like(&**current, value)?;
+    std::mem::drop(current);
+    context.tree.set(hook, parsed);
+    Ok(())
+}
+
+// hooks are stored type erased, so the new value is parsed to the type of the
+// current one
+fn parse_like(current: &dyn Any, value: &str) -> Result<Box<dyn Any + Send + Sync>, String> {
+    macro_rules! try_parse {
+        ($($ty:ty),*) => {
+            $(if current.is::<$ty>() {
+                return value
+                    .trim()
+                    .parse::<$ty>()
+                    .map(|v| Box::new(v) as _)
+                    .map_err(|e| format!("could not parse {:?}: {}", value, e));
+            })*
+        };
+    }
+    try_parse!(bool, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+    if current.is::<String>() {
+        return Ok(Box::new(value.to_string()));
+    }
+    Err("only hooks with numbers, bools or strings can be set".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Command::parse("snapshot"), Ok(Command::Snapshot));
+        assert_eq!(Command::parse("highlight 12"), Ok(Command::Highlight(Some(12))));
+        assert_eq!(Command::parse("highlight none"), Ok(Command::Highlight(None)));
+        assert_eq!(
+            Command::parse("set 3 1 hello world"),
+            Ok(Command::Set { widget: 3, hook: 1, value: "hello world".into() })
+        );
+        assert!(Command::parse("set 3 x 1").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+    }
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(parse_like(&1.0f32, "2.5").unwrap().downcast_ref::<f32>(), Some(&2.5));
+        assert_eq!(parse_like(&false, "true").unwrap().downcast_ref::<bool>(), Some(&true));
+        assert!(parse_like(&1u8, "300").is_err());
+        assert!(parse_like(&(), "1").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Reloads the widget code from a dynamic library whenever it is rebuilt,
+//! enabled with the `hot_reload` feature.
+//!
+//! The library has to export its top node with `hot_reload_entry!` and has to
+//! link narui dynamically (`crate-type = ["dylib"]` and `-C prefer-dynamic`),
+//! so the app and all versions of the library share one copy of narui. As they
+//! share its types, both have to be built with the same compiler and the same
+//! version of narui. Loaded libraries are never unloaded, as values created by
+//! their code can outlive them.
+
+use crate::UnevaluatedFragment;
+use libloading::Library;
+use std::{
+    fs,
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+pub(crate) const ENTRY_SYMBOL: &[u8] = b"narui_hot_reload_top_node";
+
+/// Exports the top node of a widget library for `app::render_hot_reload`.
+#[macro_export]
+macro_rules! hot_reload_entry {
+    ($top_node:expr) => {
+        #[no_mangle]
+        pub extern "C" fn narui_hot_reload_top_node() -> *mut $crate::UnevaluatedFragment {
+            ::std::boxed::Box::into_raw(::std::boxed::Box::new($top_node))
+        }
+    };
+}
+
+pub(crate) struct HotReloader {
+    path: PathBuf,
+    // the copies of the library are loaded from here, it is removed when the app
+    // exits
+    copies: PathBuf,
+    changed: Receiver<()>,
+    generation: usize,
+}
+
+impl HotReloader {
+    /// watches the library at `path`. `wake` is called from another thread
+    /// when it changed.
+    pub(crate) fn start(path: PathBuf, wake: impl Fn() + Send + 'static) -> Self {
+        let (tx, rx) = channel();
+        let watched = path.clone();
+        thread::spawn(move || {
+            let modified = || std::fs::metadata(&watched).and_then(|m| m.modified()).ok();
+            let mut last = modified();
+            loop {
+                thread::sleep(POLL_INTERVAL);
+                let current = modified();
+                if current == last || current.is_none() {
+                    continue;
+                }
+                // the linker might still be writing, so wait until the file settled
+                let mut settled: Option<SystemTime> = current;
+                loop {
+                    thread::sleep(POLL_INTERVAL);
+                    let current = modified();
+                    if current == settled {
+                        break;
+                    }
+                    settled = current;
+                }
+                last = settled;
+                if tx.send(()).is_err() {
+                    return;
+                }
+                wake();
+            }
+        });
+        let copies = std::env::temp_dir().join(format!("narui_hot_reload_{}", std::process::id()));
+        Self { path, copies, changed: rx, generation: 0 }
+    }
+
+    /// the top node of the new code, if the library was rebuilt and could be
+    /// loaded
+    pub(crate) fn poll(&mut self) -> Option<UnevaluatedFragment> {
+        self.changed.try_iter().last()?;
+        self.load()
+    }
+
+    pub(crate) fn load(&mut self) -> Option<UnevaluatedFragment> {
+        // the library is loaded from a copy, as loading the same path again would
+        // return the already loaded library
+        let extension = self.path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let copy = self.copies.join(format!("{}.{}", self.generation, extension));
+        self.generation += 1;
+        if let Err(e) = fs::create_dir_all(&self.copies).and_then(|_| fs::copy(&self.path, &copy)) {
+            log::error!("could not copy {}: {}", self.path.display(), e);
+            return None;
+        }
+
+        let top_node = unsafe {
+            let library = Library::new(&copy);
+            // a loaded library stays mapped when its file is removed, so only windows
+            // needs the copy until the directory is removed at exit
+            #[cfg(unix)]
+            let _ = fs::remove_file(&copy);
+            let library = match library {
+                Ok(library) => library,
+                Err(e) => {
+                    log::error!("could not load {}: {}", self.path.display(), e);
+                    return None;
+                }
+            };
+            type Entry = unsafe extern "C" fn() -> *mut UnevaluatedFragment;
+            let top_node = match library.get::<Entry>(ENTRY_SYMBOL) {
+                Ok(entry) => *Box::from_raw(entry()),
+                Err(e) => {
+                    log::error!("{} has no hot_reload_entry!: {}", self.path.display(), e);
+                    return None;
+                }
+            };
+            std::mem::forget(library);
+            top_node
+        };
+        log::info!("loaded {}", self.path.display());
+        Some(top_node)
+    }
+}
+
+impl Drop for HotReloader {
+    fn drop(&mut self) {
+        // fails on windows for the libraries that are still loaded
+        let _ = fs::remove_dir_all(&self.copies);
+    }
+}
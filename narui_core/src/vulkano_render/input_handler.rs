@@ -30,32 +30,30 @@ impl InputHandler {
     pub fn enqueue_input(&mut self, event: WindowEvent) -> bool {
         match event {
             WindowEvent::CursorMoved { position, .. } => {
-                self.cursor_position = position.into();
-                self.cursor_moved = true;
+                self.cursor_moved(position.into());
                 true
             }
-            WindowEvent::MouseInput {
-                state: ElementState::Pressed,
-                button: MouseButton::Left,
-                ..
-            } => {
-                self.cursor_pressed = true;
-                true
-            }
-            WindowEvent::MouseInput {
-                state: ElementState::Released,
-                button: MouseButton::Left,
-                ..
-            } => {
-                self.cursor_released = true;
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                self.mouse_input(state == ElementState::Pressed);
                 true
             }
             _ => false,
         }
     }
+    pub fn cursor_moved(&mut self, position: Physical<Vec2>) {
+        self.cursor_position = position;
+        self.cursor_moved = true;
+    }
+    pub fn mouse_input(&mut self, pressed: bool) {
+        if pressed {
+            self.cursor_pressed = true;
+        } else {
+            self.cursor_released = true;
+        }
+    }
     pub fn handle_input(
         &mut self,
-        input_render_object: &[(Idx, Option<Physical<Rect>>)],
+        input_render_object: &[(Idx, Physical<Rect>, Option<Physical<Rect>>)],
         layouter: &Layouter,
         context: CallbackContext,
         scale_factor: ScaleFactor,
@@ -65,8 +63,11 @@ impl InputHandler {
         }
 
         let mut updated = false;
-        for (key, clipping_rect) in input_render_object {
-            let (rect, obj) = layouter.get_positioned_physical(*key, scale_factor);
+        for (key, rect, clipping_rect) in input_render_object {
+            // the rect comes from rendering and not from the layouter, so hit testing
+            // matches what is on screen (for example during layout animations)
+            let (_, obj) = layouter.get_positioned_physical(*key, scale_factor);
+            let rect = *rect;
             let rect = if let Some(clipping_rect) = clipping_rect {
                 rect.map(|rect| clipping_rect.map(|clipping_rect| rect.clip(clipping_rect))).into()
             } else {
@@ -1,9 +1,15 @@
+#[cfg(feature = "devtools")]
+mod devtools;
 pub(crate) mod glyph_brush;
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
 mod input_handler;
 pub(crate) mod lyon;
 pub(crate) mod primitive_renderer;
 pub mod raw_render;
+pub(crate) mod recording;
 pub(crate) mod render;
+mod shortcut;
 pub(crate) mod subpass_stack;
 pub(crate) mod vk_util;
 
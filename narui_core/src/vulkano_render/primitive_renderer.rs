@@ -425,13 +425,13 @@ impl Renderer {
             element: RenderObjectOrSubPass::RenderObject(RenderObject::DebugRect),
             clipping_rect,
             rect,
-            ..
+            z_index,
         } = render_object
         {
             let rect = *rect;
             self.data.add_rounded_rect(
                 [1.0, 0.0, 0.0, 0.5],
-                0.0,
+                1.0 - *z_index as f32 / 65535.0,
                 rect,
                 clipping_rect.unwrap_or(rect),
                 0.0_f32.to_physical(scale_factor),
@@ -0,0 +1,486 @@
+//! Recording and replaying of input and of shouts from other threads.
+//!
+//! Setting `NARUI_RECORD=<file>` records the cursor and the left mouse button,
+//! window resizes, scale factors and all shouts from `ThreadContext`s together
+//! with the time they were handled at. `NARUI_REPLAY=<file>` plays a recording
+//! back in the window instead of taking live input, `app::replay_headless`
+//! plays it back without a window, for example in a test.
+//!
+//! Only shouts of numbers, bools and strings can be recorded, the other ones
+//! are applied live when replaying. Timers and animations run on the wall
+//! clock, so they are only reproduced as well as the timing of the replay
+//! matches the recording.
+
+use super::input_handler::InputHandler;
+use crate::{
+    context::{
+        key::{KeyMap, PackedKeyPart},
+        patched_tree::{HookRef, PatchedTree, TreeItem},
+    },
+    eval::layout::Physical,
+    Key,
+    Vec2,
+};
+use parking_lot::Mutex;
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fmt::{Display, Formatter},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use winit::event::{ElementState, MouseButton, WindowEvent};
+
+const HEADER: &str = "narui-recording 1";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordedEvent {
+    /// the position of the cursor in physical pixels
+    CursorMoved(Vec2),
+    /// the left mouse button was pressed (true) or released (false)
+    MouseInput(bool),
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    ScaleFactor(f64),
+    Shout(RecordedShout),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedShout {
+    // the packed key parts from the root down to the widget, none for stores
+    path: Option<Vec<PackedKeyPart>>,
+    index: u16,
+    type_name: String,
+    value: String,
+}
+
+/// The events that were handled together in one iteration of the event loop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    /// the time since the recording started
+    pub time: Duration,
+    pub events: Vec<RecordedEvent>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    pub batches: Vec<Batch>,
+}
+
+impl Recording {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err("not a narui recording".to_string()),
+        }
+
+        let mut batches: Vec<Batch> = vec![];
+        for (line_number, line) in lines {
+            let error = |e: &str| format!("line {}: {}", line_number + 1, e);
+            if line.is_empty() {
+                continue;
+            }
+            let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+            if kind == "batch" {
+                let millis = rest.parse().map_err(|_| error("invalid time"))?;
+                batches.push(Batch { time: Duration::from_millis(millis), events: vec![] });
+                continue;
+            }
+            let event = RecordedEvent::parse(kind, rest).ok_or_else(|| error("invalid event"))?;
+            batches
+                .last_mut()
+                .ok_or_else(|| error("event before the first batch"))?
+                .events
+                .push(event);
+        }
+        Ok(Self { batches })
+    }
+}
+
+impl Display for Recording {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        for batch in &self.batches {
+            write!(f, "{}", batch)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Batch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "batch {}", self.time.as_millis())?;
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl RecordedEvent {
+    pub(crate) fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                Some(Self::CursorMoved(Physical::from(*position).unwrap_physical()))
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                Some(Self::MouseInput(*state == ElementState::Pressed))
+            }
+            WindowEvent::Resized(size) => {
+                Some(Self::Resized { width: size.width, height: size.height })
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                Some(Self::ScaleFactor(*scale_factor))
+            }
+            _ => None,
+        }
+    }
+
+    fn parse(kind: &str, rest: &str) -> Option<Self> {
+        let mut parts = rest.split(' ');
+        Some(match kind {
+            "cursor_moved" => Self::CursorMoved(Vec2::new(
+                parts.next()?.parse().ok()?,
+                parts.next()?.parse().ok()?,
+            )),
+            "mouse_input" => Self::MouseInput(match parts.next()? {
+                "pressed" => true,
+                "released" => false,
+                _ => return None,
+            }),
+            "resized" => Self::Resized {
+                width: parts.next()?.parse().ok()?,
+                height: parts.next()?.parse().ok()?,
+            },
+            "scale_factor" => Self::ScaleFactor(parts.next()?.parse().ok()?),
+            "shout" => {
+                let mut parts = rest.splitn(4, ' ');
+                let path = match parts.next()? {
+                    "store" => None,
+                    path => Some(
+                        path.strip_prefix("root")?
+                            .split('.')
+                            .skip(1)
+                            .map(|tail| tail.parse().ok())
+                            .collect::<Option<_>>()?,
+                    ),
+                };
+                Self::Shout(RecordedShout {
+                    path,
+                    index: parts.next()?.parse().ok()?,
+                    type_name: parts.next()?.to_string(),
+                    value: parts.next()?.to_string(),
+                })
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl Display for RecordedEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CursorMoved(pos) => write!(f, "cursor_moved {} {}", pos.x, pos.y),
+            Self::MouseInput(pressed) => {
+                write!(f, "mouse_input {}", if *pressed { "pressed" } else { "released" })
+            }
+            Self::Resized { width, height } => write!(f, "resized {} {}", width, height),
+            Self::ScaleFactor(scale_factor) => write!(f, "scale_factor {}", scale_factor),
+            Self::Shout(RecordedShout { path, index, type_name, value }) => {
+                write!(f, "shout ")?;
+                match path {
+                    None => write!(f, "store")?,
+                    Some(path) => {
+                        write!(f, "root")?;
+                        for tail in path {
+                            write!(f, ".{}", tail)?;
+                        }
+                    }
+                }
+                write!(f, " {} {} {}", index, type_name, value)
+            }
+        }
+    }
+}
+
+impl RecordedShout {
+    fn new(key_map: &KeyMap, hook: HookRef, type_name: &str, value: String) -> Self {
+        let ((key, index), ..) = hook;
+        let path = if key == Key::STORE {
+            None
+        } else {
+            let mut path = vec![];
+            let mut current = key;
+            while current != Key::ROOT {
+                path.push(key_map.key_tail(current).pack());
+                current = key_map.key_parent(current);
+            }
+            path.reverse();
+            Some(path)
+        };
+        Self { path, index, type_name: type_name.to_string(), value }
+    }
+
+    fn apply(&self, tree: &PatchedTree, key_map: &KeyMap) {
+        let key = match &self.path {
+            None => Some(Key::STORE),
+            Some(path) => {
+                path.iter().try_fold(Key::ROOT, |parent, tail| key_map.find_child(parent, *tail))
+            }
+        };
+        let hook = key.and_then(|key| {
+            tree.hooks_of(key).into_iter().find(|((_, index), ..)| *index == self.index)
+        });
+        let value = decode_value(&self.type_name, &self.value);
+        let type_id = |hook| tree.get_patched(hook).map(|current| (**current).type_id());
+        match (hook, value) {
+            (Some(hook), Some(value)) if Some((*value).type_id()) == type_id(hook) => {
+                tree.set(hook, value)
+            }
+            _ => log::warn!("could not replay the shout `{}`", RecordedEvent::Shout(self.clone())),
+        }
+    }
+}
+
+/// applies a recorded event that does not concern the window
+pub(crate) fn replay_event(
+    event: &RecordedEvent,
+    input_handler: &mut InputHandler,
+    tree: &PatchedTree,
+    key_map: &KeyMap,
+) {
+    match event {
+        RecordedEvent::CursorMoved(pos) => input_handler.cursor_moved(Physical::new(*pos)),
+        RecordedEvent::MouseInput(pressed) => input_handler.mouse_input(*pressed),
+        RecordedEvent::Shout(shout) => shout.apply(tree, key_map),
+        RecordedEvent::Resized { .. } | RecordedEvent::ScaleFactor(_) => {}
+    }
+}
+
+macro_rules! recordable_types {
+    ($macro:ident) => {
+        $macro!(bool, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64, String)
+    };
+}
+
+fn encode_value(value: &dyn Any) -> Option<(&'static str, String)> {
+    macro_rules! try_encode {
+        ($($ty:ident),*) => {
+            $(if let Some(value) = value.downcast_ref::<$ty>() {
+                return Some((stringify!($ty), format!("{:?}", value)));
+            })*
+        };
+    }
+    recordable_types!(try_encode);
+    None
+}
+
+fn decode_value(type_name: &str, value: &str) -> Option<TreeItem> {
+    if type_name == "String" {
+        return unescape(value).map(|v| Box::new(v) as _);
+    }
+    macro_rules! try_decode {
+        ($($ty:ident),*) => {
+            $(if type_name == stringify!($ty) {
+                return value.parse::<$ty>().ok().map(|v| Box::new(v) as _);
+            })*
+        };
+    }
+    recordable_types!(try_decode);
+    None
+}
+
+// reverses the escaping of the `Debug` impl of strings
+fn unescape(value: &str) -> Option<String> {
+    let mut chars = value.strip_prefix('"')?.strip_suffix('"')?.chars();
+    let mut unescaped = String::new();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'u' => {
+                let code: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+            }
+            c => c,
+        });
+    }
+    Some(unescaped)
+}
+
+// the shouts from other threads since the last batch
+type RecordedShouts = Arc<Mutex<Vec<(HookRef, &'static str, String)>>>;
+
+pub(crate) struct Recorder {
+    file: BufWriter<File>,
+    start: Instant,
+    events: Vec<RecordedEvent>,
+    shouts: RecordedShouts,
+}
+
+impl Recorder {
+    pub(crate) fn from_env(tree: &PatchedTree) -> Option<Self> {
+        let path = std::env::var("NARUI_RECORD").ok()?;
+        let mut file = match File::create(&path) {
+            Ok(file) => BufWriter::new(file),
+            Err(e) => {
+                log::error!("could not create the recording {}: {}", path, e);
+                return None;
+            }
+        };
+        writeln!(file, "{}", HEADER).ok()?;
+        log::info!("recording to {}", path);
+
+        let shouts = Arc::new(Mutex::new(vec![]));
+        let recorded_shouts = shouts.clone();
+        tree.set_thread_shout_hook(Some(Box::new(move |hook, value| {
+            match encode_value(value) {
+                Some((type_name, value)) => recorded_shouts.lock().push((hook, type_name, value)),
+                None => log::warn!(
+                    "a shout from a thread can not be recorded, as its type is not supported"
+                ),
+            }
+            true
+        })));
+
+        Some(Self { file, start: Instant::now(), events: vec![], shouts })
+    }
+
+    pub(crate) fn record(&mut self, event: RecordedEvent) { self.events.push(event); }
+
+    /// writes the events since the last call as one batch. Has to be called
+    /// before the widgets that shouts were recorded for can be removed.
+    pub(crate) fn finish_batch(&mut self, key_map: &KeyMap) {
+        for (hook, type_name, value) in self.shouts.lock().drain(..) {
+            let shout = RecordedShout::new(key_map, hook, type_name, value);
+            self.events.push(RecordedEvent::Shout(shout));
+        }
+        if self.events.is_empty() {
+            return;
+        }
+        let batch = Batch { time: self.start.elapsed(), events: std::mem::take(&mut self.events) };
+        // flushed right away, so the recording is complete even if the app crashes
+        if let Err(e) = write!(self.file, "{}", batch).and_then(|_| self.file.flush()) {
+            log::error!("could not write the recording: {}", e);
+        }
+    }
+}
+
+pub(crate) struct Replay {
+    batches: VecDeque<Batch>,
+    start: Instant,
+    tree: Arc<PatchedTree>,
+}
+
+impl Replay {
+    pub(crate) fn new(recording: Recording, tree: Arc<PatchedTree>) -> Self {
+        // the recorded shouts are replayed instead of the live ones
+        tree.set_thread_shout_hook(Some(Box::new(|_, value| encode_value(value).is_none())));
+        Self { batches: recording.batches.into(), start: Instant::now(), tree }
+    }
+
+    pub(crate) fn from_env(tree: Arc<PatchedTree>) -> Option<Self> {
+        let path = std::env::var("NARUI_REPLAY").ok()?;
+        match Recording::load(&path) {
+            Ok(recording) => {
+                log::info!("replaying {}", path);
+                Some(Self::new(recording, tree))
+            }
+            Err(e) => {
+                log::error!("could not load the recording {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// the next batch, if it is due
+    pub(crate) fn due(&mut self, now: Instant) -> Option<Batch> {
+        if self.start + self.batches.front()?.time <= now {
+            self.batches.pop_front()
+        } else {
+            None
+        }
+    }
+
+    /// the next batch, regardless of its time
+    pub(crate) fn next_batch(&mut self) -> Option<Batch> { self.batches.pop_front() }
+
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        self.batches.front().map(|batch| self.start + batch.time)
+    }
+
+    pub(crate) fn is_finished(&self) -> bool { self.batches.is_empty() }
+}
+
+impl Drop for Replay {
+    fn drop(&mut self) { self.tree.set_thread_shout_hook(None); }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let recording = Recording {
+            batches: vec![
+                Batch {
+                    time: Duration::from_millis(0),
+                    events: vec![
+                        RecordedEvent::Resized { width: 800, height: 600 },
+                        RecordedEvent::ScaleFactor(1.5),
+                    ],
+                },
+                Batch {
+                    time: Duration::from_millis(1234),
+                    events: vec![
+                        RecordedEvent::CursorMoved(Vec2::new(10.5, -3.0)),
+                        RecordedEvent::MouseInput(true),
+                        RecordedEvent::Shout(RecordedShout {
+                            path: Some(vec![-5, 7]),
+                            index: 2,
+                            type_name: "String".to_string(),
+                            value: format!("{:?}", "a \"quoted\"\nline ✓\u{7}"),
+                        }),
+                        RecordedEvent::Shout(RecordedShout {
+                            path: None,
+                            index: 0,
+                            type_name: "f32".to_string(),
+                            value: "0.1".to_string(),
+                        }),
+                    ],
+                },
+            ],
+        };
+        assert_eq!(Recording::parse(&recording.to_string()), Ok(recording));
+    }
+
+    #[test]
+    fn values() {
+        let (type_name, value) = encode_value(&"a \"quoted\"\nline ✓\u{7}".to_string()).unwrap();
+        let decoded = decode_value(type_name, &value).unwrap();
+        assert_eq!(decoded.downcast_ref::<String>().unwrap(), "a \"quoted\"\nline ✓\u{7}");
+
+        let (type_name, value) = encode_value(&0.1f32).unwrap();
+        assert_eq!(decode_value(type_name, &value).unwrap().downcast_ref::<f32>(), Some(&0.1));
+
+        assert!(encode_value(&Vec2::zero()).is_none());
+    }
+}
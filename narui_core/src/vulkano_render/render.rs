@@ -1,32 +1,50 @@
-use super::{glyph_brush::GlyphBrush, input_handler::InputHandler, lyon::Lyon};
+use super::{
+    glyph_brush::GlyphBrush,
+    input_handler::InputHandler,
+    lyon::Lyon,
+    recording::{replay_event, RecordedEvent, Recorder, Recording, Replay},
+};
 use crate::{
     eval::{
         delta_eval::Evaluator,
         layout::{Layouter, Physical, PhysicalPositionedElement, ScaleFactor},
     },
     geom::Rect,
-    util::fps_report::FPSReporter,
+    util::{frame_stats::FrameStatsRecorder, span::span},
     RenderObject,
+    Stores,
     UnevaluatedFragment,
     Vec2,
 };
 use freelist::Idx;
 
+#[cfg(feature = "hot_reload")]
+use crate::vulkano_render::hot_reload::HotReloader;
 use crate::{
-    context::context,
+    context::{context, scheduler::FRAME_INTERVAL},
     eval::layout::RenderObjectOrSubPass,
     vulkano_render::{
         primitive_renderer::Renderer,
+        shortcut::{Shortcut, ShortcutListener},
         subpass_stack::{create_framebuffer, AbstractFramebuffer, AbstractImage, SubPassStack},
         vk_util::VulkanContext,
     },
+    CallbackContext,
+    ContextInspect,
 };
+#[cfg(feature = "devtools")]
+use crate::{eval::layout::OVERLAY_Z_INDEX, vulkano_render::devtools::Devtools};
+use parking_lot::Mutex;
 use std::{
     convert::TryFrom,
+    fs::OpenOptions,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
 };
 use vulkano::{
+    device::Device,
     format::Format,
     image::{ImageAccess, ImageUsage, SampleCount, SwapchainImage},
     render_pass::RenderPass,
@@ -43,6 +61,7 @@ use vulkano::{
 };
 use vulkano_win::VkSurfaceBuild;
 use winit::{
+    dpi::PhysicalSize,
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     platform::run_return::EventLoopExtRunReturn,
@@ -50,7 +69,61 @@ use winit::{
 };
 
 pub fn render(window_builder: WindowBuilder, top_node: UnevaluatedFragment) {
-    let mut event_loop: EventLoop<()> = EventLoop::new();
+    render_with_stores(window_builder, Stores::new(), top_node)
+}
+
+pub fn render_with_stores(
+    window_builder: WindowBuilder,
+    stores: Stores,
+    top_node: UnevaluatedFragment,
+) {
+    #[cfg(feature = "hot_reload")]
+    run(window_builder, stores, top_node, None);
+    #[cfg(not(feature = "hot_reload"))]
+    run(window_builder, stores, top_node);
+}
+
+/// Like `render_with_stores`, but the widget code is loaded from the dynamic
+/// library at `library` (see `hot_reload_entry!`) and reloaded whenever the
+/// library is rebuilt. The values of hooks whose widget path and type did not
+/// change are kept, everything is re-evaluated with the new code.
+#[cfg(feature = "hot_reload")]
+pub fn render_hot_reload(
+    window_builder: WindowBuilder,
+    stores: Stores,
+    library: impl Into<std::path::PathBuf>,
+) {
+    let tree = Arc::downgrade(&stores.tree);
+    let mut hot_reloader = HotReloader::start(library.into(), move || {
+        if let Some(tree) = tree.upgrade() {
+            tree.wake();
+        }
+    });
+    let top_node = hot_reloader.load().expect("could not load the widget library");
+    run(window_builder, stores, top_node, Some(hot_reloader))
+}
+
+fn run(
+    window_builder: WindowBuilder,
+    stores: Stores,
+    top_node: UnevaluatedFragment,
+    #[cfg(feature = "hot_reload")] mut hot_reloader: Option<HotReloader>,
+) {
+    let mut event_loop: EventLoop<()> = EventLoop::with_user_event();
+    // shouts from other threads wake the event loop, so it can sleep while there is
+    // nothing to do
+    let proxy = Mutex::new(event_loop.create_proxy());
+    stores.tree.set_waker(move || {
+        // this only fails if the event loop is already gone
+        let _ = proxy.lock().send_event(());
+    });
+    #[cfg(feature = "devtools")]
+    let mut devtools = {
+        let proxy = Mutex::new(event_loop.create_proxy());
+        Devtools::start(move || {
+            let _ = proxy.lock().send_event(());
+        })
+    };
     let VulkanContext { device, queues } = VulkanContext::create().unwrap();
     let surface = window_builder.build_vk_surface(&event_loop, device.instance().clone()).unwrap();
     let queue = queues
@@ -93,85 +166,176 @@ pub fn render(window_builder: WindowBuilder, top_node: UnevaluatedFragment) {
         .expect("cant create swapchain")
     };
 
-    let render_pass = vulkano::single_pass_renderpass!(device.clone(),
-        attachments: {
-            intermediary: {
-                load: Load,
-                store: Store,
-                format: swapchain.image_format(),
-                samples: SampleCount::Sample4,
-            },
-            depth: {
-                load: Load,
-                store: Store,
-                format: Format::D16_UNORM,
-                samples: SampleCount::Sample4,
-            },
-            color: {
-                load: DontCare,
-                store: Store,
-                format: swapchain.image_format(),
-                samples: SampleCount::Sample1,
-            }
-        },
-        pass: {
-            color: [intermediary],
-            depth_stencil: {depth},
-            resolve: [color],
-        }
-    )
-    .unwrap();
+    let render_pass = create_render_pass(device.clone(), swapchain.image_format());
 
     let mut framebuffers = window_size_dependent_setup(&images, render_pass.clone());
     let mut previous_frame_end = Some(sync::now(device.clone()).boxed());
 
 
-    let mut fps_report = FPSReporter::new("gui");
+    let mut frame_stats = FrameStatsRecorder::new(stores.tree.clone());
 
     let mut lyon_renderer = Lyon::new();
     let mut text_render = GlyphBrush::new(queue.clone());
     let mut renderer = Renderer::new(render_pass.clone(), device.clone(), queue.clone());
     let mut input_handler = InputHandler::new();
+    // dumping the tree is opt-in, as the dump might contain private data
+    let inspector_dir = std::env::var_os("NARUI_INSPECTOR_DIR").map(PathBuf::from);
+    let inspector_shortcut = Shortcut::from_env("NARUI_INSPECTOR_SHORTCUT", "F12");
+    let mut shortcut_listener = ShortcutListener::default();
+
+    let tree = stores.tree.clone();
+    let mut replay = Replay::from_env(tree.clone());
+    let mut recorder = if replay.is_none() { Recorder::from_env(&tree) } else { None };
+    if let Some(recorder) = &mut recorder {
+        recorder.record(RecordedEvent::Resized { width: dimensions[0], height: dimensions[1] });
+        recorder.record(RecordedEvent::ScaleFactor(surface.window().scale_factor()));
+    }
 
     let mut layouter = Layouter::new();
-    let mut evaluator = Evaluator::new(
-        context::VulkanContext { device: device.clone(), queues, render_pass: render_pass.clone() },
-        top_node,
-        &mut layouter,
-    );
+    let vulkan_context =
+        context::VulkanContext { device: device.clone(), queues, render_pass: render_pass.clone() };
+    let mut evaluator =
+        Evaluator::new(vulkan_context.clone(), top_node, &mut layouter, stores.tree);
 
     let mut recreate_swapchain = false;
     let mut has_update = true;
-    let mut input_render_objects: Vec<(Idx, Option<Physical<Rect>>)> = Vec::new();
+    let mut input_render_objects: Vec<(Idx, Physical<Rect>, Option<Physical<Rect>>)> = Vec::new();
+    let mut layout_animating = false;
+    #[cfg(feature = "devtools")]
+    let mut pending_evaluated = Vec::new();
     event_loop.run_return(move |event, _, control_flow| {
-        *control_flow = ControlFlow::WaitUntil(Instant::now() + Duration::from_millis(1000 / 70));
         let scale_factor = ScaleFactor(surface.window().scale_factor() as f32);
 
         match event {
             Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
                 *control_flow = ControlFlow::Exit;
             }
-            Event::WindowEvent { event: WindowEvent::Resized(_), .. } => {
+            Event::WindowEvent { event: WindowEvent::Resized(size), .. } => {
+                if let Some(recorder) = &mut recorder {
+                    let (width, height) = (size.width, size.height);
+                    recorder.record(RecordedEvent::Resized { width, height });
+                }
                 recreate_swapchain = true;
                 surface.window().request_redraw();
             }
             Event::WindowEvent { event, .. } => {
-                input_handler.enqueue_input(event);
+                if shortcut_listener.pressed(&event, &inspector_shortcut) {
+                    if let Some(dir) = &inspector_dir {
+                        let context = evaluator.callback_context(&layouter, &scale_factor);
+                        match write_tree_dump(dir, &context.inspect_tree().to_string()) {
+                            Ok(path) => log::info!("wrote the widget tree to {}", path.display()),
+                            Err(e) => log::error!("could not write the widget tree: {}", e),
+                        }
+                    }
+                }
+                if let Some(recorder) = &mut recorder {
+                    if let Some(event) = RecordedEvent::from_window_event(&event) {
+                        recorder.record(event);
+                    }
+                }
+                // while replaying, the recorded input replaces the live one
+                if replay.is_none() {
+                    input_handler.enqueue_input(event);
+                }
                 *control_flow = ControlFlow::Poll;
             }
             Event::MainEventsCleared => {
+                let update_start = Instant::now();
+                if let Some(recorder) = &mut recorder {
+                    recorder.finish_batch(&evaluator.key_map);
+                }
+                if let Some(replay) = &mut replay {
+                    while let Some(batch) = replay.due(update_start) {
+                        for event in batch.events {
+                            match event {
+                                RecordedEvent::Resized { width, height } => surface
+                                    .window()
+                                    .set_inner_size(PhysicalSize::new(width, height)),
+                                // the scale factor of a window can not be changed
+                                RecordedEvent::ScaleFactor(_) => {}
+                                event => replay_event(
+                                    &event,
+                                    &mut input_handler,
+                                    &tree,
+                                    &evaluator.key_map,
+                                ),
+                            }
+                        }
+                    }
+                }
+                if matches!(&replay, Some(replay) if replay.is_finished()) {
+                    log::info!("the replay is finished");
+                    replay = None;
+                }
+                #[cfg(feature = "hot_reload")]
+                if let Some(top_node) = hot_reloader.as_mut().and_then(HotReloader::poll) {
+                    tree.carry_over_widgets(&evaluator.key_map);
+                    layouter = Layouter::new();
+                    evaluator = Evaluator::new(
+                        vulkan_context.clone(),
+                        top_node,
+                        &mut layouter,
+                        tree.clone(),
+                    );
+                    tree.finish_carry_over();
+                    input_render_objects.clear();
+                    has_update = true;
+                }
                 input_handler.handle_input(
                     &input_render_objects[..],
                     &layouter,
                     evaluator.callback_context(&layouter, &scale_factor),
                     scale_factor,
                 );
+                evaluator.run_scheduled(&layouter, &scale_factor);
+                #[cfg(feature = "devtools")]
+                if let Some(devtools) = &mut devtools {
+                    if devtools
+                        .handle_commands(&evaluator.callback_context(&layouter, &scale_factor))
+                    {
+                        surface.window().request_redraw();
+                    }
+                }
                 has_update |= evaluator.update(&mut layouter);
-                if has_update {
+                #[cfg(feature = "devtools")]
+                pending_evaluated.extend(evaluator.take_evaluated());
+                frame_stats.current.evaluation += update_start.elapsed();
+                frame_stats.current.reevaluated += evaluator.take_reevaluated();
+                if has_update || layout_animating {
                     surface.window().request_redraw();
                 }
+                // shouts that happened during the update (for example from an animation) are
+                // picked up right away, otherwise we sleep until the next timer is due or
+                // something wakes us
+                if *control_flow != ControlFlow::Exit {
+                    *control_flow = if evaluator.is_patched() {
+                        ControlFlow::Poll
+                    } else if let Some(deadline) = evaluator.next_deadline() {
+                        ControlFlow::WaitUntil(
+                            if layout_animating {
+                                deadline.min(Instant::now() + FRAME_INTERVAL)
+                            } else {
+                                deadline
+                            },
+                        )
+                    } else if layout_animating {
+                        ControlFlow::WaitUntil(Instant::now() + FRAME_INTERVAL)
+                    } else {
+                        ControlFlow::Wait
+                    };
+                    if let Some(deadline) = replay.as_ref().and_then(Replay::next_deadline) {
+                        *control_flow = match *control_flow {
+                            ControlFlow::Wait => ControlFlow::WaitUntil(deadline),
+                            ControlFlow::WaitUntil(other) => {
+                                ControlFlow::WaitUntil(other.min(deadline))
+                            }
+                            other => other,
+                        };
+                    }
+                }
             }
             Event::RedrawRequested(_) => {
+                let _frame_span = span!("frame");
                 previous_frame_end.as_mut().unwrap().cleanup_finished();
 
                 if recreate_swapchain {
@@ -225,7 +389,17 @@ pub fn render(window_builder: WindowBuilder, top_node: UnevaluatedFragment) {
 
                 input_render_objects.clear();
 
+                let layout_start = Instant::now();
                 layouter.do_layout(evaluator.top_node, Vec2::from(dimensions) / scale_factor.0);
+                layout_animating = layouter.animate(Instant::now());
+                frame_stats.current.layout = layout_start.elapsed();
+                #[cfg(feature = "devtools")]
+                if let Some(devtools) = &devtools {
+                    devtools.send_update(
+                        &evaluator.callback_context(&layouter, &scale_factor),
+                        &std::mem::take(&mut pending_evaluated),
+                    );
+                }
 
                 let mut subpass_stack = SubPassStack::new(
                     format,
@@ -236,25 +410,60 @@ pub fn render(window_builder: WindowBuilder, top_node: UnevaluatedFragment) {
                     depth_image.clone(),
                 );
 
-                for (_, obj) in layouter.iter_layouted_physical(evaluator.top_node, scale_factor) {
-                    text_render.prerender(&obj, scale_factor);
-                }
-                let (mut text_state, texture, texture_fut) = text_render.finish(&mut renderer.data);
+                let text_prerender_start = Instant::now();
+                let (mut text_state, texture, texture_fut) = {
+                    let _span = span!("text_prerender");
+                    for (_, obj) in
+                        layouter.iter_layouted_physical(evaluator.top_node, scale_factor)
+                    {
+                        text_render.prerender(&obj, scale_factor);
+                    }
+                    text_render.finish(&mut renderer.data)
+                };
+                frame_stats.current.text_prerender = text_prerender_start.elapsed();
 
-                for (idx, obj) in layouter.iter_layouted_physical(evaluator.top_node, scale_factor)
+                let tessellation_start = Instant::now();
                 {
-                    subpass_stack.handle(&renderer.data, &obj);
-                    lyon_renderer.render(&mut renderer.data, &obj, scale_factor);
-                    text_render.render(&obj, &mut renderer.data, &mut text_state);
-                    renderer.render(&obj, scale_factor);
-                    if let PhysicalPositionedElement {
-                        element: RenderObjectOrSubPass::RenderObject(RenderObject::Input { .. }),
-                        ..
-                    } = &obj
+                    let _span = span!("tessellation");
+                    for (idx, obj) in
+                        layouter.iter_layouted_physical(evaluator.top_node, scale_factor)
                     {
-                        input_render_objects.push((idx, obj.clipping_rect));
+                        subpass_stack.handle(&renderer.data, &obj);
+                        lyon_renderer.render(&mut renderer.data, &obj, scale_factor);
+                        text_render.render(&obj, &mut renderer.data, &mut text_state);
+                        renderer.render(&obj, scale_factor);
+                        if let PhysicalPositionedElement {
+                            element:
+                                RenderObjectOrSubPass::RenderObject(RenderObject::Input { .. }),
+                            ..
+                        } = &obj
+                        {
+                            input_render_objects.push((idx, obj.rect, obj.clipping_rect));
+                        }
+                    }
+                    #[cfg(feature = "devtools")]
+                    if let Some(rect) = devtools.as_ref().and_then(|devtools| {
+                        let context = evaluator.callback_context(&layouter, &scale_factor);
+                        devtools.highlighted_rect(&context)
+                    }) {
+                        renderer.render(
+                            &PhysicalPositionedElement {
+                                element: RenderObjectOrSubPass::RenderObject(
+                                    &RenderObject::DebugRect,
+                                ),
+                                clipping_rect: None,
+                                rect,
+                                z_index: OVERLAY_Z_INDEX,
+                            },
+                            scale_factor,
+                        );
                     }
                 }
+                frame_stats.current.tessellation = tessellation_start.elapsed();
+                frame_stats.current.vertices = renderer.data.vertices.len();
+                frame_stats.current.indices = renderer.data.indices.len();
+
+                let recording_start = Instant::now();
                 subpass_stack.finish(&renderer.data);
                 let (
                     vertex_fut,
@@ -283,24 +492,28 @@ pub fn render(window_builder: WindowBuilder, top_node: UnevaluatedFragment) {
                     vertex_buffer,
                     index_buffer,
                 );
-                fps_report.frame();
-
-                let future = previous_frame_end
-                    .take()
-                    .unwrap()
-                    .join(
-                        texture_fut
-                            .map(|v| Box::new(v) as Box<dyn GpuFuture>)
-                            .unwrap_or_else(|| Box::new(sync::now(device.clone())) as _),
-                    )
-                    .join(index_fut)
-                    .join(vertex_fut)
-                    .join(primitive_fut)
-                    .join(acquire_fut)
-                    .then_execute(queue.clone(), command_buffer)
-                    .unwrap()
-                    .then_swapchain_present(queue.clone(), swapchain.clone(), image_num)
-                    .then_signal_fence_and_flush();
+                frame_stats.current.recording = recording_start.elapsed();
+
+                let submission_start = Instant::now();
+                let future = {
+                    let _span = span!("submit");
+                    previous_frame_end
+                        .take()
+                        .unwrap()
+                        .join(
+                            texture_fut
+                                .map(|v| Box::new(v) as Box<dyn GpuFuture>)
+                                .unwrap_or_else(|| Box::new(sync::now(device.clone())) as _),
+                        )
+                        .join(index_fut)
+                        .join(vertex_fut)
+                        .join(primitive_fut)
+                        .join(acquire_fut)
+                        .then_execute(queue.clone(), command_buffer)
+                        .unwrap()
+                        .then_swapchain_present(queue.clone(), swapchain.clone(), image_num)
+                        .then_signal_fence_and_flush()
+                };
 
                 match future {
                     Ok(future) => {
@@ -316,6 +529,14 @@ pub fn render(window_builder: WindowBuilder, top_node: UnevaluatedFragment) {
                     }
                 }
 
+                frame_stats.current.submission = submission_start.elapsed();
+                #[allow(unused_variables)]
+                let stats = frame_stats.finish_frame();
+                #[cfg(feature = "devtools")]
+                if let Some(devtools) = &devtools {
+                    devtools.send_frame(&stats);
+                }
+
                 for callback in after_frame_callbacks {
                     callback(&callback_context);
                 }
@@ -325,6 +546,123 @@ pub fn render(window_builder: WindowBuilder, top_node: UnevaluatedFragment) {
     });
 }
 
+/// Replays a recording without a window and calls `check` with the resulting
+/// state, to turn recordings into regression tests. The batches are replayed
+/// right after each other, regardless of their recorded time.
+pub fn replay_headless(
+    stores: Stores,
+    top_node: UnevaluatedFragment,
+    recording: Recording,
+    check: impl FnOnce(&CallbackContext),
+) {
+    let tree = stores.tree.clone();
+    let mut replay = Replay::new(recording, tree.clone());
+    let mut input_handler = InputHandler::new();
+    let mut layouter = Layouter::new();
+    let vulkan_context = headless_vulkan_context().expect("replaying needs a vulkan device");
+    let mut evaluator = Evaluator::new(vulkan_context, top_node, &mut layouter, stores.tree);
+
+    let mut dimensions = [800, 600];
+    let mut scale_factor = ScaleFactor(1.0);
+    while let Some(batch) = replay.next_batch() {
+        for event in &batch.events {
+            match event {
+                RecordedEvent::Resized { width, height } => dimensions = [*width, *height],
+                RecordedEvent::ScaleFactor(factor) => scale_factor = ScaleFactor(*factor as f32),
+                _ => {}
+            }
+        }
+        layouter.do_layout(evaluator.top_node, Vec2::from(dimensions) / scale_factor.0);
+        layouter.animate(Instant::now());
+        let input_render_objects: Vec<_> = layouter
+            .iter_layouted_physical(evaluator.top_node, scale_factor)
+            .filter(|(_, obj)| {
+                matches!(
+                    obj.element,
+                    RenderObjectOrSubPass::RenderObject(RenderObject::Input { .. })
+                )
+            })
+            .map(|(idx, obj)| (idx, obj.rect, obj.clipping_rect))
+            .collect();
+
+        for event in &batch.events {
+            replay_event(event, &mut input_handler, &tree, &evaluator.key_map);
+        }
+        input_handler.handle_input(
+            &input_render_objects[..],
+            &layouter,
+            evaluator.callback_context(&layouter, &scale_factor),
+            scale_factor,
+        );
+        evaluator.run_scheduled(&layouter, &scale_factor);
+        evaluator.update(&mut layouter);
+
+        let after_frame_callbacks = std::mem::take(&mut evaluator.after_frame_callbacks);
+        let callback_context = evaluator.callback_context(&layouter, &scale_factor);
+        for callback in after_frame_callbacks {
+            callback(&callback_context);
+        }
+    }
+
+    layouter.do_layout(evaluator.top_node, Vec2::from(dimensions) / scale_factor.0);
+    layouter.animate(Instant::now());
+    check(&evaluator.callback_context(&layouter, &scale_factor));
+}
+
+// writes the dump to a new file in dir, never overwriting an existing one
+fn write_tree_dump(dir: &Path, dump: &str) -> std::io::Result<PathBuf> {
+    let mut n = 0;
+    loop {
+        let path = dir.join(format!("narui_tree-{}-{}.json", std::process::id(), n));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => return file.write_all(dump.as_bytes()).map(|()| path),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// a vulkan context for evaluating widgets without a window, `None` if there is
+/// no vulkan device
+pub(crate) fn headless_vulkan_context() -> Option<context::VulkanContext> {
+    let VulkanContext { device, queues } = VulkanContext::create()
+        .map_err(|error| log::warn!("could not create a vulkan context: {}", error))
+        .ok()?;
+    let render_pass = create_render_pass(device.clone(), Format::B8G8R8A8_SRGB);
+    Some(context::VulkanContext { device, queues, render_pass })
+}
+
+fn create_render_pass(device: Arc<Device>, format: Format) -> Arc<RenderPass> {
+    vulkano::single_pass_renderpass!(device,
+        attachments: {
+            intermediary: {
+                load: Load,
+                store: Store,
+                format: format,
+                samples: SampleCount::Sample4,
+            },
+            depth: {
+                load: Load,
+                store: Store,
+                format: Format::D16_UNORM,
+                samples: SampleCount::Sample4,
+            },
+            color: {
+                load: DontCare,
+                store: Store,
+                format: format,
+                samples: SampleCount::Sample1,
+            }
+        },
+        pass: {
+            color: [intermediary],
+            depth_stencil: {depth},
+            resolve: [color],
+        }
+    )
+    .unwrap()
+}
+
 /// This method is called once during initialization, then again whenever the
 /// window is resized
 fn window_size_dependent_setup(
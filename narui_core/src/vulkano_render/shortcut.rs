@@ -0,0 +1,70 @@
+use winit::event::{ElementState, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent};
+
+/// A keyboard shortcut like `ctrl+shift+I`. Keys are named like the variants
+/// of `VirtualKeyCode`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Shortcut {
+    modifiers: ModifiersState,
+    key: String,
+}
+
+impl Shortcut {
+    pub(crate) fn parse(shortcut: &str) -> Option<Self> {
+        let mut modifiers = ModifiersState::empty();
+        let mut key = None;
+        for part in shortcut.split('+').map(str::trim) {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "control" => modifiers |= ModifiersState::CTRL,
+                "shift" => modifiers |= ModifiersState::SHIFT,
+                "alt" => modifiers |= ModifiersState::ALT,
+                "logo" | "super" | "cmd" => modifiers |= ModifiersState::LOGO,
+                _ if key.is_none() && !part.is_empty() => key = Some(part.to_string()),
+                _ => return None,
+            }
+        }
+        Some(Self { modifiers, key: key? })
+    }
+
+    /// reads the shortcut from the environment variable `var`, falling back to
+    /// `default`
+    pub(crate) fn from_env(var: &str, default: &str) -> Self {
+        std::env::var(var)
+            .ok()
+            .and_then(|shortcut| {
+                let parsed = Self::parse(&shortcut);
+                if parsed.is_none() {
+                    log::warn!("could not parse shortcut {}={:?}", var, shortcut);
+                }
+                parsed
+            })
+            .unwrap_or_else(|| Self::parse(default).unwrap())
+    }
+
+    fn matches(&self, key: VirtualKeyCode, modifiers: ModifiersState) -> bool {
+        self.modifiers == modifiers && format!("{:?}", key).eq_ignore_ascii_case(&self.key)
+    }
+}
+
+/// keeps track of the modifiers to detect when a `Shortcut` is pressed
+#[derive(Default)]
+pub(crate) struct ShortcutListener {
+    modifiers: ModifiersState,
+}
+
+impl ShortcutListener {
+    /// returns true if the event is the press of the shortcut
+    pub(crate) fn pressed(&mut self, event: &WindowEvent, shortcut: &Shortcut) -> bool {
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                false
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. },
+                ..
+            } => shortcut.matches(*key, self.modifiers),
+            _ => false,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
     eval::layout::{Physical, PhysicalPositionedElement, RenderObjectOrSubPass},
+    util::span::span,
     vulkano_render::primitive_renderer::RenderData,
     CallbackContext,
     Rect,
@@ -352,6 +353,7 @@ impl SubPassStack {
             u64,
         ),
     {
+        let _span = span!("record_commands", commands = self.render_commands.len());
         log::trace!("collected render commands {:?}", self.render_commands);
 
         let mut builder = AutoCommandBufferBuilder::primary(
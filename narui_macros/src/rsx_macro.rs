@@ -59,7 +59,19 @@ fn handle_rsx_node(x: Node) -> (TokenStream, TokenStream) {
             };
 
             if name.to_string() == "key" {
-                key = quote! {#narui::KeyPart::FragmentKey { widget_id: #node_name::WIDGET_ID.load(std::sync::atomic::Ordering::SeqCst), location_id: (#loc - __widget_loc_start) as u16, key: #value as _ }}
+                key = quote! {{
+                    let __location_id = (#loc - __widget_loc_start) as u16;
+                    let __widget_id = #node_name::WIDGET_ID.load(std::sync::atomic::Ordering::SeqCst);
+                    let __value = #value;
+                    match #narui::direct_key!(&__value) {
+                        Some(__key) => #narui::KeyPart::FragmentKey { widget_id: __widget_id, location_id: __location_id, key: __key },
+                        None => {
+                            let __formatter = #narui::arg_formatter!(&__value);
+                            let __key = context.key_map.intern_key(context.widget_local.key, __location_id, __value, __formatter);
+                            #narui::KeyPart::FragmentInternedKey { widget_id: __widget_id, location_id: __location_id, key: __key }
+                        }
+                    }
+                }}
             } else {
                 processed_attributes.insert(name.to_string(), quote! {#name=#value});
             }
@@ -89,9 +101,10 @@ fn handle_rsx_node(x: Node) -> (TokenStream, TokenStream) {
         let processed_attributes = processed_attributes.values();
         let beginning = quote! {
             let (#key_ident, #idx_ident) = {
+                let key_part = #key;
                 let fragment_store = &mut context.fragment_store;
-                let key = context.key_map.key_with(context.widget_local.key, #key, || fragment_store.add_empty_fragment().into());
-                let idx = #narui::Fragment::from(key);
+                let key = context.key_map.key_with(context.widget_local.key, key_part, || fragment_store.add_empty_fragment().into());
+                let idx = fragment_store.fragment(key);
                 (key, idx)
             };
             let #args_listenable_ident = {
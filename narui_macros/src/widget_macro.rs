@@ -70,16 +70,14 @@ fn generate_constructor_macro(function: &ItemFn, mod_ident: &Ident, in_mod: &mut
     let arg_names = get_arg_names(function);
 
     let shout_args = generate_shout_args_macro_part(function, mod_ident, in_mod);
-    let arg_numbers: Vec<_> = (0..(arg_names.len())).map(Literal::usize_unsuffixed).collect();
     let function_ident = &function.sig.ident;
 
     let function_call = quote! {{
         #[allow(unused_unsafe)]
         unsafe {
-            #mod_ident::#function_ident(
-                #($listenables.1.#arg_numbers.parse(&*args[#arg_numbers]).clone(),)*
-                $context
-            )
+            #[allow(unused_variables)]
+            let args = $listenables.1.parse(args);
+            #mod_ident::#function_ident(#(args.#arg_names.clone(),)* $context)
         }
     }};
 
@@ -196,13 +194,27 @@ fn generate_shout_args_macro_part(
             quote! { #x: #ty }
         })
         .collect();
+    // the args struct is generic, as the type of an arg can be `impl Trait`
+    let type_params: Vec<_> = (0..arg_names.len())
+        .map(|i| Ident::new(&format!("__Arg{}", i), Span::call_site()))
+        .collect();
     let constrain_types_ident_pub = Ident::new("constrain_types", function.sig.span());
     in_mod.push(quote! {
         use super::*;
-        #[allow(clippy::unused_unit)]
-        pub fn #constrain_types_ident_pub(#(#inputs,)*) -> ((#(#types,)*), (#(#narui::ArgRef<#types>,)*)) {
-            let arg_refs = (#(#narui::ArgRef::for_value(&#constrain_fn_input_idents),)*);
-            ((#(#constrain_fn_input_idents,)*), arg_refs)
+
+        pub struct __Args<#(#type_params,)*> {
+            #(pub #arg_names: #type_params,)*
+        }
+        impl<#(#type_params: 'static,)*> #narui::WidgetArgs for __Args<#(#type_params,)*> {
+            fn values(&self) -> #narui::ArgValues {
+                #narui::smallvec![#(&self.#arg_names as _,)*]
+            }
+        }
+
+        pub fn #constrain_types_ident_pub(#(#inputs,)*) -> (__Args<#(#types,)*>, #narui::ArgRef<__Args<#(#types,)*>>) {
+            let args = __Args { #(#arg_names: #constrain_fn_input_idents,)* };
+            let arg_ref = #narui::ArgRef::for_value(&args);
+            (args, arg_ref)
         }
 
         pub use #narui::shout_args as shout_args;
@@ -214,12 +226,11 @@ fn generate_shout_args_macro_part(
 
     quote! {
         (@shout_args span=$span:ident, context=$context:expr, idx=$idx:ident, $($args:tt)*) => {{
-            #[allow(unused_variables)]
-            let (args_ordered, arg_refs) = #mod_ident::kw_arg_call!($span #widget_name
+            let (args, arg_ref) = #mod_ident::kw_arg_call!($span #widget_name
                 #mod_ident::#constrain_types_ident_pub{#(#initializers,)*}($($args)*)
             );
-            #mod_ident::shout_args!($context, $idx, #(args_ordered.#arg_numbers,)*);
-            ($idx, arg_refs)
+            #mod_ident::shout_args!($context, $idx, args, #(#arg_names: #arg_numbers,)*);
+            ($idx, arg_ref)
         }};
     }
 }
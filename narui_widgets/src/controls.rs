@@ -55,8 +55,15 @@ pub fn slider(
 ) -> Fragment {
     let widget_key = context.widget_local.idx;
     let clicked = context.listenable(false);
-    let on_click =
-        move |context: &CallbackContext, is_clicked, _, _| context.shout(clicked, is_clicked);
+    let on_click = move |context: &CallbackContext, is_clicked, _, _| {
+        // a whole drag is undone at once
+        match (context.spy(clicked), is_clicked) {
+            (false, true) => context.begin_transaction(),
+            (true, false) => context.end_transaction(),
+            _ => {}
+        }
+        context.shout(clicked, is_clicked)
+    };
 
     let on_move = move |context: &CallbackContext, position: Vec2, _| {
         let clicked = context.spy(clicked);
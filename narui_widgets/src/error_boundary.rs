@@ -0,0 +1,35 @@
+use crate::*;
+use narui_core::{layout::Transparent, re_export::smallvec::smallvec, *};
+use narui_macros::{rsx, widget};
+
+/// catches panics while evaluating its children and shows the panic message
+/// together with a button to retry instead of them
+#[widget]
+pub fn error_boundary(
+    children: Fragment,
+    #[default(18.0)] size: f32,
+    context: &mut WidgetContext,
+) -> FragmentInner {
+    let error = context.catch_panics();
+    let child = match context.listen(error) {
+        None => children,
+        Some(message) => {
+            let retry = move |context: &CallbackContext| context.shout(error, None);
+            rsx! {
+                <column main_axis_size=MainAxisSize::Min>
+                    <text size=size color=theme::FG_LIGHT>{message}</text>
+                    <button on_click=retry>
+                        <text size=size>{"retry"}</text>
+                    </button>
+                </column>
+            }
+        }
+    };
+
+    FragmentInner::Node {
+        children: smallvec![child],
+        layout: Box::new(Transparent),
+        is_clipper: false,
+        subpass: None,
+    }
+}
@@ -0,0 +1,34 @@
+use crate::*;
+use narui_core::*;
+use narui_macros::{rsx, widget};
+use std::time::Duration;
+
+/// shows the statistics of the last frame. While it is shown, the app renders
+/// continuously.
+#[widget]
+pub fn frame_stats_overlay(#[default(12.0)] size: f32, context: &mut WidgetContext) -> Fragment {
+    let stats = context.listen(context.frame_stats());
+    let ms = |duration: Duration| duration.as_secs_f32() * 1000.0;
+    let lines = [
+        format!("{:.0} fps ({:.2} ms)", stats.fps(), ms(stats.frame_time)),
+        format!("evaluation {:.2} ms ({} widgets)", ms(stats.evaluation), stats.reevaluated),
+        format!("layout {:.2} ms", ms(stats.layout)),
+        format!("text prerender {:.2} ms", ms(stats.text_prerender)),
+        format!("tessellation {:.2} ms", ms(stats.tessellation)),
+        format!("recording {:.2} ms", ms(stats.recording)),
+        format!("submission {:.2} ms", ms(stats.submission)),
+        format!("{} vertices, {} indices", stats.vertices, stats.indices),
+    ];
+
+    rsx! {
+        <rect fill=Some(theme::BG) border_radius=Paxel(4.0)>
+            <padding padding=EdgeInsets::all(size / 2.0)>
+                <column cross_axis_alignment=CrossAxisAlignment::Start main_axis_size=MainAxisSize::Min>
+                    {lines.iter().enumerate().map(|(i, line)| rsx! {
+                        <text key=i size=size>{line.clone()}</text>
+                    }).collect()}
+                </column>
+            </padding>
+        </rect>
+    }
+}
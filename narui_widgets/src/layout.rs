@@ -1,5 +1,6 @@
 use narui_core::{layout::*, re_export::smallvec::smallvec, *};
 use narui_macros::widget;
+use std::time::Duration;
 
 #[widget]
 pub fn column(
@@ -136,3 +137,21 @@ pub fn aspect_ratio(
         subpass: None,
     }
 }
+
+/// animates the rendered position and size of its child when the layout of the
+/// child changes. Only the rendering (and hit testing) follows the animation,
+/// the surrounding layout uses the new rect right away.
+#[widget]
+pub fn animated_layout(
+    children: Fragment,
+    #[default(Duration::from_millis(200))] duration: Duration,
+    #[default] curve: Curve,
+    context: &mut WidgetContext,
+) -> FragmentInner {
+    FragmentInner::Node {
+        children: smallvec![children],
+        layout: Box::new(LayoutAnimation { duration, curve }),
+        is_clipper: false,
+        subpass: None,
+    }
+}
@@ -25,4 +25,12 @@ pub use input_widget::*;
 mod subpass_widget;
 pub use subpass_widget::*;
 
+#[path = "error_boundary.rs"]
+mod error_boundary_widget;
+pub use error_boundary_widget::*;
+
+#[path = "frame_stats_overlay.rs"]
+mod frame_stats_overlay_widget;
+pub use frame_stats_overlay_widget::*;
+
 pub mod theme;
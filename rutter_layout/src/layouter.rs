@@ -46,6 +46,7 @@ pub struct LayoutItem<'a, T> {
     pub pos: Offset,
     pub idx: Idx,
     pub z_index_offset: u32,
+    pub has_children: bool,
     pub obj: &'a T,
 }
 
@@ -58,6 +59,7 @@ impl<'a, T> LayoutItem<'a, T> {
             pos: node.abs_pos.get().unwrap(),
             obj: &node.obj,
             z_index_offset: node.z_index_offset.get().unwrap(),
+            has_children: node.child.is_some(),
             idx,
         }
     }
@@ -158,9 +160,14 @@ impl<T: Deref<Target = dyn Layout> + std::fmt::Debug> Layouter<T> {
     pub fn set_children(&mut self, parent_idx: Idx, mut children: impl Iterator<Item = Idx>) {
         let mut len = 0;
         let mut any_child_dirty = false;
+        // moving children around changes their positions even if none of them is dirty.
+        // as long as the order did not change, the next sibling of the last child still
+        // is the old next child
+        let mut reordered = false;
 
         if let Some(new_child_idx) = children.next() {
             len = 1;
+            reordered = self.nodes[parent_idx].child != Some(new_child_idx);
             self.nodes[parent_idx].child = Some(new_child_idx);
             let mut last_child_idx = new_child_idx;
 
@@ -169,6 +176,7 @@ impl<T: Deref<Target = dyn Layout> + std::fmt::Debug> Layouter<T> {
 
                 let last = &mut self.nodes[last_child_idx];
                 any_child_dirty = any_child_dirty || last.any_dirty_children.get();
+                reordered = reordered || last.next_sibling != Some(new_child_idx);
 
                 last.next_sibling = Some(new_child_idx);
 
@@ -187,7 +195,7 @@ impl<T: Deref<Target = dyn Layout> + std::fmt::Debug> Layouter<T> {
             self.nodes[parent_idx].child = None;
         }
 
-        let dirty = (self.nodes[parent_idx].num_children != len) || any_child_dirty;
+        let dirty = (self.nodes[parent_idx].num_children != len) || any_child_dirty || reordered;
         self.nodes[parent_idx].num_children = len;
 
         if dirty {
@@ -197,6 +205,15 @@ impl<T: Deref<Target = dyn Layout> + std::fmt::Debug> Layouter<T> {
 
     pub fn remove(&mut self, idx: Idx) { self.nodes.remove(idx); }
 
+    /// the number of nodes that were added and not removed
+    pub fn node_count(&self) -> usize { self.nodes.len() }
+
+    /// the number of nodes there is memory for
+    pub fn node_capacity(&self) -> usize { self.nodes.capacity() }
+
+    /// gives back the memory of removed nodes
+    pub fn shrink(&mut self) { self.nodes.shrink() }
+
     pub fn do_layout(&mut self, constraints: BoxConstraints, root_pos: Offset, idx: Idx) {
         let nodes_wrapper =
             unsafe { &self.nodes.iter_raw().map(|v| v as _).collect::<Vec<_>>()[..] };
@@ -247,6 +264,14 @@ impl<T: Deref<Target = dyn Layout> + std::fmt::Debug> Layouter<T> {
         let node = &self.nodes[idx];
         (node.abs_pos.get().unwrap(), node.size.get().unwrap(), &node.obj)
     }
+
+    /// like get_layout, but None if the node was not laid out yet
+    pub fn try_get_layout(&self, idx: Idx) -> Option<(Offset, Size, &T)> {
+        let node = &self.nodes[idx];
+        Some((node.abs_pos.get()?, node.size.get()?, &node.obj))
+    }
+
+    pub fn parent(&self, idx: Idx) -> Option<Idx> { self.nodes[idx].parent }
 }
 
 #[derive(Debug)]
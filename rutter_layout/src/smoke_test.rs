@@ -87,3 +87,33 @@ fn smoke_test() {
         (Offset { x: 32.5, y: 75.0 }, Size { width: 35.0, height: 25.0 })
     );
 }
+
+#[test]
+fn reorder_children() {
+    let mut layouter = Layouter::<Box<dyn Layout>>::new();
+    let column = layouter.add_node(Box::new(Column {
+        cross_axis_alignment: CrossAxisAlignment::Start,
+        main_axis_alignment: MainAxisAlignment::Start,
+        main_axis_size: MainAxisSize::Max,
+    }));
+    let boxes: Vec<_> = [10.0, 20.0, 30.0]
+        .iter()
+        .map(|height| {
+            layouter.add_node(Box::new(SizedBox::new(Size { width: 10.0, height: *height })))
+        })
+        .collect();
+    let layout = |layouter: &mut Layouter<Box<dyn Layout>>, order: [usize; 3]| {
+        layouter.set_children(column, order.iter().map(|i| boxes[*i]));
+        layouter.do_layout(
+            BoxConstraints::tight_for(Size { width: 100.0, height: 100.0 }),
+            Offset::zero(),
+            column,
+        );
+        boxes.iter().map(|b| layouter.get_layout(*b).0.y).collect::<Vec<_>>()
+    };
+
+    assert_eq!(layout(&mut layouter, [0, 1, 2]), vec![0.0, 10.0, 30.0]);
+    // none of the children is dirty, but their positions changed
+    assert_eq!(layout(&mut layouter, [2, 0, 1]), vec![30.0, 40.0, 0.0]);
+    assert_eq!(layout(&mut layouter, [2, 1, 0]), vec![50.0, 30.0, 0.0]);
+}